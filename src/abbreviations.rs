@@ -0,0 +1,176 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Abbreviations.
+//!
+//! Support the `*[HTML]: HyperText Markup Language` abbreviation syntax of
+//! PHP Markdown Extra, which pulldown-cmark does not parse.
+
+use pulldown_cmark::{Event, Tag};
+
+/// An abbreviation and its expansion.
+pub type Abbreviation = (String, String);
+
+/// Parse an abbreviation definition from `line`.
+fn parse_definition(line: &str) -> Option<Abbreviation> {
+    let line = line.trim();
+    if !line.starts_with("*[") {
+        return None;
+    }
+    let end = line.find("]:")?;
+    let abbreviation = line[2..end].trim();
+    let expansion = line[end + 2..].trim();
+    if abbreviation.is_empty() {
+        None
+    } else {
+        Some((abbreviation.to_string(), expansion.to_string()))
+    }
+}
+
+/// Parse the text of a paragraph which only defines abbreviations.
+///
+/// Return `None` if the paragraph contains anything but text, or any line
+/// which does not define an abbreviation.
+fn parse_definitions<'a, 'e: 'a, I>(paragraph: I) -> Option<Vec<Abbreviation>>
+where
+    I: IntoIterator<Item = &'a Event<'e>>,
+{
+    let mut text = String::new();
+    for event in paragraph {
+        match event {
+            Event::Text(part) => text.push_str(part),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            _ => return None,
+        }
+    }
+    text.lines().map(parse_definition).collect()
+}
+
+/// Extract abbreviation definitions from `events`.
+///
+/// Remove all paragraphs which only define abbreviations, and return the
+/// remaining events along with all abbreviations.  Every event comes with a
+/// `T`, e.g. its offset in the source.
+pub fn extract_abbreviations<T: Clone>(
+    events: Vec<(Event<'_>, T)>,
+) -> (Vec<(Event<'_>, T)>, Vec<Abbreviation>) {
+    let mut remaining = Vec::with_capacity(events.len());
+    let mut abbreviations = Vec::new();
+    let mut events = events.into_iter();
+    while let Some((event, t)) = events.next() {
+        if let Event::Start(Tag::Paragraph) = event {
+            let mut paragraph = Vec::new();
+            let mut end = t.clone();
+            for (event, t) in events.by_ref() {
+                if let Event::End(Tag::Paragraph) = event {
+                    end = t;
+                    break;
+                }
+                paragraph.push((event, t));
+            }
+            match parse_definitions(paragraph.iter().map(|(event, _)| event)) {
+                Some(definitions) => abbreviations.extend(definitions),
+                None => {
+                    remaining.push((Event::Start(Tag::Paragraph), t));
+                    remaining.extend(paragraph);
+                    remaining.push((Event::End(Tag::Paragraph), end));
+                }
+            }
+        } else {
+            remaining.push((event, t));
+        }
+    }
+    (remaining, abbreviations)
+}
+
+/// Whether `text` has a word boundary at `index`.
+fn is_boundary(text: &str, index: usize) -> bool {
+    let is_word = |c: Option<char>| matches!(c, Some(c) if c.is_alphanumeric());
+    !is_word(text[..index].chars().next_back()) || !is_word(text[index..].chars().next())
+}
+
+/// Split `text` at the given `abbreviations`.
+///
+/// Return parts of `text`, and whether each part is an abbreviation.  Only
+/// consider abbreviations which stand as whole words.
+pub fn split_abbreviations<'t>(
+    text: &'t str,
+    abbreviations: &[Abbreviation],
+) -> Vec<(bool, &'t str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let found = abbreviations.iter().find(|(abbreviation, _)| {
+            let end = index + abbreviation.len();
+            text[index..].starts_with(abbreviation.as_str())
+                && is_boundary(text, index)
+                && is_boundary(text, end)
+        });
+        match found {
+            Some((abbreviation, _)) => {
+                if start < index {
+                    parts.push((false, &text[start..index]));
+                }
+                start = index + abbreviation.len();
+                parts.push((true, &text[index..start]));
+                index = start;
+            }
+            None => {
+                index += text[index..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+    }
+    if start < text.len() {
+        parts.push((false, &text[start..]));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_definition() {
+        assert_eq!(
+            super::parse_definition("*[HTML]: HyperText Markup Language"),
+            Some(("HTML".to_string(), "HyperText Markup Language".to_string()))
+        );
+        assert_eq!(super::parse_definition("*[]: Nothing"), None);
+        assert_eq!(super::parse_definition("[HTML]: /html"), None);
+    }
+
+    #[test]
+    fn split_abbreviations() {
+        let abbreviations = vec![
+            ("HTML".to_string(), String::new()),
+            ("W3C".to_string(), String::new()),
+        ];
+        assert_eq!(
+            super::split_abbreviations("The W3C made HTML, not XHTML.", &abbreviations),
+            vec![
+                (false, "The "),
+                (true, "W3C"),
+                (false, " made "),
+                (true, "HTML"),
+                (false, ", not XHTML.")
+            ]
+        );
+        assert_eq!(
+            super::split_abbreviations("HTML", &abbreviations),
+            vec![(true, "HTML")]
+        );
+    }
+}
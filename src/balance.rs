@@ -0,0 +1,134 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Balance arbitrary sequences of events.
+
+use pulldown_cmark::{Event, Tag};
+use std::collections::VecDeque;
+use std::mem::discriminant;
+
+/// Balance start and end tags in `events`.
+///
+/// Drop end tags without a matching start tag, close all tags left open by an
+/// end tag of an outer tag, and close all tags still open at the end.  Take
+/// the end of each tag from its start, so that both carry the same data, e.g.
+/// the same heading level.
+pub fn balance<'a, I>(events: I) -> Balanced<'a, I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    Balanced {
+        events,
+        open: Vec::new(),
+        pending: VecDeque::new(),
+    }
+}
+
+/// An iterator over balanced events.
+///
+/// See [`balance`].
+pub struct Balanced<'a, I> {
+    events: I,
+    /// Tags started but not yet ended.
+    open: Vec<Tag<'a>>,
+    /// Events to emit before the next event.
+    pending: VecDeque<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for Balanced<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        while self.pending.is_empty() {
+            match self.events.next() {
+                Some(Event::Start(tag)) => {
+                    self.open.push(tag.clone());
+                    self.pending.push_back(Event::Start(tag));
+                }
+                Some(Event::End(tag)) => {
+                    let kind = discriminant(&tag);
+                    let position = self
+                        .open
+                        .iter()
+                        .rposition(|open| discriminant(open) == kind);
+                    if let Some(position) = position {
+                        while position < self.open.len() {
+                            let open = self.open.pop().unwrap();
+                            self.pending.push_back(Event::End(open));
+                        }
+                    }
+                }
+                Some(event) => self.pending.push_back(event),
+                None => {
+                    let open = self.open.pop()?;
+                    self.pending.push_back(Event::End(open));
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::balance;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{Event, Tag};
+
+    #[test]
+    fn keep_balanced_events() {
+        let events = vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text("foo".into()),
+            Event::End(Tag::Paragraph),
+        ];
+        assert_eq!(
+            balance(events.clone().into_iter()).collect::<Vec<_>>(),
+            events
+        );
+    }
+
+    #[test]
+    fn drop_unmatched_end_tags() {
+        let events = vec![Event::End(Tag::Paragraph), Event::Text("foo".into())];
+        assert_eq!(
+            balance(events.into_iter()).collect::<Vec<_>>(),
+            vec![Event::Text("foo".into())]
+        );
+    }
+
+    #[test]
+    fn close_open_tags() {
+        let events = vec![
+            Event::Start(Tag::BlockQuote),
+            Event::Start(Tag::Heading(2)),
+            Event::Start(Tag::Emphasis),
+            Event::End(Tag::Heading(5)),
+            Event::Start(Tag::Paragraph),
+        ];
+        assert_eq!(
+            balance(events.into_iter()).collect::<Vec<_>>(),
+            vec![
+                Event::Start(Tag::BlockQuote),
+                Event::Start(Tag::Heading(2)),
+                Event::Start(Tag::Emphasis),
+                Event::End(Tag::Emphasis),
+                Event::End(Tag::Heading(2)),
+                Event::Start(Tag::Paragraph),
+                Event::End(Tag::Paragraph),
+                Event::End(Tag::BlockQuote),
+            ]
+        );
+    }
+}
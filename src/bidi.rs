@@ -0,0 +1,79 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bidirectional text.
+
+use unicode_width::UnicodeWidthStr;
+
+/// First strong isolate, to isolate text with its own direction.
+pub const FIRST_STRONG_ISOLATE: char = '\u{2068}';
+
+/// Pop directional isolate, to end an isolate.
+pub const POP_DIRECTIONAL_ISOLATE: char = '\u{2069}';
+
+/// Whether `c` controls the direction of text.
+///
+/// These characters have no width in terminals.
+fn is_directional_formatting(c: char) -> bool {
+    matches!(c, '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Whether `c` is a letter of a right-to-left script.
+///
+/// Cover the blocks of Hebrew, Arabic, Syriac, Thaana, NKo and related
+/// scripts, with their presentation forms.
+fn is_right_to_left(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{05ff}'
+            | '\u{0600}'..='\u{06ff}'
+            | '\u{0700}'..='\u{08ff}'
+            | '\u{fb1d}'..='\u{fdff}'
+            | '\u{fe70}'..='\u{feff}'
+            | '\u{10800}'..='\u{10fff}'
+            | '\u{1e800}'..='\u{1efff}'
+    ) && c.is_alphabetic()
+}
+
+/// Whether `text` contains right-to-left letters.
+pub fn has_right_to_left(text: &str) -> bool {
+    text.chars().any(is_right_to_left)
+}
+
+/// The number of columns `text` occupies.
+///
+/// Count wide characters, e.g. CJK ideographs, as two columns, and combining
+/// marks and directional formatting characters as none.
+pub fn text_width(text: &str) -> usize {
+    text.split(is_directional_formatting)
+        .map(UnicodeWidthStr::width)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn width_without_formatting() {
+        assert_eq!(text_width("abc"), 3);
+        assert_eq!(
+            text_width("\u{2068}\u{5e9}\u{5dc}\u{5d5}\u{5dd}\u{2069}"),
+            4
+        );
+        assert_eq!(text_width("a\u{200f}b"), 2);
+        assert_eq!(text_width("\u{6f22}\u{5b57}"), 4);
+        assert_eq!(text_width("e\u{301}"), 1);
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The configuration file of mdcat.
+//!
+//! Keys mirror the long command line options, and options given on the command
+//! line take precedence over the configuration file.  Flags like
+//! `--no-dedent-code` turn off flags which the configuration file turns on.
+
+use anstyle::{Ansi256Color, AnsiColor, Color, RgbColor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// The style of paragraphs in the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ParagraphConfig {
+    /// How to align text: `left` or `justify`.
+    pub alignment: Option<String>,
+    /// Indent the first line of paragraphs by this number of columns.
+    pub first_line_indent: Option<usize>,
+    /// Indent all but the first line of paragraphs by this number of columns.
+    pub hanging_indent: Option<usize>,
+}
+
+/// The style of a heading level in the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HeadingConfig {
+    /// The colour of the heading, see [`parse_colour`].
+    pub colour: Option<String>,
+    /// Whether to make the heading bold; defaults to true.
+    pub bold: Option<bool>,
+    /// Whether to make the heading italic.
+    pub italic: bool,
+    /// Whether to underline the heading.
+    pub underline: bool,
+    /// Whether to write the heading in uppercase.
+    pub uppercase: bool,
+    /// Whether to adorn the heading with `┄`; defaults to true.
+    pub adornment: Option<bool>,
+    /// The number of columns to indent the heading by.
+    pub indent: usize,
+}
+
+/// The style of rules in the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RuleConfig {
+    /// The symbol to repeat along the rule.
+    pub symbol: Option<String>,
+    /// The colour of the rule, see [`parse_colour`].
+    pub colour: Option<String>,
+    /// The number of times to repeat the symbol; fill the width if unset.
+    pub width: Option<usize>,
+    /// Whether to center the rule.
+    pub centered: bool,
+}
+
+/// The style of tables in the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TableConfig {
+    /// The background colour of every other body row, see [`parse_colour`].
+    pub stripe: Option<String>,
+}
+
+/// Symbols in the configuration file, each a single character.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SymbolsConfig {
+    /// The adornment of headings.
+    pub heading: Option<String>,
+    /// The bullet of unordered list items.
+    pub bullet: Option<String>,
+    /// The marker of checked task list items.
+    pub checked: Option<String>,
+    /// The marker of unchecked task list items.
+    pub unchecked: Option<String>,
+    /// The ellipsis to mark truncated text.
+    pub ellipsis: Option<String>,
+    /// The marker of continued lines in wrapped code blocks.
+    pub continuation: Option<String>,
+    /// The horizontal line of borders.
+    pub horizontal: Option<String>,
+    /// The vertical line of table borders.
+    pub vertical: Option<String>,
+    /// Nine corners and junctions of table borders, e.g. `┌┬┐├┼┤└┴┘`.
+    pub junctions: Option<String>,
+}
+
+/// Authentication and headers for HTTP requests to a host in the
+/// configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpHostConfig {
+    /// The name of the host.
+    pub host: String,
+    /// A bearer token for requests to the host.
+    pub token: Option<String>,
+    /// The user name for basic authentication.
+    pub user: Option<String>,
+    /// The password for basic authentication.
+    pub password: Option<String>,
+    /// Additional headers for requests to the host.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// The configuration file of mdcat.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// See `--encoding`.
+    pub encoding: Option<String>,
+    /// See `--margin`.
+    pub margin: Option<usize>,
+    /// See `--content-width`.
+    pub content_width: Option<usize>,
+    /// See `--long-links`.
+    pub long_links: Option<String>,
+    /// See `--reference-numbering`.
+    pub reference_numbering: Option<String>,
+    /// See `--flush-references`.
+    pub flush_references: Option<String>,
+    /// See `--link-titles`.
+    pub link_titles: Option<String>,
+    /// See `--tab-width`.
+    pub tab_width: Option<usize>,
+    /// See `--dedent-code`.
+    pub dedent_code: bool,
+    /// See `--long-code`.
+    pub long_code: Option<String>,
+    /// See `--code-background`.
+    pub code_background: bool,
+    /// See `--figure-numbers`.
+    pub figure_numbers: bool,
+    /// See `--animations`.
+    pub animations: bool,
+    /// See `--max-image-bytes`.
+    pub max_image_bytes: Option<usize>,
+    /// See `--max-image-read-bytes`.
+    pub max_image_read_bytes: Option<usize>,
+    /// See `--double-height-headings`.
+    pub double_height_headings: bool,
+    /// See `--footnote-marks`.
+    pub footnote_marks: Option<String>,
+    /// See `--super-subscripts`.
+    pub super_subscripts: bool,
+    /// See `--abbreviations`.
+    pub abbreviations: bool,
+    /// See `--sniff-code`.
+    pub sniff_code: bool,
+    /// See `--default-code-language`.
+    pub default_code_language: Option<String>,
+    /// See `--number-snippets`.
+    pub number_snippets: bool,
+    /// See `--theme`.
+    pub theme: Option<String>,
+    /// See `--style-sheet`.
+    pub style_sheet: Option<String>,
+    /// See `--page-break`.
+    pub page_break: Option<String>,
+    /// See `--separator`.
+    pub separator: Option<String>,
+    /// See `--show-html-comments`.
+    pub show_html_comments: bool,
+    /// See `--bidi-isolates`.
+    pub bidi_isolates: bool,
+    /// See `--copy-mode`.
+    pub copy_mode: bool,
+    /// See `--raw-blocks`.
+    pub raw_blocks: bool,
+    /// See `--banner-headings`.
+    pub banner_headings: bool,
+    /// See `--skip-frontmatter`.
+    pub skip_frontmatter: bool,
+    /// See `--local`.
+    pub local: bool,
+    /// See `--restrict-local-images`.
+    pub restrict_local_images: bool,
+    /// See `--proxy`.
+    pub proxy: Option<String>,
+    /// See `--ca-bundle`.
+    pub ca_bundle: Option<PathBuf>,
+    /// See `--insecure`.
+    pub insecure: bool,
+    /// The style of paragraphs.
+    pub paragraph: ParagraphConfig,
+    /// The styles of headings, by level from `1` to `6`.
+    pub headings: BTreeMap<String, HeadingConfig>,
+    /// The style of rules.
+    pub rule: RuleConfig,
+    /// The style of tables.
+    pub table: TableConfig,
+    /// The symbols to decorate markdown with.
+    pub symbols: SymbolsConfig,
+    /// Authentication and headers for HTTP requests, per host.
+    pub http: Vec<HttpHostConfig>,
+}
+
+/// Parse a colour.
+///
+/// Accept the names of the eight basic ANSI colours, an index into the xterm
+/// palette from 0 to 255, or a 24-bit colour in `#rrggbb` notation.
+pub fn parse_colour(value: &str) -> Option<Color> {
+    let colour = match value.trim().to_lowercase().as_str() {
+        "black" => AnsiColor::Black.into(),
+        "red" => AnsiColor::Red.into(),
+        "green" => AnsiColor::Green.into(),
+        "yellow" => AnsiColor::Yellow.into(),
+        "blue" => AnsiColor::Blue.into(),
+        "magenta" | "purple" => AnsiColor::Magenta.into(),
+        "cyan" => AnsiColor::Cyan.into(),
+        "white" => AnsiColor::White.into(),
+        hex if hex.starts_with('#') && hex.len() == 7 => {
+            let component = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+            RgbColor(component(1..3)?, component(3..5)?, component(5..7)?).into()
+        }
+        index => Ansi256Color(index.parse::<u8>().ok()?).into(),
+    };
+    Some(colour)
+}
+
+impl Config {
+    /// The path of the default configuration file.
+    ///
+    /// `mdcat/mdcat.toml` in `$XDG_CONFIG_HOME`, or in `~/.config` if unset.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|directory| directory.join("mdcat").join("mdcat.toml"))
+    }
+
+    /// Parse a configuration from TOML `text`.
+    pub fn from_toml(text: &str) -> Result<Config, Box<dyn Error>> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Load the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        Config::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load the configuration file at the default path if it exists.
+    ///
+    /// Return the default configuration if there's no configuration file.
+    pub fn load_default() -> Result<Config, Box<dyn Error>> {
+        match Config::default_path() {
+            Some(path) if path.is_file() => Config::load(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_toml(
+            "content-width = 72\nlong-links = \"ellipsize\"\ndedent-code = true\n\n\
+             [paragraph]\nalignment = \"justify\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.content_width, Some(72));
+        assert_eq!(config.long_links.as_deref(), Some("ellipsize"));
+        assert!(config.dedent_code);
+        assert!(!config.code_background);
+        assert_eq!(config.paragraph.alignment.as_deref(), Some("justify"));
+    }
+
+    #[test]
+    fn parse_encoding() {
+        let config = Config::from_toml("encoding = \"latin1\"\n").unwrap();
+        assert_eq!(config.encoding.as_deref(), Some("latin1"));
+        assert_eq!(Config::default().encoding, None);
+    }
+
+    #[test]
+    fn parse_image_limits() {
+        let config =
+            Config::from_toml("max-image-bytes = 1000\nmax-image-read-bytes = 2000\n").unwrap();
+        assert_eq!(config.max_image_bytes, Some(1000));
+        assert_eq!(config.max_image_read_bytes, Some(2000));
+    }
+
+    #[test]
+    fn parse_heading_styles() {
+        let config =
+            Config::from_toml("[headings.1]\ncolour = \"red\"\nuppercase = true\n").unwrap();
+        let heading = &config.headings["1"];
+        assert_eq!(heading.colour.as_deref(), Some("red"));
+        assert_eq!(heading.bold, None);
+        assert!(heading.uppercase);
+    }
+
+    #[test]
+    fn parse_symbols() {
+        let config =
+            Config::from_toml("[symbols]\nbullet = \"*\"\njunctions = \"+++++++++\"\n").unwrap();
+        assert_eq!(config.symbols.bullet.as_deref(), Some("*"));
+        assert_eq!(config.symbols.junctions.as_deref(), Some("+++++++++"));
+        assert_eq!(config.symbols.heading, None);
+    }
+
+    #[test]
+    fn parse_colours() {
+        assert_eq!(parse_colour("Purple"), Some(AnsiColor::Magenta.into()));
+        assert_eq!(parse_colour("208"), Some(Ansi256Color(208).into()));
+        assert_eq!(
+            parse_colour("#268bd2"),
+            Some(RgbColor(0x26, 0x8b, 0xd2).into())
+        );
+        assert_eq!(parse_colour("#26"), None);
+        assert_eq!(parse_colour("256"), None);
+    }
+
+    #[test]
+    fn parse_http_hosts() {
+        let config = Config::from_toml(
+            "[[http]]\nhost = \"example.com\"\ntoken = \"secret\"\n\n\
+             [http.headers]\nX-Foo = \"bar\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.http.len(), 1);
+        assert_eq!(config.http[0].host, "example.com");
+        assert_eq!(config.http[0].token.as_deref(), Some("secret"));
+        assert_eq!(config.http[0].headers["X-Foo"], "bar");
+    }
+
+    #[test]
+    fn reject_unknown_keys() {
+        assert!(Config::from_toml("colour = true\n").is_err());
+    }
+}
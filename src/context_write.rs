@@ -11,21 +11,40 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::resources::timeout_until;
 
-use crate::Settings;
-use ansi_term::{Colour, Style};
+use crate::slug::{permalink, Heading, Slugger};
+use crate::{
+    highlighting_theme, CodeOverflow, Colours, DocumentSeparator, FootnoteMarker, HttpSettings,
+    LinkKind, LinkPlacement, LinkTitles, PageBreak, ParagraphIndent, ReferenceFlushPolicy,
+    ReferenceNumbering, ReferenceOverflow, RenderedLink, Settings, TextAlignment, Warning,
+};
+use anstyle::{Color, RgbColor};
 use pulldown_cmark::Event::*;
 use pulldown_cmark::Tag::*;
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType, Tag};
+use pulldown_cmark::{Alignment, CodeBlockKind, CowStr, Event, LinkType, Tag};
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::error::Error;
 use std::io;
 use std::io::Write;
 use std::path::Path;
+use std::time::{Instant, SystemTime};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::Theme;
+use syntect::highlighting::{Style as SyntectStyle, Theme};
+use syntect::util::LinesWithEndings;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+use crate::abbreviations::{split_abbreviations, Abbreviation};
+use crate::banner;
+use crate::bidi;
+use crate::convert;
+use crate::html;
+use crate::scripts;
+use crate::syntax;
 use crate::terminal::*;
+use crate::Style;
 
 /// The "level" the current event occurs at.
 #[derive(Debug, PartialEq)]
@@ -56,6 +75,31 @@ struct Link<'a> {
     title: CowStr<'a>,
 }
 
+/// A segment of a line buffered for justification.
+#[derive(Debug, Clone)]
+enum LineSegment {
+    /// Text with a style.
+    Text(Style, String),
+    /// A space between words, which justification may stretch.
+    Space(Style),
+    /// Escape sequences which do not move the cursor.
+    Escapes(Vec<u8>),
+}
+
+impl LineSegment {
+    /// The number of columns this segment occupies.
+    fn width(&self) -> usize {
+        match self {
+            LineSegment::Text(_, text) => bidi::text_width(text),
+            LineSegment::Space(_) => 1,
+            LineSegment::Escapes(_) => 0,
+        }
+    }
+}
+
+/// The segments of a table cell, with the styles and links of its markup.
+type Cell = Vec<LineSegment>;
+
 #[derive(Debug)]
 struct StyleContext {
     /// The current style
@@ -70,6 +114,8 @@ struct StyleContext {
     /// We use this information to switch between italic and upright text for
     /// emphasis.
     emphasis_level: usize,
+    /// Whether to write text in uppercase.
+    uppercase: bool,
 }
 
 #[derive(Debug)]
@@ -78,6 +124,78 @@ struct BlockContext {
     indent_level: usize,
     /// Whether we are at block-level or inline in a block.
     level: BlockLevel,
+    /// The column of the cursor, including margin and indentation.
+    column: usize,
+    /// Whether we are inside a code block currently.
+    inside_code_block: bool,
+    /// Whether to write a space before the next text.
+    ///
+    /// When wrapping text we defer spaces between words, to avoid trailing
+    /// spaces at the end of wrapped lines.
+    pending_space: bool,
+    /// The column the text of the current line starts at.
+    line_start: usize,
+    /// Segments of the current line, if we justify text.
+    line: Option<Vec<LineSegment>>,
+    /// Whether to write lines with double height.
+    ///
+    /// Requires buffered `line` segments.
+    double_height: bool,
+    /// The number of block quotes we are currently in.
+    quote_level: usize,
+    /// A pending citation at the end of a quoted paragraph.
+    ///
+    /// We hold back lines starting with a dash in block quotes until we know
+    /// whether they end the paragraph.
+    citation: Option<String>,
+    /// Whether the last event was a soft break which we refilled.
+    after_soft_break: bool,
+    /// The number of block quotes and lists we are currently in.
+    nesting_depth: usize,
+    /// The number of block quotes and lists we skip currently.
+    ///
+    /// We skip blocks nested deeper than settings permit.
+    skipped_depth: usize,
+    /// Whether we are inside an HTML comment which spans multiple lines.
+    inside_html_comment: bool,
+    /// Whether we are writing text inside a directional isolate.
+    inside_isolate: bool,
+    /// The text of the current level-1 heading, if we write it as banner.
+    banner: Option<String>,
+}
+
+/// The horizontal layout of the document.
+#[derive(Debug)]
+struct Layout {
+    /// The number of columns left of the content.
+    margin: usize,
+    /// The number of columns available for content.
+    width: usize,
+    /// Whether to wrap text at the content width.
+    wrap: bool,
+}
+
+impl Layout {
+    /// Compute the layout for the given `settings`.
+    ///
+    /// Indent by the margin of `settings`, and if the terminal leaves more room
+    /// than the content width of `settings`, center the content in the
+    /// remaining space.
+    fn from_settings(settings: &Settings) -> Layout {
+        let available = settings.terminal_size.width.saturating_sub(settings.margin);
+        match settings.content_width {
+            Some(width) if width < available => Layout {
+                margin: settings.margin + (available - width) / 2,
+                width,
+                wrap: true,
+            },
+            content_width => Layout {
+                margin: settings.margin,
+                width: available,
+                wrap: content_width.is_some(),
+            },
+        }
+    }
 }
 
 /// Context to keep track of links.
@@ -91,6 +209,16 @@ struct LinkContext<'a> {
     current_link_type: Option<LinkType>,
     /// Whether we are inside an inline link currently.
     inside_inline_link: bool,
+    /// All links and images written so far.
+    rendered_links: Vec<RenderedLink>,
+    /// The text of the current heading, to link to its permalink.
+    heading_text: Option<String>,
+    /// Unique slugs for the headings of the document.
+    slugger: Slugger,
+    /// All headings of the document, to resolve links to fragments.
+    headings: Vec<Heading>,
+    /// The text of the current link to a heading of the document.
+    fragment_text: Option<String>,
 }
 
 /// Context for images.
@@ -100,6 +228,58 @@ struct ImageContext {
     ///
     /// Suppresses all text output.
     inline_image: bool,
+    /// Whether we link the text of the current image to the image.
+    ///
+    /// We link the text of images we cannot show if the terminal supports
+    /// inline links.
+    linked_text: bool,
+    /// The number of the last figure, i.e. image with a caption.
+    figure_number: usize,
+}
+
+/// Context for code blocks.
+#[derive(Debug)]
+struct CodeContext {
+    /// The text of the current code block, if we buffer code blocks.
+    ///
+    /// We buffer code blocks to remove common indentation.
+    buffer: Option<String>,
+    /// Whether to guess the syntax of the current code block from its first
+    /// line.
+    detect_syntax: bool,
+    /// The number of the last code block.
+    snippet_number: usize,
+    /// Whether the current code block is a raw block to write as is.
+    raw: bool,
+}
+
+/// Context for tables.
+#[derive(Debug)]
+struct TableContext {
+    /// The alignments of the columns of the current table.
+    alignments: Vec<Alignment>,
+    /// The cells of the header row.
+    head: Vec<Cell>,
+    /// The rows of the table body.
+    rows: Vec<Vec<Cell>>,
+    /// The cells of the current row.
+    row: Vec<Cell>,
+    /// The current cell, if we are inside a table cell.
+    ///
+    /// We buffer tables to measure the width of their columns.
+    cell: Option<Cell>,
+    /// Whether we are inside the header row.
+    inside_head: bool,
+}
+
+/// Context for footnotes.
+#[derive(Debug)]
+struct FootnoteContext {
+    /// The labels of all footnotes seen so far.
+    ///
+    /// We number footnotes in the order of their first reference or
+    /// definition, i.e. the number of a footnote is its index plus one.
+    labels: Vec<String>,
 }
 
 /// Context for TTY rendering.
@@ -110,8 +290,6 @@ pub struct Context<'a, 'b, W: Write> {
     base_dir: &'a Path,
     /// The sink to write to,
     writer: &'a mut W,
-    /// A theme for highlighting
-    theme: &'a Theme,
     /// The current highlighter.
     ///
     /// If set assume we are in a code block and highlight all text with this
@@ -124,14 +302,28 @@ pub struct Context<'a, 'b, W: Write> {
     style: StyleContext,
     /// Context for the current block.
     block: BlockContext,
+    /// The horizontal layout.
+    layout: Layout,
     /// Context to keep track of links.
     links: LinkContext<'b>,
     /// Context for images.
     image: ImageContext,
+    /// Context for code blocks.
+    code: CodeContext,
+    /// Context for tables.
+    table: TableContext,
+    /// Context for footnotes.
+    footnotes: FootnoteContext,
     /// The kind of the current list item.
     ///
     /// A stack of kinds to address nested lists.
     list_item_kind: Vec<ListItemKind>,
+    /// Abbreviations to underline in text, and to list at the end.
+    abbreviations: Vec<Abbreviation>,
+    /// Basic ANSI styles, if settings force styles on a terminal without any.
+    forced_style: Option<StyleCapability>,
+    /// When to stop rendering, if at all.
+    deadline: Option<Instant>,
 }
 
 impl<'a, 'b, W: Write> Context<'a, 'b, W> {
@@ -139,34 +331,164 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
         writer: &'a mut W,
         settings: &'a Settings,
         base_dir: &'a Path,
-        theme: &'a Theme,
     ) -> Context<'a, 'b, W> {
         Context {
             settings,
             base_dir,
             writer,
-            theme,
             current_highlighter: None,
             style: StyleContext {
                 current: Style::new(),
                 previous: Vec::new(),
                 emphasis_level: 0,
+                uppercase: false,
             },
             block: BlockContext {
                 indent_level: 0,
                 /// Whether we are at block-level or inline in a block.
                 level: BlockLevel::Inline,
+                column: 0,
+                inside_code_block: false,
+                pending_space: false,
+                line_start: 0,
+                line: None,
+                double_height: false,
+                quote_level: 0,
+                citation: None,
+                after_soft_break: false,
+                nesting_depth: 0,
+                skipped_depth: 0,
+                inside_html_comment: false,
+                inside_isolate: false,
+                banner: None,
             },
+            layout: Layout::from_settings(settings),
             links: LinkContext {
                 pending_links: VecDeque::new(),
                 next_link_index: 1,
                 current_link_type: None,
                 inside_inline_link: false,
+                rendered_links: Vec::new(),
+                heading_text: None,
+                slugger: Slugger::new(),
+                headings: Vec::new(),
+                fragment_text: None,
             },
             image: ImageContext {
                 inline_image: false,
+                linked_text: false,
+                figure_number: 0,
+            },
+            code: CodeContext {
+                buffer: None,
+                detect_syntax: false,
+                snippet_number: 0,
+                raw: false,
+            },
+            table: TableContext {
+                alignments: Vec::new(),
+                head: Vec::new(),
+                rows: Vec::new(),
+                row: Vec::new(),
+                cell: None,
+                inside_head: false,
             },
+            footnotes: FootnoteContext { labels: Vec::new() },
             list_item_kind: Vec::new(),
+            abbreviations: Vec::new(),
+            forced_style: match settings.terminal_capabilities.style {
+                StyleCapability::None if settings.force_style => {
+                    Some(StyleCapability::Ansi(AnsiStyle::new(StyleTier::Ansi16)))
+                }
+                _ => None,
+            },
+            deadline: None,
+        }
+    }
+
+    /// The writer of this context.
+    pub fn writer(&self) -> &W {
+        self.writer
+    }
+
+    /// Underline the given `abbreviations` in text.
+    pub fn with_abbreviations(mut self, abbreviations: Vec<Abbreviation>) -> Self {
+        self.abbreviations = abbreviations;
+        self
+    }
+
+    /// Resolve links to fragments against the given `headings`.
+    pub fn with_headings(mut self, headings: Vec<Heading>) -> Self {
+        self.links.headings = headings;
+        self
+    }
+
+    /// Stop loading images at the given `deadline`, if any.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Whether the deadline for rendering has passed.
+    pub fn deadline_passed(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => deadline <= Instant::now(),
+            None => false,
+        }
+    }
+
+    /// Settings for HTTP requests.
+    ///
+    /// Time out requests at the deadline, and cancel requests along with
+    /// rendering.
+    fn http(&self) -> Cow<'a, HttpSettings> {
+        let http = &self.settings.http;
+        if self.deadline.is_none() && self.settings.cancellation.is_none() {
+            return Cow::Borrowed(http);
+        }
+        let mut http = http.clone();
+        if let Some(deadline) = self.deadline {
+            http.timeout = Some(timeout_until(http.timeout, deadline));
+        }
+        if let Some(ref token) = self.settings.cancellation {
+            http.cancellation = Some(token.clone());
+        }
+        Cow::Owned(http)
+    }
+
+    /// The heading a link to `destination` points to, if any.
+    ///
+    /// Return the heading whose slug is the fragment of `destination`, if
+    /// `destination` is a fragment like `#installation`.
+    fn fragment_heading(&self, destination: &str) -> Option<&Heading> {
+        let mut parts = destination.splitn(2, '#');
+        match (parts.next(), parts.next()) {
+            (Some(""), Some(slug)) => self
+                .links
+                .headings
+                .iter()
+                .find(|heading| heading.slug == slug),
+            _ => None,
+        }
+    }
+
+    /// Resolve the `destination` of a link.
+    ///
+    /// Resolve links to headings of the document to their permalinks, if
+    /// settings have a base URL for permalinks, and do not resolve fragments
+    /// otherwise: We do not know the URL of the document.  Resolve all other
+    /// references with [`Context::resolve_reference`].
+    fn resolve_link(&self, destination: &str) -> Option<url::Url> {
+        if destination.starts_with('#') {
+            match (
+                &self.settings.permalink_base,
+                self.fragment_heading(destination),
+            ) {
+                (Some(base_url), Some(heading)) => Some(permalink(base_url, &heading.slug)),
+                _ => None,
+            }
+        } else {
+            self.resolve_reference(destination)
         }
     }
 
@@ -212,6 +534,10 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
     ///
     /// Restart all current styles after the newline.
     fn newline(&mut self) -> io::Result<()> {
+        self.flush_line(false)?;
+        self.block.column = 0;
+        self.block.line_start = 0;
+        self.block.pending_space = false;
         writeln!(self.writer)
     }
 
@@ -224,9 +550,57 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
         self.indent()
     }
 
+    /// Reduce the indentation level by `columns`.
+    ///
+    /// Never go below zero, even if events do not match up.
+    fn reduce_indent(&mut self, columns: usize) {
+        self.block.indent_level = self.block.indent_level.saturating_sub(columns);
+    }
+
     /// Indent according to the current indentation level.
     fn indent(&mut self) -> io::Result<()> {
-        write!(self.writer, "{}", " ".repeat(self.block.indent_level)).map_err(Into::into)
+        self.indent_by(self.block.indent_level)
+    }
+
+    /// Indent by the given number of `columns`.
+    ///
+    /// Text of the current line starts after the indentation.
+    fn indent_by(&mut self, columns: usize) -> io::Result<()> {
+        self.write_margin_if_needed()?;
+        self.write_plain(" ".repeat(columns))?;
+        self.block.line_start = self.block.column;
+        Ok(())
+    }
+
+    /// Write the layout margin if at the beginning of a line.
+    fn write_margin_if_needed(&mut self) -> io::Result<()> {
+        if self.block.column == 0 && 0 < self.layout.margin {
+            write!(self.writer, "{}", " ".repeat(self.layout.margin))?;
+            self.block.column = self.layout.margin;
+        }
+        Ok(())
+    }
+
+    /// Advance the column by the width of `text`.
+    fn advance_column(&mut self, text: &str) {
+        match text.rfind('\n') {
+            Some(index) => self.block.column = bidi::text_width(&text[index + 1..]),
+            None => self.block.column += bidi::text_width(text),
+        }
+    }
+
+    /// Whether the cursor is at the beginning of the current line.
+    ///
+    /// Consider margin and indentation as beginning of the line.
+    fn at_line_start(&self) -> bool {
+        self.block.column <= self.block.line_start
+    }
+
+    /// Write `text` without any style.
+    fn write_plain<S: AsRef<str>>(&mut self, text: S) -> io::Result<()> {
+        write!(self.writer, "{}", text.as_ref())?;
+        self.advance_column(text.as_ref());
+        Ok(())
     }
 
     /// Push a new style.
@@ -247,10 +621,260 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
     }
 
     /// Write `text` with the given `style`.
+    ///
+    /// If the layout has a margin write the margin at the beginning of each
+    /// line in `text`.
     fn write_styled<S: AsRef<str>>(&mut self, style: &Style, text: S) -> io::Result<()> {
-        match self.settings.terminal_capabilities.style {
-            StyleCapability::None => write!(self.writer, "{}", text.as_ref())?,
-            StyleCapability::Ansi(ref ansi) => ansi.write_styled(self.writer, style, text)?,
+        let text = text.as_ref();
+        if let Some(ref mut cell) = self.table.cell {
+            push_words(cell, *style, text);
+            return Ok(());
+        }
+        if self.block.pending_space {
+            self.block.pending_space = false;
+            self.write_segment(LineSegment::Space(*style))?;
+        }
+        if self.layout.margin == 0 && self.block.line.is_none() {
+            self.write_styled_unchecked(style, text)?;
+            self.advance_column(text);
+        } else {
+            for line in LinesWithEndings::from(text) {
+                let content = line.trim_end_matches('\n');
+                if !content.is_empty() {
+                    self.write_segment(LineSegment::Text(*style, content.to_string()))?;
+                }
+                if content.len() < line.len() {
+                    self.newline()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a `segment` of the current line.
+    ///
+    /// Write the margin if needed, and buffer the segment if we justify the
+    /// current line or if we are inside a table cell.
+    fn write_segment(&mut self, segment: LineSegment) -> io::Result<()> {
+        if let Some(ref mut cell) = self.table.cell {
+            cell.push(segment);
+            return Ok(());
+        }
+        self.write_margin_if_needed()?;
+        self.block.column += segment.width();
+        if let Some(ref mut line) = self.block.line {
+            line.push(segment);
+            Ok(())
+        } else {
+            self.write_segment_unchecked(segment, 1)
+        }
+    }
+
+    /// Write a `segment` as is.
+    ///
+    /// Write spaces with the given `space_width`.
+    fn write_segment_unchecked(
+        &mut self,
+        segment: LineSegment,
+        space_width: usize,
+    ) -> io::Result<()> {
+        match segment {
+            LineSegment::Text(style, text) => self.write_styled_unchecked(&style, &text),
+            LineSegment::Space(style) => {
+                self.write_styled_unchecked(&style, &" ".repeat(space_width))
+            }
+            LineSegment::Escapes(escapes) => self.writer.write_all(&escapes),
+        }
+    }
+
+    /// Write escape sequences created by `f`.
+    ///
+    /// The escape sequences must not move the cursor.
+    fn write_escapes<F>(&mut self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    {
+        let mut escapes = Vec::new();
+        f(&mut escapes)?;
+        self.write_segment(LineSegment::Escapes(escapes))
+    }
+
+    /// Write all buffered segments of the current line.
+    ///
+    /// If `justify` is true stretch spaces to align the line to the right edge
+    /// of the layout.
+    fn flush_line(&mut self, justify: bool) -> io::Result<()> {
+        let segments = match self.block.line {
+            Some(ref mut line) => std::mem::take(line),
+            None => return Ok(()),
+        };
+        if self.block.double_height {
+            // Write the top half of the line, and indent the bottom half like
+            // the top half.
+            let width: usize = segments.iter().map(LineSegment::width).sum();
+            let leading = self.block.column.saturating_sub(width);
+            write!(self.writer, "\x1b#3")?;
+            for segment in segments.iter().cloned() {
+                self.write_segment_unchecked(segment, 1)?;
+            }
+            write!(self.writer, "\n\x1b#4{}", " ".repeat(leading))?;
+        }
+        let mut gaps = segments
+            .iter()
+            .filter(|segment| matches!(segment, LineSegment::Space(_)))
+            .count();
+        let right_edge = self.line_right_edge();
+        let mut extra = if justify {
+            right_edge.saturating_sub(self.block.column)
+        } else {
+            0
+        };
+        for segment in segments {
+            let mut space_width = 1;
+            if let LineSegment::Space(_) = segment {
+                // Distribute extra space evenly, and put the remainder to the
+                // left.
+                let stretch = (extra + gaps - 1) / gaps;
+                space_width += stretch;
+                extra -= stretch;
+                gaps -= 1;
+            }
+            self.write_segment_unchecked(segment, space_width)?;
+        }
+        Ok(())
+    }
+
+    /// How to write lines of code which do not fit into the layout.
+    ///
+    /// Let lines overflow in copy mode, to not break code with markers.
+    fn code_overflow(&self) -> CodeOverflow {
+        if self.settings.copy_mode {
+            CodeOverflow::Overflow
+        } else {
+            self.settings.code_overflow
+        }
+    }
+
+    /// The colours of inline markup and decorations in the style sheet.
+    fn colours(&self) -> Colours {
+        self.settings.style_sheet.colours
+    }
+
+    /// The styles to write with.
+    ///
+    /// The style capability of the terminal, or basic ANSI styles if settings
+    /// force styles on a terminal without any.
+    fn style_capability(&self) -> &StyleCapability {
+        match self.forced_style {
+            Some(ref forced) => forced,
+            None => &self.settings.terminal_capabilities.style,
+        }
+    }
+
+    /// Write `text` with the given `style` as is.
+    ///
+    /// Do not track the column, and ignore the layout.
+    fn write_styled_unchecked(&mut self, style: &Style, text: &str) -> io::Result<()> {
+        let capability = match self.forced_style {
+            Some(ref forced) => forced,
+            None => &self.settings.terminal_capabilities.style,
+        };
+        capability.write_styled(self.writer, style, text)
+    }
+
+    /// Write `text` with the current style and wrap at the layout width.
+    ///
+    /// Break lines between words, i.e. at spaces, and only if the layout wraps
+    /// text at all; otherwise write `text` as is.  Close and reopen the
+    /// current directional isolate around line breaks.
+    fn write_wrapped(&mut self, text: &str) -> io::Result<()> {
+        let style = self.style.current;
+        if !self.layout.wrap {
+            return self.write_styled(&style, text);
+        }
+        let right_edge = self.line_right_edge();
+        for (index, word) in text.split(' ').enumerate() {
+            if 0 < index && !self.at_line_start() {
+                self.block.pending_space = true;
+            }
+            if !word.is_empty() {
+                let space = if self.block.pending_space { 1 } else { 0 };
+                let width = bidi::text_width(word) + space;
+                if right_edge < self.block.column + width && !self.at_line_start() {
+                    if self.block.inside_isolate {
+                        // Close the isolate right after the last word, before
+                        // the space we drop at the line break.
+                        self.block.pending_space = false;
+                        self.write_styled(&style, bidi::POP_DIRECTIONAL_ISOLATE.to_string())?;
+                    }
+                    self.flush_line(true)?;
+                    self.newline_and_indent()?;
+                    if self.block.inside_isolate {
+                        self.write_styled(&style, bidi::FIRST_STRONG_ISOLATE.to_string())?;
+                    }
+                }
+                self.write_styled(&style, word)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The column to wrap the current line at.
+    ///
+    /// The terminal draws double-height lines at double width, so they wrap
+    /// at half the width of the layout.
+    fn line_right_edge(&self) -> usize {
+        let right_edge = self.layout.margin + self.layout.width;
+        if self.block.double_height {
+            right_edge / 2
+        } else {
+            right_edge
+        }
+    }
+
+    /// The number of columns available for content at the current indentation.
+    fn available_width(&self) -> usize {
+        self.layout.width.saturating_sub(self.block.indent_level)
+    }
+
+    /// Write inline `text` with the current style.
+    ///
+    /// Underline abbreviations, and write `text` in uppercase if the current
+    /// style asks for it.  Put `text` into a directional isolate if it has
+    /// right-to-left letters and settings say so.
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        if self.settings.bidi_isolates && bidi::has_right_to_left(text) {
+            let style = self.style.current;
+            self.write_styled(&style, bidi::FIRST_STRONG_ISOLATE.to_string())?;
+            self.block.inside_isolate = true;
+            self.write_text_parts(text)?;
+            self.block.inside_isolate = false;
+            self.write_styled(&style, bidi::POP_DIRECTIONAL_ISOLATE.to_string())
+        } else {
+            self.write_text_parts(text)
+        }
+    }
+
+    /// Write the parts of inline `text`, with abbreviations underlined.
+    fn write_text_parts(&mut self, text: &str) -> io::Result<()> {
+        let parts: Vec<(bool, String)> = split_abbreviations(text, &self.abbreviations)
+            .into_iter()
+            .map(|(is_abbreviation, part)| {
+                if self.style.uppercase {
+                    (is_abbreviation, part.to_uppercase())
+                } else {
+                    (is_abbreviation, part.to_string())
+                }
+            })
+            .collect();
+        for (is_abbreviation, part) in parts {
+            if is_abbreviation {
+                self.set_style(self.style.current.underline());
+                self.write_wrapped(&part)?;
+                self.drop_style();
+            } else {
+                self.write_wrapped(&part)?;
+            }
         }
         Ok(())
     }
@@ -261,17 +885,74 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
         self.write_styled(&style, text)
     }
 
+    /// Write `text` as banner in the current style.
+    ///
+    /// Write `text` as is if the banner font lacks some of its characters, or
+    /// if the banner does not fit into the layout.
+    fn write_banner(&mut self, text: &str) -> io::Result<()> {
+        let right_edge = self.layout.margin + self.layout.width;
+        let available = right_edge.saturating_sub(self.block.column);
+        match banner::banner(text) {
+            Some(lines) if lines.iter().all(|line| line.len() <= available) => {
+                for (index, line) in lines.iter().enumerate() {
+                    if 0 < index {
+                        self.newline_and_indent()?;
+                    }
+                    self.write_styled_current(line)?;
+                }
+                Ok(())
+            }
+            _ => self.write_text(text),
+        }
+    }
+
+    /// Whether `text` starts a citation.
+    ///
+    /// A citation is a line of a block quote which starts with an em dash or
+    /// two dashes.  `after_soft_break` tells whether `text` follows a refilled
+    /// line break.
+    fn is_citation_start(&self, text: &str, after_soft_break: bool) -> bool {
+        let at_line_start = self.block.column == self.block.line_start || after_soft_break;
+        0 < self.block.quote_level
+            && at_line_start
+            && (text.starts_with('\u{2014}') || text.starts_with("--"))
+    }
+
+    /// Write a pending citation as normal text.
+    ///
+    /// Do nothing if there's no pending citation.
+    fn write_pending_citation(&mut self) -> io::Result<()> {
+        if let Some(citation) = self.block.citation.take() {
+            self.write_wrapped(&citation)?;
+        }
+        Ok(())
+    }
+
+    /// Write a pending citation dimmed and aligned to the right edge.
+    ///
+    /// Do nothing if there's no pending citation.
+    fn write_citation(&mut self) -> io::Result<()> {
+        if let Some(citation) = self.block.citation.take() {
+            if self.block.column != self.block.line_start {
+                // We refilled the paragraph, so put the citation on its own line
+                self.newline_and_indent()?;
+            }
+            let right_edge = self.layout.margin + self.layout.width;
+            let width = bidi::text_width(&citation);
+            let padding = right_edge.saturating_sub(self.block.column + width);
+            self.write_styled(&Style::new(), " ".repeat(padding))?;
+            self.write_styled(&self.style.current.dimmed(), citation)?;
+        }
+        Ok(())
+    }
+
     /// Enable emphasis.
     ///
     /// Enable italic or upright text according to the current emphasis level.
     fn enable_emphasis(&mut self) {
         self.style.emphasis_level += 1;
         let is_italic = self.style.emphasis_level % 2 == 1;
-        let new_style = Style {
-            is_italic,
-            ..self.style.current
-        };
-        self.set_style(new_style);
+        self.set_style(self.style.current.with_italic(is_italic));
     }
 
     /// Add a link to the context.
@@ -288,100 +969,1338 @@ impl<'a, 'b, W: Write> Context<'a, 'b, W> {
         index
     }
 
+    /// Record a link or image written with the given `placement`.
+    fn record_link(&mut self, kind: LinkKind, destination: &str, placement: LinkPlacement) {
+        let url = match kind {
+            LinkKind::Link => self.resolve_link(destination),
+            _ => self.resolve_reference(destination),
+        };
+        self.links.rendered_links.push(RenderedLink {
+            kind,
+            destination: destination.to_string(),
+            url,
+            placement,
+        });
+    }
+
+    /// End the current heading with a link to its permalink.
+    ///
+    /// Do nothing if settings have no base URL for permalinks, or if the
+    /// terminal does not support inline links.
+    fn write_permalink(&mut self) -> io::Result<()> {
+        let settings = self.settings;
+        // Create slugs for all headings, to count repeated slugs
+        let slug = match self.links.heading_text.take() {
+            Some(text) => self.links.slugger.slug(&text),
+            None => return Ok(()),
+        };
+        match (
+            &settings.permalink_base,
+            &settings.terminal_capabilities.links,
+        ) {
+            (Some(base_url), LinkCapability::OSC8(osc8)) => {
+                let url = permalink(base_url, &slug);
+                let destination = url.clone();
+                self.write_escapes(|writer| osc8.set_link_url(writer, destination))?;
+                self.write_styled(&self.style.current.dimmed(), " \u{b6}")?;
+                self.write_escapes(|writer| osc8.clear_link(writer))?;
+                self.links.rendered_links.push(RenderedLink {
+                    kind: LinkKind::Permalink,
+                    destination: format!("#{}", slug),
+                    url: Some(url),
+                    placement: LinkPlacement::Inline,
+                });
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Take all links and images written so far.
+    pub fn take_rendered_links(&mut self) -> Vec<RenderedLink> {
+        std::mem::take(&mut self.links.rendered_links)
+    }
+
+    /// Write pending links after a paragraph.
+    ///
+    /// Write pending links if settings say so after every paragraph, or if
+    /// there are as many as settings permit.
+    fn write_excess_links(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.settings.reference_flush == ReferenceFlushPolicy::AfterParagraph {
+            return self.write_pending_links();
+        }
+        match self.settings.max_pending_links {
+            Some(max) if max <= self.links.pending_links.len() => self.write_pending_links(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether to skip `event` because it is nested too deeply.
+    ///
+    /// Track the depth of block quotes and lists, and skip all events inside
+    /// block quotes and lists nested deeper than settings permit.
+    fn skip_nested(&mut self, event: &Event<'_>) -> bool {
+        let max_depth = match self.settings.max_nesting_depth {
+            Some(max_depth) => max_depth,
+            None => return false,
+        };
+        let block = &mut self.block;
+        match event {
+            Start(BlockQuote) | Start(List(_)) => {
+                if 0 < block.skipped_depth || max_depth <= block.nesting_depth {
+                    block.skipped_depth += 1;
+                } else {
+                    block.nesting_depth += 1;
+                }
+                0 < block.skipped_depth
+            }
+            End(BlockQuote) | End(List(_)) => {
+                if 0 < block.skipped_depth {
+                    block.skipped_depth -= 1;
+                    true
+                } else {
+                    block.nesting_depth = block.nesting_depth.saturating_sub(1);
+                    false
+                }
+            }
+            _ => 0 < block.skipped_depth,
+        }
+    }
+
+    /// Write the separator before a document with the given `name`.
+    ///
+    /// See [`crate::push_separator`].
+    pub fn write_separator(mut self, name: &str, first: bool) -> Result<Self, Box<dyn Error>> {
+        if !first {
+            // We continue after the end of another document
+            self.block.level = BlockLevel::Block;
+        }
+        match self.settings.document_separator {
+            DocumentSeparator::None => Ok(self),
+            DocumentSeparator::Rule if !first => {
+                let mut ctx = write_event(self, Rule)?;
+                ctx.newline()?;
+                Ok(ctx)
+            }
+            DocumentSeparator::Filename => {
+                let mut ctx = write_event(self, Start(Heading(1)))?;
+                ctx = write_event(ctx, Text(name.to_string().into()))?;
+                ctx = write_event(ctx, End(Heading(1)))?;
+                ctx.newline()?;
+                Ok(ctx)
+            }
+            DocumentSeparator::FormFeed if !first => {
+                write!(self.writer, "\x0c")?;
+                Ok(self)
+            }
+            DocumentSeparator::Rule | DocumentSeparator::FormFeed => Ok(self),
+        }
+    }
+
     /// Write all pending links.
     ///
-    /// Empty all pending links afterwards.
+    /// Set a mark before the links, and empty all pending links afterwards.
     pub fn write_pending_links(&mut self) -> Result<(), Box<dyn Error>> {
         if !self.links.pending_links.is_empty() {
             self.newline()?;
-            let link_style = self.style.current.fg(Colour::Blue);
+            self.set_mark_if_supported()?;
+            let link_style = self.style.current.fg(self.colours().link);
             while let Some(link) = self.links.pending_links.pop_front() {
-                let link_text = format!("[{}]: {} {}", link.index, link.destination, link.title);
-                self.write_styled(&link_style, link_text)?;
+                let prefix = format!("[{}]: ", link.index);
+                let title = reference_title(&link.title, self.settings.link_titles);
+                match self.settings.reference_overflow {
+                    ReferenceOverflow::Overflow => {
+                        let link_text = format!("{}{}{}", prefix, link.destination, title);
+                        self.write_styled(&link_style, link_text)?;
+                    }
+                    ReferenceOverflow::Ellipsize => {
+                        let available = self
+                            .layout
+                            .width
+                            .saturating_sub(bidi::text_width(&prefix) + bidi::text_width(&title));
+                        let ellipsis = self.settings.style_sheet.symbols.ellipsis;
+                        let destination = ellipsize_middle(&link.destination, available, ellipsis);
+                        let link_text = format!("{}{}{}", prefix, destination, title);
+                        self.write_styled(&link_style, link_text)?;
+                    }
+                    ReferenceOverflow::Wrap => {
+                        let link_text = format!("{}{}{}", prefix, link.destination, title);
+                        if bidi::text_width(&link_text) <= self.layout.width {
+                            self.write_styled(&link_style, link_text)?;
+                        } else {
+                            let indent = bidi::text_width(&prefix);
+                            let lines = hard_wrap(link_text.trim_end(), self.layout.width, indent);
+                            for (index, line) in lines.iter().enumerate() {
+                                if 0 < index {
+                                    self.newline()?;
+                                    self.indent_by(indent)?;
+                                }
+                                self.write_styled(&link_style, line)?;
+                            }
+                        }
+                    }
+                }
                 self.newline()?
             }
         };
         Ok(())
     }
 
-    /// Write a simple border.
-    fn write_border(&mut self) -> io::Result<()> {
-        let separator = "\u{2500}".repeat(self.settings.terminal_size.width.min(20));
-        self.write_styled(&self.style.current.fg(Colour::Green), separator)?;
+    /// Write a glossary of all abbreviations.
+    ///
+    /// Write nothing if there are no abbreviations.
+    pub fn write_glossary(&mut self) -> io::Result<()> {
+        if !self.abbreviations.is_empty() {
+            self.newline()?;
+            for (abbreviation, expansion) in std::mem::take(&mut self.abbreviations) {
+                self.write_styled(&self.style.current.underline(), abbreviation)?;
+                self.write_styled_current(format!(": {}", expansion))?;
+                self.newline()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a dimmed footer with the `source` of a document and the time it
+    /// was `fetched` at, if any.
+    pub fn write_footer(&mut self, source: &str, fetched: Option<SystemTime>) -> io::Result<()> {
+        self.newline()?;
+        let footer = match fetched {
+            Some(fetched) => format!("Source: {}, fetched {}", source, format_utc(fetched)),
+            None => format!("Source: {}", source),
+        };
+        self.write_styled(&self.style.current.dimmed(), footer)?;
         self.newline()
     }
 
-    /// Write highlighted `text`.
+    /// Write a ruler with column markers over the content width.
     ///
-    /// If the code context has a highlighter, use it to highlight `text` and
-    /// write it.  Otherwise write `text` without highlighting.
-    fn write_highlighted(&mut self, text: CowStr<'b>) -> io::Result<()> {
-        if let (Some(ref mut highlighter), StyleCapability::Ansi(ref ansi)) = (
-            &mut self.current_highlighter,
-            &self.settings.terminal_capabilities.style,
-        ) {
-            let regions = highlighter.highlight(&text, &self.settings.syntax_set);
-            highlighting::write_as_ansi(self.writer, ansi, &regions)?;
-        } else {
-            self.write_styled_current(&text)?;
+    /// Mark every fifth column with `+`, and every tenth column with the
+    /// last digit of its tens.
+    pub fn write_column_ruler(&mut self) -> io::Result<()> {
+        let ruler: String = (1..=self.layout.width)
+            .map(|column| match column {
+                column if column % 10 == 0 => {
+                    std::char::from_digit((column / 10 % 10) as u32, 10).unwrap_or('|')
+                }
+                column if column % 5 == 0 => '+',
+                _ => '\u{b7}',
+            })
+            .collect();
+        self.write_margin_if_needed()?;
+        self.write_styled(&self.style.current.dimmed(), ruler)?;
+        self.newline()
+    }
+
+    /// Annotate the start of a `block` with its indentation and width.
+    fn write_layout_annotation(&mut self, block: &str) -> io::Result<()> {
+        let indent = self.block.indent_level;
+        let width = self.layout.width.saturating_sub(indent);
+        let annotation = format!("[{}: indent {}, width {}]", block, indent, width);
+        self.write_margin_if_needed()?;
+        self.write_styled(&self.style.current.dimmed(), annotation)?;
+        if let BlockLevel::Inline = self.block.level {
+            // Blocks at block level start on a new line anyway
+            self.newline_and_indent()?;
         }
         Ok(())
     }
 
-    /// Set a mark on the current position of the terminal if supported,
-    /// otherwise do nothing.
-    fn set_mark_if_supported(&mut self) -> io::Result<()> {
-        match self.settings.terminal_capabilities.marks {
-            MarkCapability::ITerm2(ref marks) => marks.set_mark(self.writer),
-            MarkCapability::None => Ok(()),
-        }
+    /// Write a dimmed notice which tells that rendering took too long.
+    ///
+    /// Write the notice as a block of its own.
+    pub fn write_truncated(&mut self) -> io::Result<()> {
+        self.start_inline_text()?;
+        let ellipsis = self.settings.style_sheet.symbols.ellipsis;
+        let notice = format!("{} truncated, rendering took too long", ellipsis);
+        self.write_styled(&self.style.current.dimmed(), notice)?;
+        self.end_inline_text_with_margin()
     }
-}
 
-/// Write a single `event` in the given context.
+    /// Write a dimmed trailer which tells that `count` more lines follow.
+    pub fn write_more_lines(&mut self, count: usize) -> io::Result<()> {
+        let lines = if count == 1 { "line" } else { "lines" };
+        let ellipsis = self.settings.style_sheet.symbols.ellipsis;
+        let trailer = format!("{} {} more {}", ellipsis, count, lines);
+        self.write_styled(&self.style.current.dimmed(), trailer)?;
+        self.newline()
+    }
+
+    /// Write a simple border.
+    ///
+    /// Write an empty line instead in copy mode.
+    fn write_border(&mut self) -> io::Result<()> {
+        if self.settings.copy_mode {
+            return self.newline();
+        }
+        let separator = self.horizontal_line(self.layout.width.min(20));
+        self.write_styled(&self.style.current.fg(self.colours().border), separator)?;
+        self.newline()
+    }
+
+    /// Write the top border of a code block.
+    ///
+    /// Label the border with the number of the code block if settings say so.
+    /// Write nothing in copy mode.
+    fn write_code_border(&mut self) -> io::Result<()> {
+        self.code.snippet_number += 1;
+        if self.settings.copy_mode {
+            Ok(())
+        } else if self.settings.number_snippets {
+            let separator = self.horizontal_line(self.layout.width.min(20));
+            self.write_styled(&self.style.current.fg(self.colours().border), separator)?;
+            let label = format!(" [snippet {}]", self.code.snippet_number);
+            self.write_styled(&self.style.current.dimmed(), label)?;
+            self.newline()
+        } else {
+            self.write_border()
+        }
+    }
+
+    /// Write the current table and clear it.
+    ///
+    /// Pad cells to the width of their column according to the alignment of
+    /// the column, write the header row in the header style of the style
+    /// sheet, and stripe the rows of the body if the style sheet says so.
+    fn write_table(&mut self) -> io::Result<()> {
+        let alignments = std::mem::take(&mut self.table.alignments);
+        let head = std::mem::take(&mut self.table.head);
+        let rows = std::mem::take(&mut self.table.rows);
+        let mut natural = vec![0; alignments.len()];
+        let mut minimal = vec![0; alignments.len()];
+        for row in std::iter::once(&head).chain(rows.iter()) {
+            for ((natural, minimal), cell) in natural.iter_mut().zip(&mut minimal).zip(row) {
+                let words = cell_words(cell);
+                let spaces = words.iter().filter(|(space, _)| space.is_some()).count();
+                let widths = words.iter().map(|(_, word)| segments_width(word));
+                *natural = (*natural).max(widths.clone().sum::<usize>() + spaces);
+                *minimal = (*minimal).max(widths.max().unwrap_or(0));
+            }
+        }
+        // Each column takes three more columns for its left border and padding,
+        // and the table one more for the right border.
+        let widths = self
+            .available_width()
+            .checked_sub(alignments.len() * 3 + 1)
+            .and_then(|budget| column_widths(&natural, &minimal, budget));
+        let widths = match widths {
+            Some(widths) => widths,
+            // If the table doesn't fit even with wrapped cells fall back to
+            // records.
+            None => return self.write_table_records(&head, &rows),
+        };
+        let table_style = self.settings.style_sheet.table;
+        let junctions = self.settings.style_sheet.symbols.junctions;
+        self.start_inline_text()?;
+        self.write_table_border(&widths, &junctions[0..3])?;
+        self.newline_and_indent()?;
+        self.write_table_row(&head, &alignments, &widths, &table_style.header)?;
+        self.newline_and_indent()?;
+        self.write_table_border(&widths, &junctions[3..6])?;
+        for (index, row) in rows.iter().enumerate() {
+            let style = match table_style.stripe {
+                Some(stripe) if index % 2 == 1 => stripe,
+                _ => self.style.current,
+            };
+            self.newline_and_indent()?;
+            self.write_table_row(row, &alignments, &widths, &style)?;
+        }
+        self.newline_and_indent()?;
+        self.write_table_border(&widths, &junctions[6..9])?;
+        self.end_inline_text_with_margin()
+    }
+
+    /// The number of the footnote with the given `label`.
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let labels = &mut self.footnotes.labels;
+        match labels.iter().position(|other| other == label) {
+            Some(index) => index + 1,
+            None => {
+                labels.push(label.to_string());
+                labels.len()
+            }
+        }
+    }
+
+    /// Write the marker of the footnote with the given `label`.
+    ///
+    /// Return the width of the marker.
+    fn write_footnote_marker(&mut self, label: &str) -> io::Result<usize> {
+        let number = self.footnote_number(label);
+        let marker = footnote_marker(self.settings.footnote_marker, number);
+        self.write_styled(&self.style.current.fg(self.colours().link), &marker)?;
+        Ok(marker.chars().count())
+    }
+
+    /// Write a table as records, for tables too wide for the layout.
+    ///
+    /// Write every row of the table `body` as a list of `Header: value` lines,
+    /// with the `head` of each column, and separate rows with rules.
+    fn write_table_records(&mut self, head: &[Cell], body: &[Vec<Cell>]) -> io::Result<()> {
+        let table_style = self.settings.style_sheet.table;
+        let rule = self.horizontal_line(self.available_width());
+        self.start_inline_text()?;
+        for (index, row) in body.iter().enumerate() {
+            if 0 < index {
+                self.newline_and_indent()?;
+                self.write_styled(&table_style.border, &rule)?;
+            }
+            for (column, header) in head.iter().enumerate() {
+                if 0 < index || 0 < column {
+                    self.newline_and_indent()?;
+                }
+                self.write_cell_wrapped(header)?;
+                self.write_styled(&table_style.header, ":")?;
+                let value = row.get(column).map_or(&[][..], Vec::as_slice);
+                if !value.is_empty() {
+                    self.write_segment(LineSegment::Space(self.style.current))?;
+                    self.write_cell_wrapped(value)?;
+                }
+            }
+        }
+        self.end_inline_text_with_margin()
+    }
+
+    /// Write the segments of a table `cell`, and wrap at the layout width.
+    fn write_cell_wrapped(&mut self, cell: &[LineSegment]) -> io::Result<()> {
+        let right_edge = self.line_right_edge();
+        for (index, (space, word)) in cell_words(cell).into_iter().enumerate() {
+            let space_width = if space.is_some() { 1 } else { 0 };
+            let width = space_width + segments_width(&word);
+            if 0 < index && right_edge < self.block.column + width && !self.at_line_start() {
+                self.newline_and_indent()?;
+            } else if let Some(style) = space {
+                self.write_segment(LineSegment::Space(style))?;
+            }
+            for segment in word {
+                self.write_segment(segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A horizontal line of the given `width`.
+    fn horizontal_line(&self, width: usize) -> String {
+        let horizontal = self.settings.style_sheet.symbols.horizontal;
+        horizontal.to_string().repeat(width)
+    }
+
+    /// Write a horizontal border of a table with columns of the given `widths`.
+    ///
+    /// Take the left, middle and right `junctions` of the border: start the
+    /// border with the left one, end it with the right one, and use the
+    /// middle one between columns.
+    fn write_table_border(&mut self, widths: &[usize], junctions: &[char]) -> io::Result<()> {
+        let lines: Vec<String> = widths
+            .iter()
+            .map(|width| self.horizontal_line(width + 2))
+            .collect();
+        let middle = junctions[1].to_string();
+        let border = format!("{}{}{}", junctions[0], lines.join(&middle), junctions[2]);
+        self.write_styled(&self.settings.style_sheet.table.border, border)
+    }
+
+    /// Write a `row` of a table with the given `style`.
+    ///
+    /// Wrap each cell at the width of its column, pad each line of a cell to
+    /// the width of its column and align it within the column.
+    fn write_table_row(
+        &mut self,
+        row: &[Cell],
+        alignments: &[Alignment],
+        widths: &[usize],
+        style: &Style,
+    ) -> io::Result<()> {
+        let border_style = self.settings.style_sheet.table.border;
+        let vertical = self.settings.style_sheet.symbols.vertical.to_string();
+        let cells: Vec<Vec<Cell>> = widths
+            .iter()
+            .enumerate()
+            .map(|(index, width)| wrap_cell(row.get(index).map_or(&[][..], Vec::as_slice), *width))
+            .collect();
+        let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+        for line in 0..height {
+            if 0 < line {
+                self.newline_and_indent()?;
+            }
+            self.write_styled(&border_style, &vertical)?;
+            for ((cell, width), alignment) in cells.iter().zip(widths).zip(alignments) {
+                let segments = cell.get(line).map_or(&[][..], Vec::as_slice);
+                let padding = width.saturating_sub(segments_width(segments));
+                let (left, right) = match alignment {
+                    Alignment::None | Alignment::Left => (0, padding),
+                    Alignment::Center => (padding / 2, padding - padding / 2),
+                    Alignment::Right => (padding, 0),
+                };
+                let isolate = self.settings.bidi_isolates
+                    && segments.iter().any(|segment| match segment {
+                        LineSegment::Text(_, text) => bidi::has_right_to_left(text),
+                        _ => false,
+                    });
+                self.write_styled(style, " ".repeat(left + 1))?;
+                if isolate {
+                    self.write_styled(style, bidi::FIRST_STRONG_ISOLATE.to_string())?;
+                }
+                for segment in segments.iter().cloned() {
+                    self.write_segment(segment)?;
+                }
+                if isolate {
+                    self.write_styled(style, bidi::POP_DIRECTIONAL_ISOLATE.to_string())?;
+                }
+                self.write_styled(style, " ".repeat(right + 1))?;
+                self.write_styled(&border_style, &vertical)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The theme to highlight code with.
+    ///
+    /// Resolve the theme only when a code block needs it, so that documents
+    /// without code never load the default theme.
+    fn theme(&self) -> &'a Theme {
+        highlighting_theme(self.settings)
+    }
+
+    /// Guess the syntax of the current code block from its `first_line`.
+    ///
+    /// If we find a syntax, replace the plain code style with a highlighter
+    /// for this syntax.
+    fn detect_syntax(&mut self, first_line: &str) {
+        let syntax_set = &self.settings.syntax_set;
+        if let Some(syntax) = syntax::find_syntax_by_first_line(syntax_set, first_line) {
+            self.drop_style();
+            self.current_highlighter = Some(HighlightLines::new(syntax, self.theme()));
+        }
+    }
+
+    /// Write highlighted `text`.
+    ///
+    /// If the code context has a highlighter, use it to highlight `text` and
+    /// write it.  Otherwise write `text` without highlighting.
+    ///
+    /// Wrap or truncate lines which do not fit into the layout according to
+    /// settings.
+    fn write_highlighted(&mut self, text: CowStr<'b>) -> io::Result<()> {
+        let background = self.code_background();
+        if self.current_highlighter.is_none()
+            && self.code_overflow() == CodeOverflow::Overflow
+            && background.is_none()
+        {
+            return self.write_styled_current(&text);
+        }
+        let is_ansi = matches!(self.style_capability(), StyleCapability::Ansi(_));
+        for line in LinesWithEndings::from(&text) {
+            if line != "\n" || background.is_some() {
+                self.write_margin_if_needed()?;
+            }
+            // Highlight the entire line, to keep the state of the highlighter
+            // intact when wrapping or truncating the line.
+            let regions = match (&mut self.current_highlighter, is_ansi) {
+                (Some(ref mut highlighter), true) => highlighter
+                    .highlight(line, &self.settings.syntax_set)
+                    .into_iter()
+                    .map(|(style, text)| (Some(style), text))
+                    .collect(),
+                _ => vec![(None, line)],
+            };
+            self.write_code_line(regions)?;
+        }
+        Ok(())
+    }
+
+    /// Write `regions` of a single line of code.
+    ///
+    /// Write regions without a highlighting style in the current style.  If
+    /// the line does not fit into the layout wrap it with a continuation
+    /// marker or truncate it with an ellipsis, according to settings.
+    fn write_code_line(&mut self, regions: Vec<(Option<SyntectStyle>, &str)>) -> io::Result<()> {
+        let right_edge = self.layout.margin + self.layout.width;
+        let marker_style = match self.code_background() {
+            Some(background) => self.style.current.dimmed().on(background),
+            None => self.style.current.dimmed(),
+        };
+        let mut regions = regions;
+        loop {
+            let available = right_edge.saturating_sub(self.block.column);
+            // Give up if the line fits, or if there is no room for a
+            // continuation marker and at least one character.
+            if regions_width(&regions) <= available || available < 3 {
+                break;
+            }
+            match self.code_overflow() {
+                CodeOverflow::Overflow => break,
+                CodeOverflow::Truncate => {
+                    let (head, tail) = split_regions(&regions, available - 1);
+                    self.write_code_regions(&head)?;
+                    let ellipsis = self.settings.style_sheet.symbols.ellipsis;
+                    self.write_styled_unchecked(&marker_style, &ellipsis.to_string())?;
+                    self.block.column += 1;
+                    if matches!(tail.last(), Some((_, text)) if text.ends_with('\n')) {
+                        self.fill_code_background()?;
+                        self.newline()?;
+                    }
+                    return Ok(());
+                }
+                CodeOverflow::Wrap => {
+                    let (head, tail) = split_regions(&regions, available);
+                    self.write_code_regions(&head)?;
+                    self.fill_code_background()?;
+                    self.newline()?;
+                    self.write_margin_if_needed()?;
+                    let continuation = self.settings.style_sheet.symbols.continuation;
+                    let marker = format!("{} ", continuation);
+                    self.write_styled_unchecked(&marker_style, &marker)?;
+                    self.block.column += 2;
+                    regions = tail;
+                }
+            }
+        }
+        self.write_code_regions(&regions)
+    }
+
+    /// Write highlighted `regions` of code as is.
+    ///
+    /// Write regions without a highlighting style in the current style.  Paint
+    /// the code background up to the end of the line, if enabled.
+    fn write_code_regions(&mut self, regions: &[(Option<SyntectStyle>, &str)]) -> io::Result<()> {
+        let background = self.code_background();
+        for &(style, text) in regions {
+            // Fill the background before the line break.
+            let (text, line_break) = match background {
+                Some(_) if text.ends_with('\n') => (&text[..text.len() - 1], true),
+                _ => (text, false),
+            };
+            let style = match (style, background) {
+                (Some(style), _) if self.settings.theme.is_some() => {
+                    highlighting::to_rgb(style, background)
+                }
+                (Some(style), _) => highlighting::to_ansi(style, background),
+                (None, Some(background)) => self.style.current.on(background),
+                (None, None) => self.style.current,
+            };
+            self.write_styled_unchecked(&style, text)?;
+            self.advance_column(text);
+            if line_break {
+                self.fill_code_background()?;
+                self.newline()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The background colour for code blocks.
+    ///
+    /// Return the background colour of the theme if settings enable code
+    /// backgrounds and the terminal supports 24-bit colours, and we are inside
+    /// a code block.
+    fn code_background(&self) -> Option<Color> {
+        let tier = self.style_capability().tier();
+        if self.settings.code_background
+            && self.block.inside_code_block
+            && StyleTier::TrueColor <= tier
+        {
+            self.theme()
+                .settings
+                .background
+                .map(|colour| RgbColor(colour.r, colour.g, colour.b).into())
+        } else {
+            None
+        }
+    }
+
+    /// Paint the code background up to the right edge of the layout.
+    ///
+    /// Do nothing if code backgrounds are disabled.
+    fn fill_code_background(&mut self) -> io::Result<()> {
+        if let Some(background) = self.code_background() {
+            let right_edge = self.layout.margin + self.layout.width;
+            let padding = " ".repeat(right_edge.saturating_sub(self.block.column));
+            self.write_styled_unchecked(&Style::new().on(background), &padding)?;
+            self.advance_column(&padding);
+        }
+        Ok(())
+    }
+
+    /// Write the image at `link` inline, with the given `size`.
+    ///
+    /// Return whether the terminal supports inline images and we could read
+    /// and render the image.  Never load images after the deadline.
+    fn write_inline_image(&mut self, link: &str, size: ImageSize) -> Result<bool, Box<dyn Error>> {
+        if self.deadline_passed() {
+            return Ok(false);
+        }
+        let settings = self.settings;
+        let base_dir = self.base_dir;
+        let http = self.http();
+        let url = self.resolve_reference(link).filter(|url| {
+            if !settings.resource_access.permits(url) {
+                self.warn(Warning::RemoteImageSkipped(url.clone()));
+                false
+            } else if !settings.local_file_access.permits(url, base_dir) {
+                self.warn(Warning::ImageOutsideBaseDir {
+                    url: url.clone(),
+                    base_dir: base_dir.to_path_buf(),
+                });
+                false
+            } else {
+                true
+            }
+        });
+        match (&settings.terminal_capabilities.image, url) {
+            (ImageCapability::Terminology(ref terminology), Some(ref url)) => {
+                // Fit the image into the content width
+                let size = TerminalSize {
+                    width: self.layout.width,
+                    ..settings.terminal_size
+                };
+                terminology.write_inline_image(&mut self.writer, size, url)?;
+                Ok(true)
+            }
+            (ImageCapability::ITerm2(ref iterm2), Some(ref url)) => {
+                let contents = settings.image_cache.iterm2(url, || {
+                    iterm2
+                        .read_and_render(
+                            url,
+                            settings.animations,
+                            settings.max_image_read_bytes,
+                            settings.max_image_pixels,
+                            &http,
+                        )
+                        .and_then(|contents| {
+                            convert::shrink_to_fit(contents, settings.max_inline_image_bytes)
+                        })
+                });
+                match contents {
+                    Ok(contents) => {
+                        iterm2.write_inline_image(self.writer, url.as_str(), &contents, size)?;
+                        Ok(true)
+                    }
+                    Err(error) => {
+                        self.warn(Warning::image_failed(url, error.as_ref()));
+                        Ok(false)
+                    }
+                }
+            }
+            (ImageCapability::Kitty(ref kitty), Some(ref url)) => {
+                let kitty_image = settings.image_cache.kitty(url, size, || {
+                    kitty.read_and_render(
+                        url,
+                        size,
+                        settings.max_image_read_bytes,
+                        settings.max_image_pixels,
+                        &http,
+                    )
+                });
+                match kitty_image {
+                    Ok(kitty_image) => {
+                        kitty.write_inline_image(self.writer, kitty_image)?;
+                        Ok(true)
+                    }
+                    Err(error) => {
+                        self.warn(Warning::image_failed(url, error.as_ref()));
+                        Ok(false)
+                    }
+                }
+            }
+            (_, None) | (ImageCapability::None, _) => Ok(false),
+        }
+    }
+
+    /// Pass `warning` to the warning handler of settings, if any.
+    fn warn(&self, warning: Warning) {
+        if let Some(ref handler) = self.settings.warnings {
+            handler.warn(&warning);
+        }
+    }
+
+    /// Write the `caption` of an image.
+    ///
+    /// Write the caption dimmed and centered on a line of its own, and number
+    /// it if enabled.  Afterwards continue at block level.
+    fn write_caption(&mut self, caption: &str) -> io::Result<()> {
+        self.image.figure_number += 1;
+        let caption = if self.settings.figure_numbers {
+            format!("Figure {}: {}", self.image.figure_number, caption)
+        } else {
+            caption.to_string()
+        };
+        self.newline()?;
+        let width = self.layout.width.saturating_sub(self.block.indent_level);
+        let padding = width.saturating_sub(bidi::text_width(&caption)) / 2;
+        self.indent_by(self.block.indent_level + padding)?;
+        let style = self.style.current.dimmed();
+        self.write_styled(&style, caption)?;
+        self.newline()?;
+        self.block.level = BlockLevel::Block;
+        Ok(())
+    }
+
+    /// Break the page before a level-1 heading, according to settings.
+    ///
+    /// Do nothing at the beginning of the document, i.e. if no block was
+    /// written yet.  A page break replaces the usual margin between blocks.
+    fn write_page_break(&mut self) -> io::Result<()> {
+        if self.block.level == BlockLevel::Block {
+            match self.settings.page_break {
+                PageBreak::None => return Ok(()),
+                PageBreak::FormFeed => write!(self.writer, "\x0c")?,
+                PageBreak::BlankLines(lines) => write!(self.writer, "{}", "\n".repeat(lines))?,
+            }
+            self.block.level = BlockLevel::Inline;
+        }
+        Ok(())
+    }
+
+    /// Set a mark on the current position of the terminal if supported,
+    /// otherwise do nothing.
+    fn set_mark_if_supported(&mut self) -> io::Result<()> {
+        match self.settings.terminal_capabilities.marks {
+            MarkCapability::ITerm2(ref marks) => marks.set_mark(self.writer),
+            MarkCapability::OSC133(ref marks) => marks.set_mark(self.writer),
+            MarkCapability::None => Ok(()),
+        }
+    }
+}
+
+/// The `title` of a link reference, with a leading space.
+///
+/// Return an empty string for empty titles, and if `link_titles` only shows
+/// titles as tooltips.
+fn reference_title(title: &str, link_titles: LinkTitles) -> String {
+    match link_titles {
+        _ if title.is_empty() => String::new(),
+        LinkTitles::Plain => format!(" {}", title),
+        LinkTitles::Quoted => format!(" \"{}\"", title),
+        LinkTitles::Tooltip => String::new(),
+    }
+}
+
+/// Ellipsize the middle of `text` to fit into `width` columns.
+///
+/// Replace the middle with `ellipsis`.  Never split graphemes, and measure
+/// their display width, so wide characters count as two columns.
+fn ellipsize_middle(text: &str, width: usize, ellipsis: char) -> String {
+    if bidi::text_width(text) <= width {
+        text.to_string()
+    } else {
+        let keep = width.saturating_sub(UnicodeWidthChar::width(ellipsis).unwrap_or(1));
+        let tail_width = keep / 2;
+        let head_width = keep - tail_width;
+        let mut head = String::new();
+        let mut used = 0;
+        for grapheme in text.graphemes(true) {
+            used += bidi::text_width(grapheme);
+            if head_width < used {
+                break;
+            }
+            head.push_str(grapheme);
+        }
+        let mut tail = Vec::new();
+        let mut used = 0;
+        for grapheme in text.graphemes(true).rev() {
+            used += bidi::text_width(grapheme);
+            if tail_width < used {
+                break;
+            }
+            tail.push(grapheme);
+        }
+        head.push(ellipsis);
+        head.extend(tail.into_iter().rev());
+        head
+    }
+}
+
+/// Hard-wrap `text` into lines of `width` columns.
+///
+/// Wrap between any graphemes, regardless of word boundaries, and measure
+/// their display width.  Make continuation lines `indent` columns shorter than
+/// the first line.
+fn hard_wrap(text: &str, width: usize, indent: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = 0;
+    let mut line_width = width.max(1);
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = bidi::text_width(grapheme);
+        if !line.is_empty() && line_width < used + grapheme_width {
+            lines.push(std::mem::take(&mut line));
+            used = 0;
+            line_width = width.saturating_sub(indent).max(1);
+        }
+        line.push_str(grapheme);
+        used += grapheme_width;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The marker of the footnote with the given `number` in the given style.
+fn footnote_marker(marker: FootnoteMarker, number: usize) -> String {
+    match marker {
+        FootnoteMarker::Superscript => number
+            .to_string()
+            .chars()
+            .filter_map(scripts::superscript)
+            .collect(),
+        FootnoteMarker::Bracketed => format!("[^{}]", number),
+        FootnoteMarker::Asterisks => "*".repeat(number),
+    }
+}
+
+/// Push the words of `text` with `style` to `segments`.
+///
+/// Turn whitespace into spaces between words, to wrap at words later.
+fn push_words(segments: &mut Vec<LineSegment>, style: Style, text: &str) {
+    for (index, word) in text.split(char::is_whitespace).enumerate() {
+        if 0 < index {
+            segments.push(LineSegment::Space(style));
+        }
+        if !word.is_empty() {
+            segments.push(LineSegment::Text(style, word.to_string()));
+        }
+    }
+}
+
+/// The number of columns `segments` occupy.
+fn segments_width(segments: &[LineSegment]) -> usize {
+    segments.iter().map(LineSegment::width).sum()
+}
+
+/// Split the segments of a table `cell` into words.
+///
+/// Return every word along with the style of the space before it, if any.
+/// Break around wide characters, e.g. CJK ideographs, even without spaces,
+/// so that text without spaces wraps as well; words after such breaks and the
+/// first word have no space before them.  Keep escapes with the word they
+/// precede or follow, and drop leading, trailing and repeated spaces.
+fn cell_words(cell: &[LineSegment]) -> Vec<(Option<Style>, Cell)> {
+    let mut words = Vec::new();
+    let mut word = Vec::new();
+    let mut space = None;
+    for segment in cell {
+        match segment {
+            LineSegment::Space(style) => {
+                finish_word(&mut words, &mut word, &mut space, true);
+                space = Some(*style);
+            }
+            LineSegment::Text(style, text) => {
+                let mut narrow = String::new();
+                for grapheme in text.graphemes(true) {
+                    if bidi::text_width(grapheme) < 2 {
+                        narrow.push_str(grapheme);
+                        continue;
+                    }
+                    if !narrow.is_empty() {
+                        word.push(LineSegment::Text(*style, std::mem::take(&mut narrow)));
+                    }
+                    finish_word(&mut words, &mut word, &mut space, false);
+                    word.push(LineSegment::Text(*style, grapheme.to_string()));
+                    finish_word(&mut words, &mut word, &mut space, false);
+                }
+                if !narrow.is_empty() {
+                    word.push(LineSegment::Text(*style, narrow));
+                }
+            }
+            LineSegment::Escapes(_) => word.push(segment.clone()),
+        }
+    }
+    finish_word(&mut words, &mut word, &mut space, true);
+    if !word.is_empty() {
+        // A cell with escapes only
+        words.push((None, word));
+    }
+    words
+}
+
+/// Move the current `word` of a cell to `words`, after the given `space`.
+///
+/// Keep a word without text, i.e. with escapes only, for the next word, or
+/// append it to the previous word if it is `trailing`.
+fn finish_word(
+    words: &mut Vec<(Option<Style>, Cell)>,
+    word: &mut Cell,
+    space: &mut Option<Style>,
+    trailing: bool,
+) {
+    if segments_width(word) == 0 {
+        if let (true, Some((_, previous))) = (trailing, words.last_mut()) {
+            previous.append(word);
+        }
+    } else {
+        let space = space.take().filter(|_| !words.is_empty());
+        words.push((space, std::mem::take(word)));
+    }
+}
+
+/// Wrap a table `cell` at words into lines of at most `width` columns.
+///
+/// Do not break words, so lines with words longer than `width` exceed
+/// `width`.  Always return at least one line.
+fn wrap_cell(cell: &[LineSegment], width: usize) -> Vec<Cell> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let mut line_width = 0;
+    for (index, (space, mut word)) in cell_words(cell).into_iter().enumerate() {
+        let word_width = segments_width(&word);
+        let space_width = if space.is_some() { 1 } else { 0 };
+        if 0 < index && width < line_width + space_width + word_width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        } else if let Some(style) = space {
+            line.push(LineSegment::Space(style));
+            line_width += 1;
+        }
+        line.append(&mut word);
+        line_width += word_width;
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Balance the widths of table columns to fit into `budget` columns.
+///
+/// Use the `natural` widths of all columns if they fit.  Otherwise give each
+/// column its `minimal` width and distribute the remaining columns in
+/// proportion to how much wider each column would like to be.
+///
+/// Return `None` if not even the minimal widths fit.
+fn column_widths(natural: &[usize], minimal: &[usize], budget: usize) -> Option<Vec<usize>> {
+    if natural.iter().sum::<usize>() <= budget {
+        return Some(natural.to_vec());
+    }
+    let required = minimal.iter().sum::<usize>();
+    if budget < required {
+        return None;
+    }
+    let spare = budget - required;
+    let wanted: usize = natural.iter().zip(minimal).map(|(n, m)| n - m).sum();
+    let mut widths: Vec<usize> = natural
+        .iter()
+        .zip(minimal)
+        .map(|(n, m)| m + (n - m) * spare / wanted)
+        .collect();
+    // Give what's left after rounding down to the leftmost columns
+    let mut left = budget - widths.iter().sum::<usize>();
+    for (width, natural) in widths.iter_mut().zip(natural) {
+        if 0 < left && *width < *natural {
+            *width += 1;
+            left -= 1;
+        }
+    }
+    Some(widths)
+}
+
+/// Format `time` as UTC date and time, e.g. `2020-04-11 09:30:00 UTC`.
+fn format_utc(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Convert days since the epoch to a civil date, see
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Normalize CRLF line endings in `text` to LF.
+fn normalize_line_endings(text: CowStr<'_>) -> CowStr<'_> {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").into()
+    } else {
+        text
+    }
+}
+
+/// Expand tabs in `text` to spaces, with tab stops every `tab_width` columns.
+///
+/// Assume that `text` starts at the beginning of a line.  Leave tabs alone if
+/// `tab_width` is 0.
+fn expand_tabs(text: CowStr<'_>, tab_width: usize) -> CowStr<'_> {
+    if tab_width == 0 || !text.contains('\t') {
+        text
+    } else {
+        let mut expanded = String::with_capacity(text.len());
+        let mut column = 0;
+        for c in text.chars() {
+            match c {
+                '\t' => {
+                    let spaces = tab_width - column % tab_width;
+                    expanded.push_str(&" ".repeat(spaces));
+                    column += spaces;
+                }
+                '\n' => {
+                    expanded.push(c);
+                    column = 0;
+                }
+                _ => {
+                    expanded.push(c);
+                    column += 1;
+                }
+            }
+        }
+        expanded.into()
+    }
+}
+
+/// The number of columns of highlighted `regions`, excluding line breaks.
+fn regions_width<S>(regions: &[(S, &str)]) -> usize {
+    regions
+        .iter()
+        .map(|(_, text)| text.chars().filter(|c| *c != '\n').count())
+        .sum()
+}
+
+/// A region of text with a style.
+type Region<'t, S> = (S, &'t str);
+
+/// Split highlighted `regions` after `width` columns.
+fn split_regions<'t, S: Copy>(
+    regions: &[Region<'t, S>],
+    width: usize,
+) -> (Vec<Region<'t, S>>, Vec<Region<'t, S>>) {
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    let mut remaining = width;
+    for &(style, text) in regions {
+        let length = text.chars().count();
+        if remaining == 0 {
+            tail.push((style, text));
+        } else if length <= remaining {
+            head.push((style, text));
+            remaining -= length;
+        } else {
+            let (index, _) = text.char_indices().nth(remaining).unwrap();
+            head.push((style, &text[..index]));
+            tail.push((style, &text[index..]));
+            remaining = 0;
+        }
+    }
+    (head, tail)
+}
+
+/// Remove indentation common to all non-blank lines of `text`.
+fn dedent(text: &str) -> String {
+    fn indentation(line: &str) -> usize {
+        line.len() - line.trim_start_matches(&[' ', '\t'][..]).len()
+    }
+
+    let common = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(indentation)
+        .min()
+        .unwrap_or(0);
+    LinesWithEndings::from(text)
+        .map(|line| &line[indentation(line).min(common)..])
+        .collect()
+}
+
+/// Write a single `event` in the given context.
+///
+/// Match all events and tags exhaustively and without wildcards, here and in
+/// [`start_tag`] and [`end_tag`], so that events and tags of future
+/// pulldown-cmark versions fail to compile instead of silently falling into
+/// some default.  Handle constructs mdcat does not support explicitly, e.g.
+/// by writing their text as plain text.
 pub fn write_event<'a, 'b, W: Write>(
     mut ctx: Context<'a, 'b, W>,
     event: Event<'b>,
 ) -> Result<Context<'a, 'b, W>, Box<dyn Error>> {
+    if ctx.skip_nested(&event) {
+        return Ok(ctx);
+    }
+    if ctx.block.citation.is_some() {
+        // Write the pending citation as normal text unless the paragraph ends
+        // after the citation.
+        match &event {
+            Text(_) | Start(Emphasis) | End(Emphasis) | Start(Strong) | End(Strong) => {}
+            End(Paragraph) => ctx.write_citation()?,
+            _ => ctx.write_pending_citation()?,
+        }
+    }
+    let after_soft_break = std::mem::replace(&mut ctx.block.after_soft_break, false);
+    if let Some(ref mut heading_text) = ctx.links.heading_text {
+        match event {
+            Text(ref text) | Code(ref text) => heading_text.push_str(text),
+            _ => {}
+        }
+    }
+    if let Some(ref mut fragment_text) = ctx.links.fragment_text {
+        match event {
+            Text(ref text) | Code(ref text) => fragment_text.push_str(text),
+            _ => {}
+        }
+    }
+    if let Some(ref mut banner) = ctx.block.banner {
+        // Collect the text of banner headings, and write the banner at the
+        // end of the heading
+        match event {
+            Text(ref text) | Code(ref text) => {
+                banner.push_str(text);
+                return Ok(ctx);
+            }
+            End(Heading(1)) => {
+                let text = std::mem::take(banner);
+                ctx.block.banner = None;
+                ctx.write_banner(&text)?;
+            }
+            _ => return Ok(ctx),
+        }
+    }
+    if ctx.table.cell.is_some() {
+        // Buffer table cells with styles and links of their inline markup, and
+        // ignore all other markup
+        match event {
+            Text(text) => {
+                let style = ctx.style.current;
+                ctx.write_styled(&style, text)?;
+            }
+            Code(code) => {
+                let style = ctx.style.current.fg(ctx.colours().code);
+                ctx.write_styled(&style, code)?;
+            }
+            SoftBreak | HardBreak => {
+                let style = ctx.style.current;
+                ctx.write_styled(&style, " ")?;
+            }
+            FootnoteReference(label) => {
+                ctx.write_footnote_marker(&label)?;
+            }
+            Start(tag @ Emphasis)
+            | Start(tag @ Strong)
+            | Start(tag @ Strikethrough)
+            | Start(tag @ Link(..)) => return start_tag(ctx, tag),
+            End(tag @ Emphasis)
+            | End(tag @ Strong)
+            | End(tag @ Strikethrough)
+            | End(tag @ Link(..)) => return end_tag(ctx, tag),
+            End(TableCell) => {
+                let cell = ctx.table.cell.take().unwrap_or_default();
+                ctx.table.row.push(cell);
+                ctx.drop_style();
+            }
+            _ => {}
+        }
+        return Ok(ctx);
+    }
     match event {
+        SoftBreak if ctx.layout.wrap => {
+            // Refill paragraphs if we wrap text anyway
+            ctx.write_wrapped(" ")?;
+            ctx.block.after_soft_break = true;
+            Ok(ctx)
+        }
         SoftBreak | HardBreak => {
             ctx.newline_and_indent()?;
             Ok(ctx)
         }
         Rule => {
             ctx.start_inline_text()?;
-            let rule = "\u{2550}".repeat(ctx.settings.terminal_size.width as usize);
-            let style = ctx.style.current.fg(Colour::Green);
-            ctx.write_styled(&style, rule)?;
+            let rule_style = &ctx.settings.style_sheet.rule;
+            let symbol_width = UnicodeWidthStr::width(rule_style.symbol.as_str()).max(1);
+            let count = match rule_style.width {
+                Some(width) => width.min(ctx.layout.width / symbol_width),
+                None => ctx.layout.width / symbol_width,
+            };
+            if rule_style.centered {
+                ctx.indent_by((ctx.layout.width - count * symbol_width) / 2)?;
+            }
+            ctx.write_styled(&rule_style.style, rule_style.symbol.repeat(count))?;
             ctx.end_inline_text_with_margin()?;
             Ok(ctx)
         }
         Code(code) => {
             // Inline code
-            ctx.write_styled(&ctx.style.current.fg(Colour::Yellow), code)?;
+            ctx.write_styled(&ctx.style.current.fg(ctx.colours().code), code)?;
             Ok(ctx)
         }
         Text(text) => {
             // When we wrote an inline image suppress the text output, ie, the
             // image title.  We do not need it if we can show the image on the
             // terminal.
-            if !ctx.image.inline_image {
-                ctx.write_highlighted(text)?;
+            if ctx.image.inline_image {
+                // Skip the image title
+            } else if ctx.code.raw {
+                ctx.write_escapes(|writer| writer.write_all(text.as_bytes()))?;
+                ctx.block.column = 0;
+            } else if ctx.block.inside_code_block {
+                if std::mem::replace(&mut ctx.code.detect_syntax, false) {
+                    ctx.detect_syntax(&text);
+                }
+                let text = expand_tabs(normalize_line_endings(text), ctx.settings.tab_width);
+                match ctx.code.buffer {
+                    Some(ref mut buffer) => buffer.push_str(&text),
+                    None => ctx.write_highlighted(text)?,
+                }
+            } else if let Some(ref mut citation) = ctx.block.citation {
+                citation.push_str(&text);
+            } else if ctx.is_citation_start(&text, after_soft_break) {
+                ctx.block.citation = Some(text.into_string());
+            } else {
+                ctx.write_text(&text)?;
             }
             Ok(ctx)
         }
         TaskListMarker(checked) => {
-            let marker = if checked { "\u{2611} " } else { "\u{2610} " };
-            ctx.write_highlighted(CowStr::Borrowed(marker))?;
+            let symbols = &ctx.settings.style_sheet.symbols;
+            let marker = match (checked, ctx.settings.copy_mode) {
+                (true, false) => symbols.checked.to_string(),
+                (false, false) => symbols.unchecked.to_string(),
+                (true, true) => "[x]".to_string(),
+                (false, true) => "[ ]".to_string(),
+            };
+            ctx.write_highlighted(CowStr::from(format!("{} ", marker)))?;
             Ok(ctx)
         }
         Start(tag) => start_tag(ctx, tag),
         End(tag) => end_tag(ctx, tag),
         Html(content) => {
-            ctx.write_styled(&ctx.style.current.fg(Colour::Green), content)?;
+            if ctx.block.inside_html_comment || html::starts_comment(&content) {
+                ctx.block.inside_html_comment = !content.contains("-->");
+                if ctx.settings.show_html_comments {
+                    ctx.write_styled(&ctx.style.current.dimmed(), content)?;
+                }
+                return Ok(ctx);
+            }
+            // Show HTML images with their size if the terminal supports images
+            let written = match html::parse_img_tag(&content) {
+                Some(tag) => {
+                    ctx.flush_line(false)?;
+                    ctx.write_inline_image(tag.src, tag.size)?
+                }
+                None => false,
+            };
+            if !written {
+                ctx.write_styled(&ctx.style.current.fg(ctx.colours().html), content)?;
+            }
+            Ok(ctx)
+        }
+        FootnoteReference(label) => {
+            ctx.write_footnote_marker(&label)?;
             Ok(ctx)
         }
-        FootnoteReference(_) => panic!("mdcat does not support footnotes"),
     }
 }
 
@@ -390,37 +2309,126 @@ fn start_tag<'a, 'b, W: Write>(
     mut ctx: Context<'a, 'b, W>,
     tag: Tag<'b>,
 ) -> Result<Context<'a, 'b, W>, Box<dyn Error>> {
+    if ctx.settings.debug_layout {
+        let block = match tag {
+            Paragraph => Some("paragraph"),
+            Heading(_) => Some("heading"),
+            BlockQuote => Some("block quote"),
+            CodeBlock(_) => Some("code block"),
+            Item => Some("item"),
+            Table(_) => Some("table"),
+            FootnoteDefinition(_) => Some("footnote"),
+            List(_) | TableHead | TableRow | TableCell | Emphasis | Strong | Strikethrough
+            | Link(..) | Image(..) => None,
+        };
+        if let Some(block) = block {
+            ctx.write_layout_annotation(block)?;
+        }
+    }
     match tag {
-        Paragraph => ctx.start_inline_text()?,
+        Paragraph => {
+            ctx.start_inline_text()?;
+            let paragraph = ctx.settings.style_sheet.paragraph;
+            match paragraph.indent {
+                ParagraphIndent::None => {}
+                ParagraphIndent::FirstLine(columns) => ctx.indent_by(columns)?,
+                ParagraphIndent::Hanging(columns) => ctx.block.indent_level += columns,
+            }
+            if ctx.layout.wrap && paragraph.alignment == TextAlignment::Justify {
+                ctx.block.line = Some(Vec::new());
+            }
+        }
         Heading(level) => {
             // Before we start a new header, write all pending links to keep
-            // them close to the text where they appeared in
-            ctx.write_pending_links()?;
+            // them close to the text where they appeared in, unless settings
+            // collect them at the end of the document
+            if ctx.settings.reference_flush != ReferenceFlushPolicy::EndOfDocument {
+                ctx.write_pending_links()?;
+            }
+            // Only restart numbering if we wrote all references, to avoid
+            // duplicate numbers in the pending references
+            if level <= 2
+                && ctx.settings.reference_numbering == ReferenceNumbering::Section
+                && ctx.links.pending_links.is_empty()
+            {
+                ctx.links.next_link_index = 1;
+            }
+            if level == 1 {
+                ctx.write_page_break()?;
+            }
+            let heading = *ctx.settings.style_sheet.heading(level);
+            ctx.block.indent_level += heading.indent;
             ctx.start_inline_text()?;
+            if ctx.block.column == 0 && 0 < heading.indent {
+                // At the start of the document we did not write a line break
+                // and thus no indentation yet.
+                ctx.indent()?;
+            }
             ctx.set_mark_if_supported()?;
-            ctx.set_style(Style::new().fg(Colour::Blue).bold());
-            ctx.write_styled_current("\u{2504}".repeat(level as usize))?
+            if level == 1 && ctx.settings.banner_headings {
+                ctx.block.banner = Some(String::new());
+            } else if level == 1 && ctx.settings.double_height_headings {
+                if let StyleCapability::Ansi(_) = ctx.style_capability() {
+                    ctx.block.line = Some(Vec::new());
+                    ctx.block.double_height = true;
+                }
+            }
+            ctx.set_style(heading.style);
+            ctx.style.uppercase = heading.uppercase;
+            if ctx.settings.permalink_base.is_some() {
+                ctx.links.heading_text = Some(String::new());
+            }
+            if heading.adornment && !ctx.settings.copy_mode && ctx.block.banner.is_none() {
+                let adornment = ctx.settings.style_sheet.symbols.heading.to_string();
+                ctx.write_styled_current(adornment.repeat(level.min(6) as usize))?
+            }
         }
         BlockQuote => {
             ctx.block.indent_level += 4;
+            ctx.block.quote_level += 1;
+            ctx.start_inline_text()?;
+            let quotes = ctx.colours().quotes;
+            if ctx.block.quote_level == 1 {
+                // Make emphasis style and add the colour of quotes.
+                ctx.enable_emphasis();
+                ctx.style.current = ctx.style.current.fg(quotes[0]);
+            } else {
+                // Alternate colours of nested quotes, to tell them apart
+                let colour = quotes[(ctx.block.quote_level + 1) % 2];
+                ctx.set_style(ctx.style.current.fg(colour));
+            }
+        }
+        CodeBlock(CodeBlockKind::Fenced(ref name))
+            if name.as_ref() == "raw"
+                && ctx.settings.raw_blocks
+                && matches!(ctx.style_capability(), StyleCapability::Ansi(_)) =>
+        {
             ctx.start_inline_text()?;
-            // Make emphasis style and add green colour.
-            ctx.enable_emphasis();
-            ctx.style.current = ctx.style.current.fg(Colour::Green);
+            ctx.code.raw = true;
         }
         CodeBlock(kind) => {
             ctx.start_inline_text()?;
-            ctx.write_border()?;
-            // Try to get a highlighter for the current code.
-            ctx.current_highlighter = match kind {
-                CodeBlockKind::Indented => None,
-                CodeBlockKind::Fenced(name) if name.is_empty() => None,
-                CodeBlockKind::Fenced(name) => ctx
-                    .settings
-                    .syntax_set
-                    .find_syntax_by_token(&name)
-                    .map(|syntax| HighlightLines::new(syntax, ctx.theme)),
+            ctx.write_code_border()?;
+            ctx.block.inside_code_block = true;
+            if ctx.settings.dedent_code {
+                ctx.code.buffer = Some(String::new());
+            }
+            let has_token = matches!(kind, CodeBlockKind::Fenced(ref name) if !name.is_empty());
+            let is_indented = matches!(kind, CodeBlockKind::Indented);
+            // Try to get a highlighter for the current code, and fall back to
+            // the default language for code without a language.
+            let token = match kind {
+                CodeBlockKind::Fenced(ref name) if !name.is_empty() => Some(name.as_ref()),
+                _ => ctx.settings.default_code_language.as_deref(),
             };
+            ctx.current_highlighter = token
+                .and_then(|token| syntax::find_syntax_by_token(&ctx.settings.syntax_set, token))
+                .map(|syntax| HighlightLines::new(syntax, ctx.theme()));
+            // If we do not know the language of the code block, try to guess
+            // it from the first line of code, e.g. a shebang.  Indented code
+            // blocks have no language at all, so only guess if enabled.
+            ctx.code.detect_syntax = ctx.current_highlighter.is_none()
+                && (has_token || (ctx.settings.sniff_code && is_indented));
             if ctx.current_highlighter.is_none() {
                 // If we found no highlighter (code block had no language or
                 // a language synctex doesn't support) we set a style to
@@ -429,7 +2437,7 @@ fn start_tag<'a, 'b, W: Write>(
                 // If we have a highlighter we set no style at all because
                 // we pass the entire block contents through the highlighter
                 // and directly write the result as ANSI.
-                let style = ctx.style.current.fg(Colour::Yellow);
+                let style = ctx.style.current.fg(ctx.colours().code);
                 ctx.set_style(style);
             }
         }
@@ -444,25 +2452,52 @@ fn start_tag<'a, 'b, W: Write>(
             ctx.indent()?;
             ctx.block.level = BlockLevel::Inline;
             match ctx.list_item_kind.pop() {
-                Some(ListItemKind::Unordered) => {
-                    write!(ctx.writer, "\u{2022} ")?;
+                // Write a bullet for items outside of lists, too
+                Some(ListItemKind::Unordered) | None => {
+                    let bullet = if ctx.settings.copy_mode {
+                        '-'
+                    } else {
+                        ctx.settings.style_sheet.symbols.bullet
+                    };
+                    ctx.write_plain(format!("{} ", bullet))?;
                     ctx.block.indent_level += 2;
                     ctx.list_item_kind.push(ListItemKind::Unordered);
                 }
                 Some(ListItemKind::Ordered(number)) => {
-                    write!(ctx.writer, "{:>2}. ", number)?;
+                    ctx.write_plain(format!("{:>2}. ", number))?;
                     ctx.block.indent_level += 4;
-                    ctx.list_item_kind.push(ListItemKind::Ordered(number + 1));
+                    ctx.list_item_kind
+                        .push(ListItemKind::Ordered(number.saturating_add(1)));
                 }
-                None => panic!("List item without list item kind"),
             }
         }
-        FootnoteDefinition(_) => panic!("mdcat does not support footnotes"),
-        Table(_) | TableHead | TableRow | TableCell => panic!("mdcat does not support tables"),
+        FootnoteDefinition(label) => {
+            ctx.start_inline_text()?;
+            ctx.set_mark_if_supported()?;
+            // Indent the definition after the marker
+            let width = ctx.write_footnote_marker(&label)?;
+            ctx.write_plain(" ")?;
+            ctx.block.indent_level += width + 1;
+        }
+        Table(alignments) => ctx.table.alignments = alignments,
+        TableHead => ctx.table.inside_head = true,
+        TableRow => {}
+        TableCell => {
+            // Take the style of the header or the current stripe for all
+            // inline markup of the cell
+            let table_style = ctx.settings.style_sheet.table;
+            let style = match table_style.stripe {
+                _ if ctx.table.inside_head => table_style.header,
+                Some(stripe) if ctx.table.rows.len() % 2 == 1 => stripe,
+                _ => ctx.style.current,
+            };
+            ctx.set_style(style);
+            ctx.table.cell = Some(Vec::new());
+        }
         Strikethrough => ctx.set_style(ctx.style.current.strikethrough()),
         Emphasis => ctx.enable_emphasis(),
         Strong => ctx.set_style(ctx.style.current.bold()),
-        Link(link_type, destination, _) => {
+        Link(link_type, destination, title) => {
             ctx.links.current_link_type = Some(link_type);
             // Do nothing if the terminal doesn’t support inline links of if `destination` is no
             // valid URL:  We will write a reference link when closing the link tag.
@@ -470,40 +2505,45 @@ fn start_tag<'a, 'b, W: Write>(
                 LinkCapability::OSC8(ref osc8) => {
                     // TODO: check link type (first tuple element) to write proper mailto link for
                     // emails
-                    if let Some(url) = ctx.resolve_reference(&destination) {
-                        osc8.set_link_url(ctx.writer, url)?;
+                    if let Some(url) = ctx.resolve_link(&destination) {
+                        let tooltip = ctx.settings.link_titles == LinkTitles::Tooltip;
+                        if tooltip && !title.is_empty() {
+                            ctx.write_escapes(|writer| {
+                                osc8.set_link_url_with_title(writer, url, &title)
+                            })?;
+                        } else {
+                            ctx.write_escapes(|writer| osc8.set_link_url(writer, url))?;
+                        }
                         ctx.links.inside_inline_link = true;
+                        if StyleTier::Full <= ctx.style_capability().tier() {
+                            // Underline inline links in blue where the terminal
+                            // supports underline colours
+                            let style = ctx.style.current.underline();
+                            ctx.set_style(style.underline_colour(ctx.colours().link));
+                        }
                     }
                 }
                 LinkCapability::None => {}
             }
+            if !ctx.links.inside_inline_link && ctx.fragment_heading(&destination).is_some() {
+                // Collect the text of links to headings of this document, to
+                // refer to the heading in text instead of writing a reference.
+                ctx.links.fragment_text = Some(String::new());
+            }
         }
         Image(_, link, _title) => {
-            let url = ctx
-                .resolve_reference(&link)
-                .filter(|url| ctx.settings.resource_access.permits(url));
-            match (&ctx.settings.terminal_capabilities.image, url) {
-                (ImageCapability::Terminology(ref terminology), Some(ref url)) => {
-                    terminology.write_inline_image(
-                        &mut ctx.writer,
-                        ctx.settings.terminal_size,
-                        url,
-                    )?; /*  */
-                    ctx.image.inline_image = true;
-                }
-                (ImageCapability::ITerm2(ref iterm2), Some(ref url)) => {
-                    if let Ok(contents) = iterm2.read_and_render(url) {
-                        iterm2.write_inline_image(ctx.writer, url.as_str(), &contents)?;
-                        ctx.image.inline_image = true;
-                    }
-                }
-                (ImageCapability::Kitty(ref kitty), Some(ref url)) => {
-                    if let Ok(kitty_image) = kitty.read_and_render(url) {
-                        kitty.write_inline_image(ctx.writer, kitty_image)?;
-                        ctx.image.inline_image = true;
+            // Images don't fit into justified lines
+            ctx.flush_line(false)?;
+            ctx.image.inline_image = ctx.write_inline_image(&link, ImageSize::default())?;
+            if !ctx.image.inline_image && !ctx.links.inside_inline_link {
+                // If we cannot show the image, link its text to the image, so
+                // that users can click through to it
+                if let LinkCapability::OSC8(ref osc8) = ctx.settings.terminal_capabilities.links {
+                    if let Some(url) = ctx.resolve_reference(&link) {
+                        ctx.write_escapes(|writer| osc8.set_link_url(writer, url))?;
+                        ctx.image.linked_text = true;
                     }
                 }
-                (_, None) | (ImageCapability::None, _) => {}
             }
         }
     };
@@ -516,19 +2556,42 @@ fn end_tag<'a, 'b, W: Write>(
     tag: Tag<'b>,
 ) -> Result<Context<'a, 'b, W>, Box<dyn Error>> {
     match tag {
-        Paragraph => ctx.end_inline_text_with_margin()?,
-        Heading(_) => {
+        Paragraph => {
+            if let ParagraphIndent::Hanging(columns) = ctx.settings.style_sheet.paragraph.indent {
+                ctx.reduce_indent(columns);
+            }
+            ctx.end_inline_text_with_margin()?;
+            ctx.block.line = None;
+            ctx.write_excess_links()?;
+        }
+        Heading(level) => {
+            ctx.reduce_indent(ctx.settings.style_sheet.heading(level).indent);
+            ctx.write_permalink()?;
             ctx.drop_style();
-            ctx.end_inline_text_with_margin()?
+            ctx.style.uppercase = false;
+            ctx.end_inline_text_with_margin()?;
+            ctx.block.line = None;
+            ctx.block.double_height = false;
         }
         BlockQuote => {
-            ctx.block.indent_level -= 4;
-            // Drop emphasis and current style
-            ctx.style.emphasis_level -= 1;
+            ctx.reduce_indent(4);
+            if ctx.block.quote_level == 1 {
+                // Drop emphasis and current style
+                ctx.style.emphasis_level = ctx.style.emphasis_level.saturating_sub(1);
+            }
             ctx.drop_style();
+            ctx.block.quote_level = ctx.block.quote_level.saturating_sub(1);
             ctx.end_inline_text_with_margin()?
         }
+        CodeBlock(_) if ctx.code.raw => {
+            // Raw blocks end with a line break, and act as a block of their own
+            ctx.code.raw = false;
+            ctx.block.level = BlockLevel::Block;
+        }
         CodeBlock(_) => {
+            if let Some(buffer) = ctx.code.buffer.take() {
+                ctx.write_highlighted(dedent(&buffer).into())?;
+            }
             match ctx.current_highlighter {
                 None => ctx.drop_style(),
                 Some(_) => {
@@ -538,6 +2601,7 @@ fn end_tag<'a, 'b, W: Write>(
                     ctx.current_highlighter = None;
                 }
             }
+            ctx.block.inside_code_block = false;
             ctx.write_border()?;
             // Move back to block context, but do not add a dedicated margin
             // because the bottom border we printed above already acts as
@@ -552,28 +2616,59 @@ fn end_tag<'a, 'b, W: Write>(
         Item => {
             // Reset indent level according to list item kind
             match ctx.list_item_kind.last() {
-                Some(&ListItemKind::Ordered(_)) => ctx.block.indent_level -= 4,
-                Some(&ListItemKind::Unordered) => ctx.block.indent_level -= 2,
+                Some(&ListItemKind::Ordered(_)) => ctx.reduce_indent(4),
+                Some(&ListItemKind::Unordered) => ctx.reduce_indent(2),
                 None => (),
             }
-            ctx.end_inline_text_with_margin()?
+            ctx.end_inline_text_with_margin()?;
+            ctx.write_excess_links()?;
+        }
+        FootnoteDefinition(label) => {
+            let number = ctx.footnote_number(&label);
+            let marker = footnote_marker(ctx.settings.footnote_marker, number);
+            ctx.reduce_indent(marker.chars().count() + 1);
+        }
+        TableCell => {}
+        TableHead => {
+            ctx.table.inside_head = false;
+            ctx.table.head = std::mem::take(&mut ctx.table.row);
         }
-        FootnoteDefinition(_) | Table(_) | TableHead | TableRow | TableCell => {}
+        TableRow => {
+            let row = std::mem::take(&mut ctx.table.row);
+            ctx.table.rows.push(row);
+        }
+        Table(_) => ctx.write_table()?,
         Strikethrough => ctx.drop_style(),
         Emphasis => {
             ctx.drop_style();
-            ctx.style.emphasis_level -= 1;
+            ctx.style.emphasis_level = ctx.style.emphasis_level.saturating_sub(1);
         }
         Strong => ctx.drop_style(),
         Link(_, destination, title) => {
             if ctx.links.inside_inline_link {
                 match ctx.settings.terminal_capabilities.links {
                     LinkCapability::OSC8(ref osc8) => {
-                        osc8.clear_link(ctx.writer)?;
+                        ctx.write_escapes(|writer| osc8.clear_link(writer))?;
                     }
                     LinkCapability::None => {}
                 }
+                if StyleTier::Full <= ctx.style_capability().tier() {
+                    ctx.drop_style();
+                }
                 ctx.links.inside_inline_link = false;
+                ctx.record_link(LinkKind::Link, &destination, LinkPlacement::Inline);
+            } else if let Some(text) = ctx.links.fragment_text.take() {
+                // Refer to headings of this document by their text, unless the
+                // link text already is the text of the heading, e.g. in a table
+                // of contents.
+                let heading = ctx
+                    .fragment_heading(&destination)
+                    .map(|heading| heading.text.clone());
+                if let Some(heading) = heading.filter(|heading| *heading != text) {
+                    let style = ctx.style.current.dimmed();
+                    ctx.write_styled(&style, format!(" (\u{a7} {})", heading))?;
+                }
+                ctx.record_link(LinkKind::Link, &destination, LinkPlacement::Text);
             } else {
                 // When we did not write an inline link, create a normal reference
                 // link instead.  Even if the terminal supports inline links this
@@ -582,25 +2677,987 @@ fn end_tag<'a, 'b, W: Write>(
                     Some(LinkType::Autolink) | Some(LinkType::Email) => {
                         // Do nothing for autolinks: We shouldn't repeat the link destination,
                         // if the link text _is_ the destination.
+                        ctx.record_link(LinkKind::Link, &destination, LinkPlacement::Text);
                     }
                     _ => {
                         // Reference link
+                        let index = ctx.links.next_link_index;
+                        let placement = LinkPlacement::Reference(index);
+                        ctx.record_link(LinkKind::Link, &destination, placement);
                         let index = ctx.add_link(destination, title);
-                        let style = ctx.style.current.fg(Colour::Blue);
+                        let style = ctx.style.current.fg(ctx.colours().link);
                         ctx.write_styled(&style, format!("[{}]", index))?
                     }
                 }
             }
         }
-        Image(_, link, _) => {
-            if !ctx.image.inline_image {
+        Image(_, link, title) => {
+            if ctx.image.inline_image {
+                ctx.record_link(LinkKind::Image, &link, LinkPlacement::Inline);
+            } else {
                 // If we could not write an inline image, write the image link
                 // after the image title.
-                let style = ctx.style.current.fg(Colour::Blue);
-                ctx.write_styled(&style, format!(" ({})", link))?
+                let style = ctx.style.current.fg(ctx.colours().link);
+                ctx.write_styled(&style, format!(" ({})", link))?;
+                match ctx.settings.terminal_capabilities.links {
+                    LinkCapability::OSC8(ref osc8) if ctx.image.linked_text => {
+                        ctx.write_escapes(|writer| osc8.clear_link(writer))?;
+                        ctx.record_link(LinkKind::Image, &link, LinkPlacement::Inline);
+                    }
+                    _ => ctx.record_link(LinkKind::Image, &link, LinkPlacement::Text),
+                }
             }
             ctx.image.inline_image = false;
+            ctx.image.linked_text = false;
+            if !title.is_empty() {
+                ctx.write_caption(&title)?;
+            }
         }
     };
     Ok(ctx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{plain_settings, render_with, styled};
+    use crate::{HeadingStyle, ParagraphStyle, RuleStyle, StyleSheet, Symbols, TableStyle};
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::CowStr;
+
+    #[test]
+    fn ellipsize_middle() {
+        assert_eq!(
+            super::ellipsize_middle("abcdefghij", 10, '\u{2026}'),
+            "abcdefghij"
+        );
+        assert_eq!(
+            super::ellipsize_middle("abcdefghij", 5, '\u{2026}'),
+            "ab\u{2026}ij"
+        );
+        assert_eq!(
+            super::ellipsize_middle("abcdefghij", 6, '\u{2026}'),
+            "abc\u{2026}ij"
+        );
+        assert_eq!(
+            super::ellipsize_middle("abcdefghij", 0, '\u{2026}'),
+            "\u{2026}"
+        );
+        // Wide characters take two columns each
+        assert_eq!(
+            super::ellipsize_middle(
+                "\u{65e5}\u{672c}\u{8a9e}\u{306e}\u{6587}\u{5b57}",
+                7,
+                '\u{2026}'
+            ),
+            "\u{65e5}\u{2026}\u{5b57}"
+        );
+    }
+
+    #[test]
+    fn format_utc() {
+        use std::time::{Duration, SystemTime};
+        let format =
+            |seconds| super::format_utc(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(format(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format(1_586_597_400), "2020-04-11 09:30:00 UTC");
+    }
+
+    #[test]
+    fn normalize_line_endings() {
+        let normalize = |text| super::normalize_line_endings(CowStr::Borrowed(text)).into_string();
+        assert_eq!(normalize("foo\nbar"), "foo\nbar");
+        assert_eq!(normalize("foo\r\nbar\r\n"), "foo\nbar\n");
+        assert_eq!(normalize("foo\rbar"), "foo\rbar");
+    }
+
+    #[test]
+    fn expand_tabs() {
+        let expand = |text| super::expand_tabs(CowStr::Borrowed(text), 4).into_string();
+        assert_eq!(expand("foo"), "foo");
+        assert_eq!(expand("\tfoo"), "    foo");
+        assert_eq!(expand("ab\tc\td"), "ab  c   d");
+        assert_eq!(expand("a\tb\n\tc"), "a   b\n    c");
+        assert_eq!(
+            super::expand_tabs(CowStr::Borrowed("\tfoo"), 0).into_string(),
+            "\tfoo"
+        );
+    }
+
+    #[test]
+    fn split_regions() {
+        let regions = [(1, "foo"), (2, "bar"), (3, "\n")];
+        assert_eq!(super::regions_width(&regions), 6);
+        assert_eq!(
+            super::split_regions(&regions, 4),
+            (vec![(1, "foo"), (2, "b")], vec![(2, "ar"), (3, "\n")])
+        );
+        assert_eq!(
+            super::split_regions(&regions, 3),
+            (vec![(1, "foo")], vec![(2, "bar"), (3, "\n")])
+        );
+    }
+
+    #[test]
+    fn dedent() {
+        assert_eq!(super::dedent("foo\n  bar\n"), "foo\n  bar\n");
+        assert_eq!(
+            super::dedent("    foo\n      bar\n\n  \n    baz\n"),
+            "foo\n  bar\n\n\nbaz\n"
+        );
+        assert_eq!(super::dedent("\tfoo\n\t\tbar"), "foo\n\tbar");
+    }
+
+    #[test]
+    fn footnote_marker() {
+        use crate::FootnoteMarker::*;
+        assert_eq!(
+            super::footnote_marker(Superscript, 1024),
+            "\u{b9}\u{2070}\u{b2}\u{2074}"
+        );
+        assert_eq!(super::footnote_marker(Bracketed, 12), "[^12]");
+        assert_eq!(super::footnote_marker(Asterisks, 3), "***");
+    }
+
+    #[test]
+    fn wrap_cell() {
+        use super::{LineSegment, Style};
+        let wrap = |text, width| {
+            let mut cell = Vec::new();
+            super::push_words(&mut cell, Style::new(), text);
+            super::wrap_cell(&cell, width)
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .map(|segment| match segment {
+                            LineSegment::Text(_, text) => text.as_str(),
+                            LineSegment::Space(_) => " ",
+                            LineSegment::Escapes(_) => "<esc>",
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(wrap("foo bar baz", 7), vec!["foo bar", "baz"]);
+        assert_eq!(wrap("foo  bar", 3), vec!["foo", "bar"]);
+        assert_eq!(wrap(" foo bar ", 7), vec!["foo bar"]);
+        assert_eq!(wrap("foobar", 3), vec!["foobar"]);
+        assert_eq!(
+            wrap("\u{6f22}\u{5b57}\u{304b}\u{306a}", 4),
+            vec!["\u{6f22}\u{5b57}", "\u{304b}\u{306a}"]
+        );
+        assert_eq!(
+            wrap("a \u{6f22}\u{5b57}b", 3),
+            vec!["a", "\u{6f22}", "\u{5b57}b"]
+        );
+        assert_eq!(wrap("", 3), vec![""]);
+    }
+
+    #[test]
+    fn column_widths() {
+        assert_eq!(super::column_widths(&[3, 5], &[3, 2], 10), Some(vec![3, 5]));
+        assert_eq!(
+            super::column_widths(&[20, 10, 4], &[4, 4, 4], 20),
+            Some(vec![10, 6, 4])
+        );
+        assert_eq!(super::column_widths(&[20, 10], &[8, 8], 15), None);
+    }
+
+    #[test]
+    fn hard_wrap() {
+        assert_eq!(super::hard_wrap("abcde", 5, 2), vec!["abcde"]);
+        assert_eq!(
+            super::hard_wrap("abcdefghij", 5, 2),
+            vec!["abcde", "fgh", "ij"]
+        );
+        assert!(super::hard_wrap("", 5, 2).is_empty());
+        // Wide characters take two columns each
+        assert_eq!(
+            super::hard_wrap("\u{65e5}\u{672c}\u{8a9e}\u{306e}\u{6587}\u{5b57}", 5, 1),
+            vec!["\u{65e5}\u{672c}", "\u{8a9e}\u{306e}", "\u{6587}\u{5b57}"]
+        );
+    }
+
+    #[test]
+    fn page_break_before_level_1_headings() {
+        let settings = |page_break| Settings {
+            page_break,
+            ..plain_settings()
+        };
+        let render =
+            |page_break| render_with(&settings(page_break), "# Foo\n\nbar\n\n## Baz\n\n# Qux");
+        assert_eq!(
+            render(PageBreak::None),
+            "\u{2504}Foo\n\nbar\n\n\u{2504}\u{2504}Baz\n\n\u{2504}Qux\n"
+        );
+        assert_eq!(
+            render(PageBreak::FormFeed),
+            "\u{2504}Foo\n\nbar\n\n\u{2504}\u{2504}Baz\n\x0c\u{2504}Qux\n"
+        );
+        assert_eq!(
+            render(PageBreak::BlankLines(3)),
+            "\u{2504}Foo\n\nbar\n\n\u{2504}\u{2504}Baz\n\n\n\n\u{2504}Qux\n"
+        );
+    }
+
+    #[test]
+    fn margin_and_content_width_center_and_wrap_text() {
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                width: 30,
+                height: 24,
+            },
+            margin: 2,
+            content_width: Some(10),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "lorem ipsum\ndolor sit amet");
+        let margin = " ".repeat(11);
+        assert_eq!(
+            result,
+            format!("{0}lorem\n{0}ipsum\n{0}dolor sit\n{0}amet\n", margin)
+        );
+    }
+
+    #[test]
+    fn isolate_right_to_left_text() {
+        let shalom = "\u{5e9}\u{5dc}\u{5d5}\u{5dd}";
+        let olam = "\u{5e2}\u{5d5}\u{5dc}\u{5dd}";
+        let markdown = format!("ab {} {}", shalom, olam);
+        let mut settings = Settings {
+            terminal_size: TerminalSize {
+                width: 10,
+                height: 24,
+            },
+            content_width: Some(10),
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, &markdown),
+            format!("ab {}\n{}\n", shalom, olam)
+        );
+        settings.bidi_isolates = true;
+        assert_eq!(
+            render_with(&settings, &markdown),
+            format!("\u{2068}ab {}\u{2069}\n\u{2068}{}\u{2069}\n", shalom, olam)
+        );
+    }
+
+    #[test]
+    fn justify_and_indent_paragraphs() {
+        let render = |alignment, indent| {
+            let settings = Settings {
+                terminal_size: TerminalSize {
+                    width: 6,
+                    height: 24,
+                },
+                content_width: Some(6),
+                style_sheet: StyleSheet {
+                    paragraph: ParagraphStyle { alignment, indent },
+                    ..StyleSheet::default()
+                },
+                ..plain_settings()
+            };
+            render_with(&settings, "aa b cc dd")
+        };
+        assert_eq!(
+            render(TextAlignment::Left, ParagraphIndent::None),
+            "aa b\ncc dd\n"
+        );
+        assert_eq!(
+            render(TextAlignment::Justify, ParagraphIndent::None),
+            "aa   b\ncc dd\n"
+        );
+        assert_eq!(
+            render(TextAlignment::Left, ParagraphIndent::FirstLine(2)),
+            "  aa b\ncc dd\n"
+        );
+        assert_eq!(
+            render(TextAlignment::Justify, ParagraphIndent::Hanging(2)),
+            "aa   b\n  cc\n  dd\n"
+        );
+    }
+
+    #[test]
+    fn wrap_long_link_references() {
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                width: 20,
+                height: 24,
+            },
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "[foo](http://example.com/a/long/path)");
+        assert_eq!(
+            result,
+            "foo[1]\n\n[1]: http://example.\n     com/a/long/path\n"
+        );
+    }
+
+    #[test]
+    fn limit_pending_links() {
+        let markdown = "[a](http://a.example)\n\n[b](http://b.example)\n";
+        let settings = Settings {
+            max_pending_links: Some(1),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        let first_link = result.find("[1]: http://a.example").unwrap();
+        assert!(first_link < result.find("b[2]").unwrap(), "{:?}", result);
+        assert!(result.contains("[2]: http://b.example"), "{:?}", result);
+    }
+
+    #[test]
+    fn number_references_per_section() {
+        let markdown = "# A\n\n[a](http://a.example)\n\n## B\n\n[b](http://b.example)\n\n\
+                        ### C\n\n[c](http://c.example)\n";
+        let mut settings = plain_settings();
+        let result = render_with(&settings, markdown);
+        assert!(result.contains("[2]: http://b.example"), "{:?}", result);
+        assert!(result.contains("[3]: http://c.example"), "{:?}", result);
+
+        settings.reference_numbering = ReferenceNumbering::Section;
+        let result = render_with(&settings, markdown);
+        assert!(result.contains("[1]: http://a.example"), "{:?}", result);
+        assert!(result.contains("[1]: http://b.example"), "{:?}", result);
+        assert!(result.contains("[2]: http://c.example"), "{:?}", result);
+    }
+
+    #[test]
+    fn flush_references() {
+        let markdown =
+            "[a](http://a.example)\n\n[b](http://b.example)\n\n# C\n\n[c](http://c.example)\n";
+        let render = |reference_flush| {
+            let settings = Settings {
+                reference_flush,
+                ..plain_settings()
+            };
+            render_with(&settings, markdown)
+        };
+        let result = render(ReferenceFlushPolicy::PerSection);
+        let position = |text| result.find(text).unwrap();
+        assert!(position("b[2]") < position("[1]: http://a"), "{:?}", result);
+        assert!(position("[2]: http://b") < position("C"), "{:?}", result);
+
+        let result = render(ReferenceFlushPolicy::EndOfDocument);
+        let position = |text| result.find(text).unwrap();
+        assert!(position("c[3]") < position("[1]: http://a"), "{:?}", result);
+
+        let result = render(ReferenceFlushPolicy::AfterParagraph);
+        let position = |text| result.find(text).unwrap();
+        assert!(position("[1]: http://a") < position("b[2]"), "{:?}", result);
+    }
+
+    #[test]
+    fn write_link_titles() {
+        let markdown = "[a](http://a.example \"Title\") [b](http://b.example)\n";
+        let render = |link_titles| {
+            let settings = Settings {
+                link_titles,
+                ..plain_settings()
+            };
+            render_with(&settings, markdown)
+        };
+        assert_eq!(
+            render(LinkTitles::Plain),
+            "a[1] b[2]\n\n[1]: http://a.example Title\n[2]: http://b.example\n"
+        );
+        assert_eq!(
+            render(LinkTitles::Quoted),
+            "a[1] b[2]\n\n[1]: http://a.example \"Title\"\n[2]: http://b.example\n"
+        );
+        assert_eq!(
+            render(LinkTitles::Tooltip),
+            "a[1] b[2]\n\n[1]: http://a.example\n[2]: http://b.example\n"
+        );
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            link_titles: LinkTitles::Tooltip,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(
+            result.starts_with("\x1b]8;title=Title;http://a.example/\x07a"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn link_text_of_images_not_shown() {
+        let markdown = "![alt](http://example.com/x.png)\n";
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert_eq!(
+            result,
+            "\x1b]8;;http://example.com/x.png\x07alt (http://example.com/x.png)\x1b]8;;\x07\n"
+        );
+    }
+
+    #[test]
+    fn mark_footnotes_and_references() {
+        let markdown = "Foo[^a] [b](http://b.example)\n\n[^a]: Bar\n";
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::none()
+                .with_marks(MarkCapability::OSC133(OSC133Marks)),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(result.contains("\x1b]133;A\x07\u{b9} Bar"), "{:?}", result);
+        assert!(
+            result.contains("\x1b]133;A\x07[1]: http://b.example"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn html_comments() {
+        let render = |markdown, show_html_comments| {
+            let settings = Settings {
+                show_html_comments,
+                ..plain_settings()
+            };
+            render_with(&settings, markdown)
+        };
+        assert_eq!(render("a <!-- b --> c\n", false), "a  c\n");
+        assert_eq!(render("a <!-- b --> c\n", true), "a <!-- b --> c\n");
+        assert_eq!(render("<!--\nfoo\n-->\n\nbar\n", false), "bar\n");
+    }
+
+    #[test]
+    fn limit_nesting_depth() {
+        let markdown = "> a\n>\n> > b\n> > * c\n>\n> d\n\n* e\n  * f\n";
+        let settings = Settings {
+            max_nesting_depth: Some(1),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        for text in &["a", "d", "e"] {
+            assert!(result.contains(text), "{:?}", result);
+        }
+        for text in &["b", "c", "f"] {
+            assert!(!result.contains(text), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn wrap_and_truncate_long_code_lines() {
+        let render = |code_overflow| {
+            let settings = Settings {
+                terminal_size: TerminalSize {
+                    width: 8,
+                    height: 24,
+                },
+                code_overflow,
+                ..plain_settings()
+            };
+            render_with(&settings, "    0123456789\n    ab\n")
+        };
+        let border = "\u{2500}".repeat(8);
+        assert_eq!(
+            render(CodeOverflow::Overflow),
+            format!("{0}\n0123456789\nab\n{0}\n", border)
+        );
+        assert_eq!(
+            render(CodeOverflow::Wrap),
+            format!("{0}\n01234567\n\u{21aa} 89\nab\n{0}\n", border)
+        );
+        assert_eq!(
+            render(CodeOverflow::Truncate),
+            format!("{0}\n0123456\u{2026}\nab\n{0}\n", border)
+        );
+    }
+
+    #[test]
+    fn image_captions() {
+        let render = |figure_numbers| {
+            let settings = Settings {
+                terminal_size: TerminalSize {
+                    width: 20,
+                    height: 24,
+                },
+                figure_numbers,
+                ..plain_settings()
+            };
+            let markdown = "![a](b.png \"foo\")\n\nbar";
+            render_with(&settings, markdown)
+        };
+        assert_eq!(render(false), "a (b.png)\n        foo\n\nbar\n");
+        assert_eq!(render(true), "a (b.png)\n   Figure 1: foo\n\nbar\n");
+    }
+
+    #[test]
+    fn double_height_headings() {
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            double_height_headings: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "# Foo\n\n## Bar");
+        let lines: Vec<&str> = result.lines().collect();
+        assert!(lines[0].starts_with("\x1b#3"), "{:?}", lines[0]);
+        assert!(lines[0].contains("Foo"), "{:?}", lines[0]);
+        assert!(lines[1].starts_with("\x1b#4"), "{:?}", lines[1]);
+        assert!(lines[1].contains("Foo"), "{:?}", lines[1]);
+        assert_eq!(lines[2], "");
+        assert!(!lines[3].contains("\x1b#"), "{:?}", lines[3]);
+    }
+
+    #[test]
+    fn wrap_double_height_headings_at_half_width() {
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            terminal_size: TerminalSize {
+                width: 20,
+                height: 24,
+            },
+            double_height_headings: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "# Lorem ipsum dolor sit amet");
+        let lines: Vec<&str> = result.lines().collect();
+        let halves = ["\x1b#3", "\x1b#4"];
+        for (line, half) in lines.iter().zip(halves.iter().cycle()) {
+            assert!(line.starts_with(half), "{:?}", line);
+            // Count all characters outside of SGR escape sequences
+            let mut width = 0;
+            let mut chars = line.trim_start_matches(half).chars();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' {
+                    chars.by_ref().find(|&c| c == 'm');
+                } else {
+                    width += 1;
+                }
+            }
+            assert!(width <= 10, "{:?} wider than 10 columns", line);
+        }
+        assert!(4 <= lines.len(), "{:?}", lines);
+    }
+
+    #[test]
+    fn paint_code_background_across_content_width() {
+        use anstyle::{AnsiColor, RgbColor};
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::TrueColor)),
+                ..TerminalCapabilities::ansi()
+            },
+            terminal_size: TerminalSize {
+                width: 4,
+                height: 24,
+            },
+            code_background: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "    ab\n");
+        // Solarized (dark) background
+        let background = RgbColor(0x00, 0x2b, 0x36);
+        let line = format!(
+            "{}{}\n",
+            styled(Style::new().fg(AnsiColor::Yellow).on(background), "ab"),
+            styled(Style::new().on(background), "  ")
+        );
+        assert!(result.contains(&line), "{:?} not in {:?}", line, result);
+    }
+
+    #[test]
+    fn style_headings_per_level() {
+        use anstyle::AnsiColor;
+        let mut style_sheet = StyleSheet::default();
+        style_sheet.headings[0] = HeadingStyle {
+            style: Style::new().fg(AnsiColor::Red),
+            uppercase: true,
+            ..HeadingStyle::default()
+        };
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            style_sheet,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "# Foo\n\n## Bar");
+        let level_1 = styled(Style::new().fg(AnsiColor::Red), "FOO");
+        assert!(
+            result.contains(&level_1),
+            "{:?} not in {:?}",
+            level_1,
+            result
+        );
+        let level_2 = styled(HeadingStyle::default().style, "Bar");
+        assert!(
+            result.contains(&level_2),
+            "{:?} not in {:?}",
+            level_2,
+            result
+        );
+    }
+
+    #[test]
+    fn hide_heading_adornments() {
+        let mut style_sheet = StyleSheet::default();
+        for heading in style_sheet.headings.iter_mut() {
+            heading.adornment = false;
+        }
+        style_sheet.headings[1].indent = 2;
+        let settings = Settings {
+            style_sheet,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "## Foo\n\n# Bar\n\n## Baz");
+        assert_eq!(result, "  Foo\n\nBar\n\n  Baz\n");
+    }
+
+    #[test]
+    fn rule_styles() {
+        let render = |rule: RuleStyle| {
+            let settings = Settings {
+                terminal_size: TerminalSize {
+                    width: 10,
+                    height: 24,
+                },
+                style_sheet: StyleSheet {
+                    rule,
+                    ..StyleSheet::default()
+                },
+                ..plain_settings()
+            };
+            render_with(&settings, "---")
+        };
+        assert_eq!(
+            render(RuleStyle::default()),
+            format!("{}\n", "\u{2550}".repeat(10))
+        );
+        let asterism = RuleStyle {
+            symbol: "\u{2042}".to_string(),
+            width: Some(1),
+            centered: true,
+            ..RuleStyle::default()
+        };
+        assert_eq!(render(asterism), "    \u{2042}\n");
+        let short = RuleStyle {
+            symbol: "-".to_string(),
+            width: Some(3),
+            ..RuleStyle::default()
+        };
+        assert_eq!(render(short), "---\n");
+        let wide = RuleStyle {
+            symbol: "\u{6f22}".to_string(),
+            ..RuleStyle::default()
+        };
+        assert_eq!(render(wide), format!("{}\n", "\u{6f22}".repeat(5)));
+        let combined = RuleStyle {
+            symbol: "e\u{301}".to_string(),
+            ..RuleStyle::default()
+        };
+        assert_eq!(render(combined), format!("{}\n", "e\u{301}".repeat(10)));
+    }
+
+    #[test]
+    fn render_footnotes() {
+        let markdown = "Foo[^a] bar[^b]\n\n[^b]: Bar\n\n[^a]: Foo\n";
+        assert_eq!(
+            render_with(&plain_settings(), markdown),
+            "Foo\u{b9} bar\u{b2}\n\n\u{b2} Bar\n\n\u{b9} Foo\n"
+        );
+        let settings = Settings {
+            footnote_marker: FootnoteMarker::Bracketed,
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, markdown),
+            "Foo[^1] bar[^2]\n\n[^2] Bar\n\n[^1] Foo\n"
+        );
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            footnote_marker: FootnoteMarker::Asterisks,
+            ..plain_settings()
+        };
+        // Terminals cannot jump to an anchor in rendered text, so don't link
+        // markers
+        assert_eq!(
+            render_with(&settings, markdown),
+            "Foo* bar**\n\n** Bar\n\n* Foo\n"
+        );
+    }
+
+    #[test]
+    fn render_tables() {
+        let markdown = "| a | bb | c |\n|:--|:-:|--:|\n| 1 | 2 | 333 |\n| 4 | 5 | 6 |\n";
+        assert_eq!(
+            render_with(&plain_settings(), markdown),
+            "┌───┬────┬─────┐\n\
+             │ a │ bb │   c │\n\
+             ├───┼────┼─────┤\n\
+             │ 1 │ 2  │ 333 │\n\
+             │ 4 │ 5  │   6 │\n\
+             └───┴────┴─────┘\n"
+        );
+
+        let stripe = Style::new().on(anstyle::AnsiColor::Black);
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            style_sheet: StyleSheet {
+                table: TableStyle {
+                    stripe: Some(stripe),
+                    ..TableStyle::default()
+                },
+                ..StyleSheet::default()
+            },
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        let header = styled(Style::new().bold(), "bb");
+        assert!(result.contains(&header), "{:?} not in {:?}", header, result);
+        let striped = styled(stripe, "6");
+        assert!(
+            result.contains(&striped),
+            "{:?} not in {:?}",
+            striped,
+            result
+        );
+        let plain = styled(stripe, "333");
+        assert!(!result.contains(&plain), "{:?} in {:?}", plain, result);
+    }
+
+    #[test]
+    fn keep_links_in_table_cells() {
+        let markdown = "| a |\n|---|\n| [x y](http://x.example) |\n";
+        let result = render_with(&plain_settings(), markdown);
+        assert!(result.contains("\n│ x y[1] │\n"), "{:?}", result);
+        assert!(result.ends_with("[1]: http://x.example\n"), "{:?}", result);
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        let link = "\n│ \x1b]8;;http://x.example/\x07x y\x1b]8;;\x07 │\n";
+        assert!(result.contains(link), "{:?}", result);
+    }
+
+    #[test]
+    fn wrap_links_with_wide_text_in_table_cells() {
+        let markdown =
+            "| a | b |\n|---|---|\n| x | [\u{6f22}\u{5b57}\u{304b}\u{306a}](http://x.example) |\n";
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            terminal_size: TerminalSize {
+                width: 12,
+                height: 24,
+            },
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, markdown),
+            "┌───┬──────┐\n\
+             │ a │ b    │\n\
+             ├───┼──────┤\n\
+             │ x │ \x1b]8;;http://x.example/\x07\u{6f22}\u{5b57} │\n\
+             │   │ \u{304b}\u{306a}\x1b]8;;\x07 │\n\
+             └───┴──────┘\n"
+        );
+    }
+
+    #[test]
+    fn render_narrow_tables_as_records() {
+        let markdown = "| name | value |\n|---|---|\n| a | 1 |\n| b | 2 |\n";
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                width: 10,
+                height: 24,
+            },
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, markdown),
+            "name: a\nvalue: 1\n──────────\nname: b\nvalue: 2\n"
+        );
+    }
+
+    #[test]
+    fn wrap_table_cells() {
+        let markdown = "| a | b |\n|---|---|\n| x | lorem ipsum dolor |\n";
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                width: 20,
+                height: 24,
+            },
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, markdown),
+            "┌───┬──────────────┐\n\
+             │ a │ b            │\n\
+             ├───┼──────────────┤\n\
+             │ x │ lorem ipsum  │\n\
+             │   │ dolor        │\n\
+             └───┴──────────────┘\n"
+        );
+    }
+
+    #[test]
+    fn replace_symbols() {
+        let settings = Settings {
+            style_sheet: StyleSheet {
+                symbols: Symbols {
+                    heading: '#',
+                    bullet: '*',
+                    horizontal: '-',
+                    vertical: '|',
+                    junctions: ['+'; 9],
+                    ..Symbols::default()
+                },
+                ..StyleSheet::default()
+            },
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, "# Foo\n\nBar\n\n- a\n- b\n"),
+            "#Foo\n\nBar\n\n* a\n* b\n"
+        );
+        assert_eq!(
+            render_with(&settings, "| a | b |\n|---|---|\n| 1 | 2 |\n"),
+            "+---+---+\n| a | b |\n+---+---+\n| 1 | 2 |\n+---+---+\n"
+        );
+    }
+
+    #[test]
+    fn nested_block_quotes() {
+        use anstyle::AnsiColor;
+        let markdown = "x\n\n> a\n>\n>> b\n>\n> c\n";
+        assert_eq!(
+            render_with(&plain_settings(), markdown),
+            "x\n\n    a\n\n        b\n\n    c\n"
+        );
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        let outer = styled(Style::new().fg(AnsiColor::Green).italic(), "c");
+        assert!(result.contains(&outer), "{:?} not in {:?}", outer, result);
+        let inner = styled(Style::new().fg(AnsiColor::Cyan).italic(), "b");
+        assert!(result.contains(&inner), "{:?} not in {:?}", inner, result);
+    }
+
+    #[test]
+    fn align_citations_in_block_quotes() {
+        let render = |markdown, content_width| {
+            let settings = Settings {
+                terminal_size: TerminalSize {
+                    width: 20,
+                    height: 24,
+                },
+                content_width,
+                ..plain_settings()
+            };
+            render_with(&settings, markdown)
+        };
+        let quote = "x\n\n> Lorem ipsum\n> \u{2014} Cicero\n";
+        let expected = "x\n\n    Lorem ipsum\n            \u{2014} Cicero\n";
+        assert_eq!(render(quote, None), expected);
+        assert_eq!(render(quote, Some(20)), expected);
+        assert_eq!(
+            render("x\n\n> Lorem ipsum\n> \u{2014} \u{5b54}\u{5b50}\n", None),
+            "x\n\n    Lorem ipsum\n              \u{2014} \u{5b54}\u{5b50}\n"
+        );
+        assert_eq!(
+            render("x\n\n> -- no citation\n> more\n", None),
+            "x\n\n    -- no citation\n    more\n"
+        );
+    }
+
+    #[test]
+    fn number_snippets() {
+        let settings = Settings {
+            number_snippets: true,
+            ..plain_settings()
+        };
+        let markdown = "```\nfoo\n```\n\n    bar\n";
+        let result = render_with(&settings, markdown);
+        let border = "\u{2500}".repeat(20);
+        assert!(
+            result.contains(&format!("{} [snippet 1]\nfoo\n{}", border, border)),
+            "{:?}",
+            result
+        );
+        assert!(
+            result.contains(&format!("{} [snippet 2]\nbar\n{}", border, border)),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn copy_mode_omits_decorations() {
+        let markdown = "# Title\n\n* [x] Done\n* Item\n\n```\ncode\n```\n";
+        let settings = Settings {
+            copy_mode: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(result.contains("- [x] Done\n"), "{:?}", result);
+        assert!(result.contains("- Item\n"), "{:?}", result);
+        assert!(result.contains("\ncode\n"), "{:?}", result);
+        for symbol in &['\u{2504}', '\u{2500}', '\u{2022}', '\u{2611}'] {
+            assert!(!result.contains(*symbol), "{:?} in {:?}", symbol, result);
+        }
+    }
+
+    #[test]
+    fn write_raw_blocks_as_is() {
+        let markdown = "```raw\n\x1b[5mBlink\x1b[0m\n```\n\nText\n";
+        let mut settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            raw_blocks: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(
+            result.starts_with("\x1b[5mBlink\x1b[0m\n\n"),
+            "{:?}",
+            result
+        );
+        assert!(!result.contains('\u{2500}'), "{:?}", result);
+
+        // Render raw blocks as code if the terminal has no styles
+        settings.terminal_capabilities = TerminalCapabilities::none();
+        let result = render_with(&settings, markdown);
+        assert!(result.starts_with('\u{2500}'), "{:?}", result);
+    }
+
+    #[test]
+    fn banner_headings() {
+        let settings = Settings {
+            banner_headings: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "# Hi\n\nText\n\n# Grüße\n");
+        assert_eq!(
+            result,
+            "#   # ###\n#   #  #\n#####  #\n#   #  #\n#   # ###\n\nText\n\nGrüße\n"
+        );
+    }
+}
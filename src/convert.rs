@@ -0,0 +1,154 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Convert images to formats terminals understand.
+
+use image::GenericImageView;
+use mime::Mime;
+use std::error::Error;
+use std::io::Cursor;
+
+/// The maximum width and height of converted images, in pixels.
+const MAX_DIMENSION: u32 = 2048;
+
+/// Whether terminals show images of the given MIME type as they are.
+///
+/// All terminals with inline images understand PNG, JPEG and GIF images.
+pub fn is_common_format(mime: &Mime) -> bool {
+    *mime == mime::IMAGE_PNG || *mime == mime::IMAGE_JPEG || *mime == mime::IMAGE_GIF
+}
+
+/// Convert the image in `contents` to PNG.
+///
+/// Decode any format the `image` crate understands, and encode the first frame
+/// of the image as PNG.  Scale down images larger than `MAX_DIMENSION` in
+/// either direction, preserving the aspect ratio.
+pub fn convert_to_png(contents: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image = image::load_from_memory(contents)?;
+    let (width, height) = image.dimensions();
+    let image = if MAX_DIMENSION < width || MAX_DIMENSION < height {
+        image.thumbnail(MAX_DIMENSION, MAX_DIMENSION)
+    } else {
+        image
+    };
+    let mut png = Vec::new();
+    image.write_to(&mut png, image::ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/// Fail if the image in `contents` has more than `max_pixels`.
+///
+/// Read only the header of the image for its dimensions, to check the size of
+/// the image before decoding it.  Do nothing if `max_pixels` is `None`.
+pub fn check_pixels(contents: &[u8], max_pixels: Option<u64>) -> Result<(), Box<dyn Error>> {
+    if let Some(max_pixels) = max_pixels {
+        let (width, height) = image::io::Reader::new(Cursor::new(contents))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        let pixels = u64::from(width) * u64::from(height);
+        if max_pixels < pixels {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Image of {}x{} pixels exceeds maximum of {} pixels",
+                    width, height, max_pixels
+                ),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The length of `length` bytes encoded as base64.
+fn base64_length(length: usize) -> usize {
+    (length + 2) / 3 * 4
+}
+
+/// Shrink the image in `contents` until its base64 encoding fits `max_bytes`.
+///
+/// Return `contents` as is if the base64 encoding is small enough already.
+/// Otherwise halve the dimensions of the image until its PNG encoding is small
+/// enough, and fail if the image cannot get any smaller.
+pub fn shrink_to_fit(contents: Vec<u8>, max_bytes: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    if base64_length(contents.len()) <= max_bytes {
+        return Ok(contents);
+    }
+    let mut image = image::load_from_memory(&contents)?;
+    loop {
+        let (width, height) = image.dimensions();
+        if width <= 1 && height <= 1 {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Image does not fit into {} bytes", max_bytes),
+            )));
+        }
+        image = image.thumbnail((width / 2).max(1), (height / 2).max(1));
+        let mut png = Vec::new();
+        image.write_to(&mut png, image::ImageOutputFormat::Png)?;
+        if base64_length(png.len()) <= max_bytes {
+            return Ok(png);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn common_formats() {
+        assert!(is_common_format(&mime::IMAGE_PNG));
+        assert!(is_common_format(&mime::IMAGE_GIF));
+        assert!(!is_common_format(&mime::IMAGE_BMP));
+    }
+
+    #[test]
+    fn base64_length() {
+        assert_eq!(super::base64_length(0), 0);
+        assert_eq!(super::base64_length(1), 4);
+        assert_eq!(super::base64_length(3), 4);
+        assert_eq!(super::base64_length(4), 8);
+    }
+
+    #[test]
+    fn check_pixels() {
+        let data = include_bytes!("../sample/rust-logo-128x128.png");
+        assert!(super::check_pixels(data, None).is_ok());
+        assert!(super::check_pixels(data, Some(128 * 128)).is_ok());
+        assert!(super::check_pixels(data, Some(128 * 128 - 1)).is_err());
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let data = include_bytes!("../sample/rust-logo-128x128.png");
+        let unchanged = super::shrink_to_fit(data.to_vec(), data.len() * 2).unwrap();
+        assert_eq!(unchanged, data.to_vec());
+        let shrunk = super::shrink_to_fit(data.to_vec(), data.len()).unwrap();
+        assert!(super::base64_length(shrunk.len()) <= data.len());
+        let (width, _) = image::load_from_memory(&shrunk).unwrap().dimensions();
+        assert!(width < 128);
+    }
+
+    #[test]
+    fn convert_png_to_png() {
+        let data = include_bytes!("../sample/rust-logo-128x128.png");
+        let result = convert_to_png(data);
+        assert!(result.is_ok(), "Unexpected error: {:?}", result);
+        let png = result.unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.dimensions(), (128, 128));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors with categories and exit codes.
+
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// The category of an error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading input or writing output failed.
+    Io,
+    /// Rendering a document failed.
+    Render,
+    /// Access to a file or resource was denied.
+    ResourceDenied,
+    /// A document has broken links.
+    BrokenLinks,
+    /// Rendering was cancelled.
+    Cancelled,
+}
+
+impl ErrorKind {
+    /// The exit code for errors of this kind.
+    ///
+    /// These exit codes are stable; scripts can rely on them.  mdcat itself
+    /// exits with 0 on success, and with 1 for invalid arguments.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Io => 2,
+            ErrorKind::Render => 3,
+            ErrorKind::ResourceDenied => 4,
+            ErrorKind::BrokenLinks => 5,
+            ErrorKind::Cancelled => 6,
+        }
+    }
+}
+
+/// An error of mdcat, with its kind.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    source: Box<dyn std::error::Error>,
+}
+
+impl Error {
+    /// Create a new error of the given `kind`, caused by `source`.
+    pub fn new<E: Into<Box<dyn std::error::Error>>>(kind: ErrorKind, source: E) -> Error {
+        Error {
+            kind,
+            source: source.into(),
+        }
+    }
+
+    /// The kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The exit code for this error.
+    ///
+    /// See [`ErrorKind::exit_code`].
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    /// Categorize any error.
+    ///
+    /// Take the kind of mdcat errors as is, categorize IO errors by their
+    /// kind, and take all other errors as render errors.
+    fn from(source: Box<dyn std::error::Error>) -> Error {
+        match source.downcast::<Error>() {
+            Ok(error) => *error,
+            Err(source) => {
+                let kind = match source.downcast_ref::<io::Error>() {
+                    Some(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+                        ErrorKind::ResourceDenied
+                    }
+                    Some(_) => ErrorKind::Io,
+                    None => ErrorKind::Render,
+                };
+                Error { kind, source }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn categorize<E: Into<Box<dyn std::error::Error>>>(error: E) -> ErrorKind {
+        Error::from(error.into()).kind()
+    }
+
+    #[test]
+    fn categorize_errors() {
+        let io_error = |kind| io::Error::new(kind, "foo");
+        assert_eq!(categorize(io_error(io::ErrorKind::NotFound)), ErrorKind::Io);
+        assert_eq!(
+            categorize(io_error(io::ErrorKind::PermissionDenied)),
+            ErrorKind::ResourceDenied
+        );
+        assert_eq!(categorize("foo"), ErrorKind::Render);
+        let error = Error::new(ErrorKind::BrokenLinks, "foo");
+        assert_eq!(categorize(error), ErrorKind::BrokenLinks);
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [
+            ErrorKind::Io.exit_code(),
+            ErrorKind::Render.exit_code(),
+            ErrorKind::ResourceDenied.exit_code(),
+            ErrorKind::BrokenLinks.exit_code(),
+            ErrorKind::Cancelled.exit_code(),
+        ];
+        for (index, code) in codes.iter().enumerate() {
+            assert!(1 < *code);
+            assert!(!codes[index + 1..].contains(code));
+        }
+    }
+}
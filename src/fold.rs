@@ -0,0 +1,120 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fold sections of documents.
+
+use pulldown_cmark::{Event, Tag};
+
+/// Fold sections of a document.
+///
+/// Drop the contents of every section whose heading is in `folded`, i.e. all
+/// events up to the next heading of the same or a higher level, and end the
+/// heading with an ellipsis to mark the folded section.  Count headings in
+/// order of appearance, from 0.
+///
+/// Use this function to collapse and expand sections of long documents,
+/// e.g. in a viewer, and render the result with [`crate::push_tty`].
+pub fn fold_sections<'a, I>(events: I, folded: &[usize]) -> Vec<Event<'a>>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut result = Vec::new();
+    let mut heading_index = 0;
+    let mut folded_level: Option<u32> = None;
+    let mut fold_current = false;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                let inside_fold =
+                    matches!(folded_level, Some(folded_level) if folded_level < level);
+                if !inside_fold {
+                    fold_current = folded.contains(&heading_index);
+                    folded_level = None;
+                    result.push(event);
+                }
+                heading_index += 1;
+            }
+            Event::End(Tag::Heading(level)) if folded_level.is_none() => {
+                if fold_current {
+                    result.push(Event::Text(" \u{2026}".into()));
+                    folded_level = Some(level);
+                }
+                result.push(event);
+            }
+            event if folded_level.is_none() => result.push(event),
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn fold(markdown: &'static str, folded: &[usize]) -> Vec<Event<'static>> {
+        fold_sections(Parser::new(markdown), folded)
+    }
+
+    #[test]
+    fn fold_nothing() {
+        let markdown = "# A\n\nb\n";
+        assert_eq!(
+            fold(markdown, &[]),
+            Parser::new(markdown).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn fold_section_with_subsections() {
+        let markdown = "# A\n\nb\n\n## C\n\nd\n\n# E\n\nf\n";
+        assert_eq!(
+            fold(markdown, &[0]),
+            vec![
+                Event::Start(Tag::Heading(1)),
+                Event::Text("A".into()),
+                Event::Text(" \u{2026}".into()),
+                Event::End(Tag::Heading(1)),
+                Event::Start(Tag::Heading(1)),
+                Event::Text("E".into()),
+                Event::End(Tag::Heading(1)),
+                Event::Start(Tag::Paragraph),
+                Event::Text("f".into()),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_subsection() {
+        let markdown = "# A\n\n## C\n\nd\n\n# E\n";
+        assert_eq!(
+            fold(markdown, &[1]),
+            vec![
+                Event::Start(Tag::Heading(1)),
+                Event::Text("A".into()),
+                Event::End(Tag::Heading(1)),
+                Event::Start(Tag::Heading(2)),
+                Event::Text("C".into()),
+                Event::Text(" \u{2026}".into()),
+                Event::End(Tag::Heading(2)),
+                Event::Start(Tag::Heading(1)),
+                Event::Text("E".into()),
+                Event::End(Tag::Heading(1)),
+            ]
+        );
+    }
+}
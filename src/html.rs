@@ -0,0 +1,160 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal parsing of inline HTML.
+
+use crate::terminal::ImageSize;
+
+/// An HTML `img` tag.
+#[derive(Debug, PartialEq)]
+pub struct ImgTag<'a> {
+    /// The source of the image.
+    pub src: &'a str,
+    /// The size of the image, from the `width` and `height` attributes.
+    pub size: ImageSize,
+}
+
+/// Parse the attributes of an HTML tag.
+///
+/// Return pairs of lower-cased attribute names and values; the value of
+/// attributes without value is empty.  Return `None` if a quoted value lacks
+/// its closing quote.
+fn parse_attributes(mut attributes: &str) -> Option<Vec<(String, &str)>> {
+    let mut result = Vec::new();
+    loop {
+        attributes = attributes.trim_start();
+        let name_end = attributes
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(attributes.len());
+        if name_end == 0 {
+            return Some(result);
+        }
+        let name = attributes[..name_end].to_lowercase();
+        attributes = attributes[name_end..].trim_start();
+        if attributes.starts_with('=') {
+            attributes = attributes[1..].trim_start();
+            let (value, rest) = match attributes.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let end = attributes[1..].find(quote)?;
+                    (&attributes[1..=end], &attributes[end + 2..])
+                }
+                _ => {
+                    let end = attributes
+                        .find(char::is_whitespace)
+                        .unwrap_or(attributes.len());
+                    (&attributes[..end], &attributes[end..])
+                }
+            };
+            result.push((name, value));
+            attributes = rest;
+        } else {
+            result.push((name, ""));
+        }
+    }
+}
+
+/// Parse a length in pixels, with or without a `px` unit.
+fn parse_pixels(value: &str) -> Option<u32> {
+    value
+        .trim()
+        .trim_end_matches("px")
+        .parse()
+        .ok()
+        .filter(|pixels| 0 < *pixels)
+}
+
+/// Whether `html` starts an HTML comment.
+pub fn starts_comment(html: &str) -> bool {
+    html.trim_start().starts_with("<!--")
+}
+
+/// Parse `html` as a single `img` tag.
+///
+/// Return `None` if `html` contains anything but a single `img` tag, or if the
+/// tag has no `src` attribute.
+pub fn parse_img_tag(html: &str) -> Option<ImgTag<'_>> {
+    let html = html.trim();
+    match html.get(..4) {
+        Some(start) if html.ends_with('>') && start.eq_ignore_ascii_case("<img") => {}
+        _ => return None,
+    }
+    let attributes = html[4..html.len() - 1].trim_end_matches('/');
+    if (!attributes.is_empty() && !attributes.starts_with(char::is_whitespace))
+        || attributes.contains('<')
+    {
+        return None;
+    }
+    let mut tag = ImgTag {
+        src: "",
+        size: ImageSize::default(),
+    };
+    for (name, value) in parse_attributes(attributes)? {
+        match name.as_str() {
+            "src" => tag.src = value,
+            "width" => tag.size.width = parse_pixels(value),
+            "height" => tag.size.height = parse_pixels(value),
+            _ => {}
+        }
+    }
+    Some(tag).filter(|tag| !tag.src.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn starts_comment() {
+        assert!(super::starts_comment("<!-- foo -->"));
+        assert!(super::starts_comment("  <!--\n"));
+        assert!(!super::starts_comment("<p><!-- foo --></p>"));
+    }
+
+    #[test]
+    fn parse_img_tag_with_size() {
+        assert_eq!(
+            parse_img_tag("<img src=\"logo.png\" width=\"400\" height='200px' alt=\"Logo\">\n"),
+            Some(ImgTag {
+                src: "logo.png",
+                size: ImageSize {
+                    width: Some(400),
+                    height: Some(200),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_img_tag_without_size() {
+        assert_eq!(
+            parse_img_tag("<IMG SRC=logo.png width=50% />"),
+            Some(ImgTag {
+                src: "logo.png",
+                size: ImageSize::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_img_tag_rejects_other_html() {
+        assert_eq!(parse_img_tag("<p>foo</p>"), None);
+        assert_eq!(parse_img_tag("<imgfoo src=\"logo.png\">"), None);
+        assert_eq!(parse_img_tag("<img alt=\"Logo\">"), None);
+        assert_eq!(parse_img_tag("<img src=\"logo.png\"> and text"), None);
+        assert_eq!(parse_img_tag("<img src=\"logo.png\"> <b>"), None);
+        assert_eq!(parse_img_tag("<img src=\"logo.png>"), None);
+        assert_eq!(parse_img_tag("<\u{e9}\u{20ac}>"), None);
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read and decode input files.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{stdin, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// How to read input.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct InputOptions {
+    /// The encoding of input, or `None` to detect the encoding, see [`decode`].
+    pub encoding: Option<&'static Encoding>,
+    /// The maximum size of input in bytes after decompression, if any.
+    pub max_bytes: Option<usize>,
+}
+
+/// Decode `bytes` to a string.
+///
+/// If `encoding` is given decode with this encoding.  Otherwise pick the
+/// encoding from a byte order mark, or take UTF-8 if `bytes` are valid UTF-8,
+/// or fall back to Windows-1252, the superset of Latin-1 browsers use.
+///
+/// Normalize CRLF line endings to LF.
+pub fn decode(bytes: &[u8], encoding: Option<&'static Encoding>) -> String {
+    let encoding = encoding.unwrap_or_else(|| match Encoding::for_bom(bytes) {
+        Some((encoding, _)) => encoding,
+        None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+        None => WINDOWS_1252,
+    });
+    let text = encoding.decode_with_bom_removal(bytes).0;
+    if text.contains('\r') {
+        text.replace("\r\n", "\n")
+    } else {
+        text.into_owned()
+    }
+}
+
+/// Decompress gzip or zstd compressed `bytes`.
+///
+/// Detect compression from the magic number at the start of `bytes`, and
+/// return `bytes` unchanged if they are not compressed.  Fail if the
+/// decompressed input has more than `max_bytes`, and stop decompressing right
+/// after `max_bytes`, to never exhaust memory for small compressed input with
+/// huge contents.
+pub fn decompress(bytes: Vec<u8>, max_bytes: Option<usize>) -> std::io::Result<Vec<u8>> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        read_limited(MultiGzDecoder::new(bytes.as_slice()), max_bytes)
+    } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        read_limited(
+            zstd::stream::read::Decoder::new(bytes.as_slice())?,
+            max_bytes,
+        )
+    } else {
+        read_limited(bytes.as_slice(), max_bytes)
+    }
+}
+
+/// Read all of `source`, but fail if it has more than `max_bytes`.
+fn read_limited<R: Read>(source: R, max_bytes: Option<usize>) -> std::io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    match max_bytes {
+        None => {
+            let mut source = source;
+            source.read_to_end(&mut buffer)?;
+        }
+        Some(max_bytes) => {
+            source.take(max_bytes as u64 + 1).read_to_end(&mut buffer)?;
+            if max_bytes < buffer.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Input exceeds maximum size of {} bytes", max_bytes),
+                ));
+            }
+        }
+    }
+    Ok(buffer)
+}
+
+/// Parse the label of an encoding, e.g. for `--encoding`.
+pub fn parse_encoding(label: &str) -> clap::Result<&'static Encoding> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+        clap::Error::with_description(
+            &format!("Unknown encoding {}", label),
+            clap::ErrorKind::InvalidValue,
+        )
+    })
+}
+
+/// Expand glob patterns in `filenames`.
+///
+/// Replace every filename with glob characters, e.g. `docs/**/*.md`, with all
+/// matching paths in order, for shells which do not expand globs, like
+/// Windows `cmd`.  Keep filenames which exist as files, filenames which are
+/// no valid patterns, and patterns which match nothing as they are, to report
+/// them as missing files later.
+pub fn expand_globs(filenames: Vec<String>) -> Vec<String> {
+    let mut expanded = Vec::with_capacity(filenames.len());
+    for filename in filenames {
+        let is_pattern = filename.contains(&['*', '?', '['][..]);
+        if !is_pattern || Path::new(&filename).exists() {
+            expanded.push(filename);
+            continue;
+        }
+        let matches = glob::glob(&filename)
+            .map(|paths| {
+                paths
+                    .filter_map(Result::ok)
+                    .filter(|path| path.is_file())
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if matches.is_empty() {
+            expanded.push(filename);
+        } else {
+            expanded.extend(matches);
+        }
+    }
+    expanded
+}
+
+/// Read input for `filename`.
+///
+/// If `filename` is `-` read from standard input, otherwise try to open and
+/// read the given file.  Decompress gzip or zstd compressed input, and decode
+/// input according to `options`, see [`decode`].
+///
+/// Fail if input has more bytes than `options` permit, before or after
+/// decompression.
+pub fn read_input<T: AsRef<str>>(
+    filename: T,
+    options: InputOptions,
+) -> std::io::Result<(PathBuf, String)> {
+    let cd = std::env::current_dir()?;
+    let max_bytes = options.max_bytes;
+
+    if filename.as_ref() == "-" {
+        let buffer = read_limited(stdin(), max_bytes)?;
+        Ok((
+            cd,
+            decode(&decompress(buffer, max_bytes)?, options.encoding),
+        ))
+    } else {
+        let buffer = read_limited(File::open(filename.as_ref())?, max_bytes)?;
+        let base_dir = cd
+            .join(filename.as_ref())
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or(cd);
+        Ok((
+            base_dir,
+            decode(&decompress(buffer, max_bytes)?, options.encoding),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::UTF_16LE;
+
+    #[test]
+    fn decode_utf8() {
+        assert_eq!(decode("Grüße".as_bytes(), None), "Grüße");
+        assert_eq!(decode(b"\xef\xbb\xbfFoo", None), "Foo");
+    }
+
+    #[test]
+    fn decode_latin1() {
+        assert_eq!(decode(b"Gr\xfc\xdfe", None), "Grüße");
+    }
+
+    #[test]
+    fn decode_utf16() {
+        assert_eq!(decode(b"\xff\xfeF\x00o\x00o\x00", None), "Foo");
+        assert_eq!(decode(b"F\x00o\x00o\x00", Some(UTF_16LE)), "Foo");
+    }
+
+    #[test]
+    fn normalize_line_endings() {
+        assert_eq!(decode(b"Foo\r\n\r\n    bar\r\n", None), "Foo\n\n    bar\n");
+        assert_eq!(decode(b"\xff\xfea\x00\r\x00\n\x00", None), "a\n");
+    }
+
+    #[test]
+    fn decompress_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"# Foo\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(compressed, None).unwrap(), b"# Foo\n");
+    }
+
+    #[test]
+    fn decompress_zstd() {
+        let compressed = zstd::stream::encode_all(&b"# Foo\n"[..], 0).unwrap();
+        assert_eq!(decompress(compressed, None).unwrap(), b"# Foo\n");
+    }
+
+    #[test]
+    fn decompress_plain() {
+        assert_eq!(decompress(b"# Foo\n".to_vec(), None).unwrap(), b"# Foo\n");
+    }
+
+    #[test]
+    fn decompress_at_most_max_bytes() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        let contents = vec![b'a'; 1_000_000];
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&contents).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let zstd = zstd::stream::encode_all(contents.as_slice(), 19).unwrap();
+        for compressed in &[gzip, zstd] {
+            assert!(compressed.len() < 10_000);
+            let error = decompress(compressed.clone(), Some(10_000)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(
+                decompress(compressed.clone(), Some(1_000_000))
+                    .unwrap()
+                    .len(),
+                1_000_000
+            );
+        }
+        assert!(decompress(b"# Foo\n".to_vec(), Some(3)).is_err());
+    }
+
+    #[test]
+    fn parse_encodings() {
+        assert_eq!(parse_encoding("latin1").unwrap(), WINDOWS_1252);
+        assert_eq!(parse_encoding("UTF-16LE").unwrap(), UTF_16LE);
+        assert!(parse_encoding("foo").is_err());
+    }
+
+    #[test]
+    fn expand_globs() {
+        let filenames = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let expanded = super::expand_globs(filenames(&["-", "sample/*.md", "missing/*.md"]));
+        assert_eq!(expanded.first().map(String::as_str), Some("-"));
+        assert_eq!(expanded.last().map(String::as_str), Some("missing/*.md"));
+        assert!(2 < expanded.len(), "{:?}", expanded);
+        assert!(expanded.iter().any(|name| name.ends_with("common-mark.md")));
+    }
+}
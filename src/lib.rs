@@ -16,25 +16,70 @@
 
 //! Write markdown to TTYs.
 
-use pulldown_cmark::Event;
+use pulldown_cmark::{Event, Options, Parser};
 use std::error::Error;
-use std::io::Write;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::Path;
-use syntect::highlighting::ThemeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
+use url::Url;
 
+mod abbreviations;
+mod balance;
+mod banner;
+mod bidi;
+mod convert;
+pub mod error;
+mod fold;
+mod html;
+mod line_map;
+mod link_check;
+mod links;
 mod magic;
+mod metadata;
+mod parallel;
+mod pipeline;
+mod renderer;
 mod resources;
+mod scripts;
+pub mod slides;
+pub mod slug;
+mod snippets;
+mod style;
+mod style_sheet;
 mod svg;
+mod syntax;
 mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod toc;
+mod warnings;
 
 mod context_write;
 
 use context_write::*;
+use line_map::{ByteLimit, LineCounter, LineLimit};
 
 // Expose some select things for use in main
-pub use crate::resources::ResourceAccess;
+pub use crate::fold::fold_sections;
+pub use crate::line_map::LineMap;
+pub use crate::link_check::{check_links, LinkCheckSettings, LinkStatus};
+pub use crate::links::{LinkKind, LinkPlacement, RenderedLink};
+pub use crate::metadata::{metadata, Metadata};
+pub use crate::parallel::{render_parallel, Document};
+pub use crate::pipeline::{PassEvent, PassPosition, Pipeline};
+pub use crate::renderer::Renderer;
+pub use crate::resources::{HttpAuth, HttpHost, HttpSettings, LocalFileAccess, ResourceAccess};
+pub use crate::snippets::{snippets, Snippet};
+pub use crate::style::Style;
+pub use crate::style_sheet::*;
 pub use crate::terminal::*;
+pub use crate::warnings::{Warning, WarningHandler};
 
 /// Dump markdown events to a writer.
 pub fn dump_events<'a, W, I>(writer: &mut W, events: I) -> Result<(), Box<dyn Error>>
@@ -49,7 +94,7 @@ where
 }
 
 /// Settings for markdown rendering.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Settings {
     /// Capabilities of the terminal mdcat writes to.
     pub terminal_capabilities: TerminalCapabilities,
@@ -58,7 +103,451 @@ pub struct Settings {
     /// Whether remote resource access is permitted.
     pub resource_access: ResourceAccess,
     /// Syntax set for syntax highlighting of code blocks.
-    pub syntax_set: SyntaxSet,
+    pub syntax_set: Arc<SyntaxSet>,
+    /// The theme for syntax highlighting of code blocks.
+    ///
+    /// If `None` highlight with Solarized, mapped to the basic ANSI colours of
+    /// the terminal theme.  Otherwise highlight with the colours of the given
+    /// theme, degraded to what the terminal supports.
+    pub theme: Option<Arc<Theme>>,
+    /// How to break pages before level-1 headings.
+    pub page_break: PageBreak,
+    /// The number of columns to indent the whole document with.
+    pub margin: usize,
+    /// The maximum number of columns to use for content.
+    ///
+    /// If set wrap text at this width, and center content if the terminal is
+    /// wider.  If not set use the whole terminal width and do not wrap text.
+    pub content_width: Option<usize>,
+    /// The style sheet to render with.
+    pub style_sheet: StyleSheet,
+    /// How to write link references which do not fit into a line.
+    pub reference_overflow: ReferenceOverflow,
+    /// The number of columns between tab stops in code blocks.
+    ///
+    /// Expand tabs in code blocks to spaces up to the next tab stop, or leave
+    /// tabs alone if 0.
+    pub tab_width: usize,
+    /// Whether to remove indentation common to all lines of a code block.
+    pub dedent_code: bool,
+    /// How to write lines of code which do not fit into a line.
+    pub code_overflow: CodeOverflow,
+    /// Whether to paint code blocks on the background colour of the theme.
+    ///
+    /// Code blocks then span the whole content width.  This requires a
+    /// terminal with 24-bit colours.
+    pub code_background: bool,
+    /// Whether to number images with captions as figures.
+    pub figure_numbers: bool,
+    /// Whether to play animated images on terminals which support animations.
+    ///
+    /// If false show only the first frame of animated images.
+    pub animations: bool,
+    /// The maximum size of inline images, in bytes of base64 encoded data.
+    ///
+    /// Scale down larger images until they fit.
+    pub max_inline_image_bytes: usize,
+    /// A cache for rendered images.
+    pub image_cache: ImageCache,
+    /// Whether to write level-1 headings with double height and width.
+    ///
+    /// Requires a terminal which supports DEC line attributes, such as xterm or
+    /// VTE-based terminals.
+    pub double_height_headings: bool,
+    /// How to mark footnotes.
+    pub footnote_marker: FootnoteMarker,
+    /// Whether to write `^superscript^` and `~subscript~` text.
+    ///
+    /// Map characters to their Unicode superscript or subscript variants where
+    /// possible, and otherwise fall back to `^(text)` or `_(text)`.
+    ///
+    /// With strikethrough enabled pulldown-cmark parses `~subscript~` as
+    /// strikethrough; parse documents with [`parse`] to get subscript text.
+    pub super_subscripts: bool,
+    /// Whether to support `*[HTML]: HyperText Markup Language` abbreviations.
+    ///
+    /// Underline abbreviations in text, and list them at the end of the document.
+    pub abbreviations: bool,
+    /// Whether to style output even if the terminal capabilities have no styles.
+    ///
+    /// Use the basic ANSI styles then, e.g. for output to `less -R`.
+    pub force_style: bool,
+    /// The maximum number of pending reference links.
+    ///
+    /// If set write pending links at the end of the next paragraph or list
+    /// item once this many links are pending, instead of waiting for the next
+    /// heading or the end of the document.
+    pub max_pending_links: Option<usize>,
+    /// The maximum depth of nested block quotes and lists.
+    ///
+    /// If set skip block quotes and lists nested deeper, along with all their
+    /// contents.
+    pub max_nesting_depth: Option<usize>,
+    /// The maximum size of images to read, in bytes.
+    ///
+    /// If set do not show larger images inline, and never read more than this
+    /// many bytes of an image into memory.
+    pub max_image_read_bytes: Option<usize>,
+    /// The maximum number of pixels of images, i.e. width times height.
+    ///
+    /// If set do not show images with more pixels inline, and never decode
+    /// them, to not exhaust memory with small but huge images.
+    pub max_image_pixels: Option<u64>,
+    /// Whether to guess the language of indented code blocks.
+    ///
+    /// Indented code blocks have no language; if enabled guess their language
+    /// from their first line, e.g. a shebang, to highlight them.
+    pub sniff_code: bool,
+    /// The language of code blocks without a language.
+    ///
+    /// If set highlight fenced code blocks without a language token, and indented
+    /// code blocks, as this language, e.g. `rust`.
+    pub default_code_language: Option<String>,
+    /// Whether to number code blocks.
+    ///
+    /// If enabled label each code block with its number, e.g. `[snippet 2]`, to
+    /// refer to it, e.g. with [`snippets`].
+    pub number_snippets: bool,
+    /// What to write between documents.
+    ///
+    /// See [`push_separator`].
+    pub document_separator: DocumentSeparator,
+    /// The maximum number of lines to write.
+    ///
+    /// If set stop after this many lines of output, and end with a dimmed
+    /// trailer which tells how many lines remain, e.g. to preview documents.
+    pub max_lines: Option<usize>,
+    /// The base URL for permalinks of headings.
+    ///
+    /// If set and the terminal supports inline links, end every heading with a
+    /// link to the heading, i.e. this URL with the slug of the heading as fragment.
+    pub permalink_base: Option<Url>,
+    /// Whether to show HTML comments.
+    ///
+    /// If enabled write HTML comments dimmed, e.g. to debug directives for
+    /// tools; otherwise strip them.
+    pub show_html_comments: bool,
+    /// Whether to annotate the layout of the document.
+    ///
+    /// If enabled write a ruler with column markers at the beginning of the
+    /// document, and the indentation and width of each block before the block,
+    /// to debug wrapping and alignment.
+    pub debug_layout: bool,
+    /// Whether to put text with right-to-left letters into directional isolates.
+    ///
+    /// Isolate every inline fragment which contains right-to-left letters, so that
+    /// terminals which implement the Unicode bidirectional algorithm lay out
+    /// mixed-direction paragraphs correctly.
+    pub bidi_isolates: bool,
+    /// How to number link references.
+    pub reference_numbering: ReferenceNumbering,
+    /// When to write pending link references.
+    pub reference_flush: ReferenceFlushPolicy,
+    /// How to write the titles of links.
+    pub link_titles: LinkTitles,
+    /// Whether to render text for copying.
+    ///
+    /// Omit borders around code blocks and heading adornments, write plain ASCII
+    /// bullets and task markers, and never wrap or truncate lines of code, so that
+    /// text selected in the terminal pastes cleanly.  Keep colours and styles.
+    pub copy_mode: bool,
+    /// Whether to write the contents of code blocks tagged `raw` as is.
+    ///
+    /// Write raw blocks byte for byte, e.g. to embed escape sequences or ANSI art,
+    /// but only if the terminal supports styles; otherwise render them as code.
+    pub raw_blocks: bool,
+    /// Whether to render level-1 headings as large ASCII-art banners.
+    ///
+    /// Fall back to plain heading text if the banner font lacks a character of
+    /// the heading, or if the banner does not fit into the layout.
+    pub banner_headings: bool,
+    /// A function to call for problems which do not stop rendering.
+    ///
+    /// mdcat renders documents even if it fails to show some images; if set
+    /// call this function with a warning about every such problem, e.g. to
+    /// print warnings.  Otherwise ignore these problems.
+    pub warnings: Option<WarningHandler>,
+    /// Authentication and headers for HTTP requests for remote resources.
+    pub http: HttpSettings,
+    /// Which local files mdcat may access, e.g. to show images.
+    ///
+    /// Restrict local files to the base directory when rendering untrusted
+    /// markdown, to not leak arbitrary local files into the output.
+    pub local_file_access: LocalFileAccess,
+    /// The maximum size of the output, in bytes.
+    ///
+    /// If set abort rendering with an error once the output exceeds this many
+    /// bytes, e.g. to protect services against documents which expand to huge
+    /// output.
+    pub max_output_bytes: Option<usize>,
+    /// The maximum time to render a document.
+    ///
+    /// If set stop rendering after the first top-level block which ends after
+    /// this time, and end with a dimmed notice, e.g. to never let previewers
+    /// hang on slow networks.  Also limit the time to fetch remote images to
+    /// the remaining time.
+    pub max_render_time: Option<Duration>,
+    /// A token to cancel rendering with, e.g. from another thread.
+    ///
+    /// If set check the token before every event, and abort rendering with an
+    /// error once cancelled.  Also abort fetching remote images.
+    pub cancellation: Option<CancellationToken>,
+    /// Whether to skip frontmatter at the beginning of documents.
+    ///
+    /// pulldown-cmark reads frontmatter delimited by `---` as a rule followed
+    /// by a level-2 heading.  If enabled drop such a heading at the very
+    /// beginning of the document if all its lines are `key: value` pairs, like
+    /// [`metadata`].  Disabled by default, because valid CommonMark documents
+    /// may begin with exactly such a rule and heading.
+    pub skip_frontmatter: bool,
+}
+
+impl Default for Settings {
+    /// Settings for a terminal without any capabilities, of the default size.
+    ///
+    /// Load syntect's default syntaxes, never access remote resources, and
+    /// take the defaults of [`RenderEnv::settings`] for everything else.
+    fn default() -> Self {
+        RenderEnv::new(TerminalCapabilities::none(), TerminalSize::default()).settings()
+    }
+}
+
+/// The environment to render documents in.
+///
+/// Everything which is expensive to load or detect, and the same for all
+/// documents: syntax definitions, the theme, the style sheet, the terminal
+/// and the image cache.  Clones share syntax definitions, theme and image
+/// cache, so clones are cheap, and environments can move between threads.
+///
+/// Set up an environment once, and take [`RenderEnv::settings`] for every
+/// document to render.
+#[derive(Debug, Clone)]
+pub struct RenderEnv {
+    /// The capabilities of the terminal.
+    pub terminal_capabilities: TerminalCapabilities,
+    /// The size of the terminal.
+    pub terminal_size: TerminalSize,
+    /// Syntax set for syntax highlighting of code blocks.
+    pub syntax_set: Arc<SyntaxSet>,
+    /// The theme for syntax highlighting of code blocks, see
+    /// [`Settings::theme`].
+    pub theme: Option<Arc<Theme>>,
+    /// The style sheet to render with.
+    pub style_sheet: StyleSheet,
+    /// A cache for rendered images.
+    pub image_cache: ImageCache,
+}
+
+impl RenderEnv {
+    /// Create an environment for the given terminal.
+    ///
+    /// Load syntect's default syntaxes, and take mdcat's default theme and
+    /// style sheet.
+    pub fn new(terminal_capabilities: TerminalCapabilities, terminal_size: TerminalSize) -> Self {
+        RenderEnv {
+            terminal_capabilities,
+            terminal_size,
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
+            theme: None,
+            style_sheet: StyleSheet::default(),
+            image_cache: ImageCache::default(),
+        }
+    }
+
+    /// Settings to render a single document in this environment.
+    ///
+    /// Share syntax definitions, theme and image cache with this environment,
+    /// and take the defaults for all settings of the document.  In
+    /// particular never access remote resources; change the returned settings
+    /// as needed.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            resource_access: ResourceAccess::LocalOnly,
+            syntax_set: self.syntax_set.clone(),
+            theme: self.theme.clone(),
+            terminal_capabilities: self.terminal_capabilities.clone(),
+            terminal_size: self.terminal_size,
+            page_break: PageBreak::None,
+            margin: 0,
+            content_width: None,
+            style_sheet: self.style_sheet.clone(),
+            reference_overflow: ReferenceOverflow::Wrap,
+            tab_width: 4,
+            dedent_code: false,
+            code_overflow: CodeOverflow::Overflow,
+            code_background: false,
+            figure_numbers: false,
+            animations: false,
+            max_inline_image_bytes: 1 << 20,
+            image_cache: self.image_cache.clone(),
+            double_height_headings: false,
+            footnote_marker: FootnoteMarker::Superscript,
+            super_subscripts: false,
+            abbreviations: false,
+            force_style: false,
+            max_pending_links: None,
+            max_nesting_depth: None,
+            max_image_read_bytes: None,
+            max_image_pixels: None,
+            sniff_code: false,
+            default_code_language: None,
+            number_snippets: false,
+            document_separator: DocumentSeparator::None,
+            max_lines: None,
+            permalink_base: None,
+            show_html_comments: false,
+            debug_layout: false,
+            bidi_isolates: false,
+            reference_numbering: ReferenceNumbering::Document,
+            reference_flush: ReferenceFlushPolicy::PerSection,
+            link_titles: LinkTitles::Plain,
+            copy_mode: false,
+            raw_blocks: false,
+            banner_headings: false,
+            warnings: None,
+            http: HttpSettings::default(),
+            local_file_access: LocalFileAccess::Anywhere,
+            max_output_bytes: None,
+            max_render_time: None,
+            cancellation: None,
+            skip_frontmatter: false,
+        }
+    }
+}
+
+/// How to break pages before level-1 headings.
+///
+/// Page breaks help to paginate output section by section, e.g. when piping
+/// output to `lp` or a pager.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PageBreak {
+    /// Do not break pages; separate headings like every other block.
+    None,
+    /// Emit a form feed character before each level-1 heading.
+    FormFeed,
+    /// Emit the given number of blank lines before each level-1 heading.
+    BlankLines(usize),
+}
+
+/// What to write between documents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DocumentSeparator {
+    /// Write nothing between documents.
+    None,
+    /// Write a horizontal rule between documents.
+    Rule,
+    /// Write the name of each document as a level-1 heading before it.
+    Filename,
+    /// Write a form feed character between documents.
+    FormFeed,
+}
+
+/// How to write link references which do not fit into a line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReferenceOverflow {
+    /// Write references as they are, and let the terminal wrap them.
+    Overflow,
+    /// Wrap references at the content width, and indent continuation lines.
+    Wrap,
+    /// Replace the middle of the link destination with an ellipsis.
+    Ellipsize,
+}
+
+/// How to number link references.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReferenceNumbering {
+    /// Number references throughout the whole document.
+    Document,
+    /// Restart numbering at `[1]` after every level 1 and level 2 heading.
+    Section,
+}
+
+/// When to write pending link references.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReferenceFlushPolicy {
+    /// Write references before every heading, and at the end of the document.
+    PerSection,
+    /// Write all references at the end of the document.
+    EndOfDocument,
+    /// Write references after every paragraph.
+    AfterParagraph,
+}
+
+/// How to write the titles of links.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkTitles {
+    /// Write titles after the destination of link references.
+    Plain,
+    /// Write titles in double quotes after the destination of link references.
+    Quoted,
+    /// Only add titles to inline links, as tooltips in OSC 8 metadata.
+    ///
+    /// Do not write titles in link references.
+    Tooltip,
+}
+
+/// A handle to cancel rendering, e.g. from another thread.
+///
+/// All clones of a token share their state; cancel any clone to cancel all.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token which is not cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancel rendering.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether rendering was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    /// Whether both tokens are clones of the same token.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Fail if `settings` say to cancel rendering.
+fn check_cancelled(settings: &Settings) -> Result<(), Box<dyn Error>> {
+    match settings.cancellation {
+        Some(ref token) if token.is_cancelled() => Err(crate::error::Error::new(
+            crate::error::ErrorKind::Cancelled,
+            "Rendering cancelled",
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// How to write lines of code which do not fit into a line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CodeOverflow {
+    /// Write lines as they are, and let the terminal wrap them.
+    Overflow,
+    /// Wrap lines at the content width, and mark continuation lines.
+    Wrap,
+    /// Truncate lines at the content width, and end them with an ellipsis.
+    Truncate,
+}
+
+/// How to mark footnotes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FootnoteMarker {
+    /// Mark footnotes with their number in superscript digits, e.g. `²`.
+    Superscript,
+    /// Mark footnotes with their number in brackets, e.g. `[^2]`.
+    Bracketed,
+    /// Mark footnotes with as many asterisks as their number, e.g. `**`.
+    Asterisks,
 }
 
 /// Write markdown to a TTY.
@@ -74,24 +563,411 @@ pub fn push_tty<'a, 'e, W, I>(
     settings: &Settings,
     writer: &'a mut W,
     base_dir: &'a Path,
-    mut events: I,
+    events: I,
 ) -> Result<(), Box<dyn Error>>
 where
     I: Iterator<Item = Event<'e>>,
     W: Write,
 {
-    let theme = &ThemeSet::load_defaults().themes["Solarized (dark)"];
-    events
-        .try_fold(Context::new(writer, settings, base_dir, theme), write_event)?
-        .write_pending_links()?;
+    push_tty_with_links(settings, writer, base_dir, events)?;
     Ok(())
 }
 
+/// Write markdown to a TTY, and return all links and images written.
+///
+/// Like [`push_tty`], but return all links and images of the document in
+/// order, with their absolute URLs and how they were written, e.g. to build
+/// an index of links.
+pub fn push_tty_with_links<'a, 'e, W, I>(
+    settings: &Settings,
+    writer: &'a mut W,
+    base_dir: &'a Path,
+    events: I,
+) -> Result<Vec<RenderedLink>, Box<dyn Error>>
+where
+    I: Iterator<Item = Event<'e>>,
+    W: Write,
+{
+    Pipeline::new().push_tty_with_links(settings, writer, base_dir, events)
+}
+
+impl Pipeline {
+    /// Write markdown to a TTY, after running all passes of this pipeline.
+    ///
+    /// Like [`push_tty`], but run the passes of this pipeline over `events`
+    /// along with the built-in passes.
+    ///
+    /// Collect all `events` of the document first, see [`Pipeline`].
+    pub fn push_tty<'a, 'e, W, I>(
+        &self,
+        settings: &Settings,
+        writer: &'a mut W,
+        base_dir: &'a Path,
+        events: I,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        I: Iterator<Item = Event<'e>>,
+        W: Write,
+    {
+        self.push_tty_with_links(settings, writer, base_dir, events)?;
+        Ok(())
+    }
+
+    /// Write markdown to a TTY, and return all links and images written.
+    ///
+    /// Like [`Pipeline::push_tty`], but return all links and images, see
+    /// [`push_tty_with_links`].
+    pub fn push_tty_with_links<'a, 'e, W, I>(
+        &self,
+        settings: &Settings,
+        writer: &'a mut W,
+        base_dir: &'a Path,
+        events: I,
+    ) -> Result<Vec<RenderedLink>, Box<dyn Error>>
+    where
+        I: Iterator<Item = Event<'e>>,
+        W: Write,
+    {
+        let events = events.map(|event| (event, None));
+        let (links, _) = self.push_tty_with_offsets(settings, writer, base_dir, events)?;
+        Ok(links)
+    }
+
+    /// Write markdown to a TTY, and return all links and images written
+    /// along with a line map.
+    ///
+    /// Take `events` along with their offsets in the source, if any, and map
+    /// the offsets of blocks to the lines of output they start at.
+    fn push_tty_with_offsets<'a, 'e, W, I>(
+        &self,
+        settings: &Settings,
+        writer: &'a mut W,
+        base_dir: &'a Path,
+        events: I,
+    ) -> Result<(Vec<RenderedLink>, LineMap), Box<dyn Error>>
+    where
+        I: Iterator<Item = (Event<'e>, Option<usize>)>,
+        W: Write,
+    {
+        match settings.max_output_bytes {
+            Some(max_bytes) => {
+                let mut limit = ByteLimit::new(writer, max_bytes);
+                let result = write_lines(settings, self, &mut limit, base_dir, events);
+                if limit.exceeded() {
+                    Err(crate::error::Error::new(
+                        crate::error::ErrorKind::Render,
+                        format!("Output exceeds maximum size of {} bytes", max_bytes),
+                    )
+                    .into())
+                } else {
+                    result
+                }
+            }
+            None => write_lines(settings, self, writer, base_dir, events),
+        }
+    }
+}
+
+/// Write a whole document of `events` to `writer`, with at most
+/// `settings.max_lines` lines.
+///
+/// See [`write_document`].
+fn write_lines<'a, 'e, W, I>(
+    settings: &Settings,
+    pipeline: &Pipeline,
+    writer: &'a mut W,
+    base_dir: &'a Path,
+    events: I,
+) -> Result<(Vec<RenderedLink>, LineMap), Box<dyn Error>>
+where
+    I: Iterator<Item = (Event<'e>, Option<usize>)>,
+    W: Write,
+{
+    match settings.max_lines {
+        Some(max_lines) => {
+            let mut limit = LineLimit::new(writer, max_lines);
+            let result = write_document(settings, pipeline, &mut limit, base_dir, events)?;
+            let dropped_lines = limit.dropped_lines();
+            if 0 < dropped_lines {
+                let mut context = Context::new(writer, settings, base_dir);
+                context.write_more_lines(dropped_lines)?;
+            }
+            Ok(result)
+        }
+        None => write_document(settings, pipeline, writer, base_dir, events),
+    }
+}
+
+/// Write a whole document of `events` to `writer`.
+///
+/// Run the passes of `pipeline` along with the built-in passes, and return
+/// all links and images written.  Take `events` along with their offsets in
+/// the source, if any, and also return a map from the offsets of blocks to
+/// the lines of output they start at.
+fn write_document<'a, 'e, W, I>(
+    settings: &Settings,
+    pipeline: &Pipeline,
+    writer: &'a mut W,
+    base_dir: &'a Path,
+    events: I,
+) -> Result<(Vec<RenderedLink>, LineMap), Box<dyn Error>>
+where
+    I: Iterator<Item = (Event<'e>, Option<usize>)>,
+    W: Write,
+{
+    let started = Instant::now();
+    let events = pipeline.run(PassPosition::First, events.collect());
+    let events = if settings.skip_frontmatter {
+        metadata::strip_frontmatter(events)
+    } else {
+        events
+    };
+    let events = toc::insert_toc(events);
+    let events = pipeline.run(PassPosition::AfterTableOfContents, events);
+    let mut counter = LineCounter::new(writer);
+    let mut context = Context::new(&mut counter, settings, base_dir);
+    if settings.debug_layout {
+        context.write_column_ruler()?;
+    }
+    let events = if settings.abbreviations {
+        let (events, abbreviations) = abbreviations::extract_abbreviations(events);
+        context = context.with_abbreviations(abbreviations);
+        events
+    } else {
+        events
+    };
+    let events = pipeline.run(PassPosition::AfterAbbreviations, events);
+    let events = if settings.super_subscripts {
+        scripts::with_scripts(events.into_iter()).collect()
+    } else {
+        events
+    };
+    let events = pipeline.run(PassPosition::Last, events);
+    let deadline = settings.max_render_time.map(|time| started + time);
+    let mut context = context
+        .with_headings(slug::headings(events.iter().map(|(event, _)| event)))
+        .with_deadline(deadline);
+    let mut line_map = LineMap::default();
+    // Track the depth of nested tags, to stop after a top-level block only
+    let mut depth = 0;
+    let total = events.len();
+    for (index, (event, offset)) in events.into_iter().enumerate() {
+        check_cancelled(settings)?;
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        let block_offset = offset.filter(|_| line_map::starts_block(&event));
+        context = write_event(context, event)?;
+        if let Some(offset) = block_offset {
+            line_map.insert(offset, context.writer().lines);
+        }
+        if depth == 0 && index + 1 < total && context.deadline_passed() {
+            context.write_truncated()?;
+            break;
+        }
+    }
+    context.write_pending_links()?;
+    context.write_glossary()?;
+    Ok((context.take_rendered_links(), line_map))
+}
+
+/// Write markdown to a TTY, and map the source to lines of output.
+///
+/// Like [`push_tty`], but take `events` along with their offsets in the
+/// source, as from `pulldown_cmark::Parser::into_offset_iter`, and return a
+/// map from offsets of blocks in the source to the lines of output they start
+/// at.  Blocks of a table of contents map to the offset of its directive.
+pub fn push_tty_with_line_map<'a, 'e, W, I>(
+    settings: &Settings,
+    writer: &'a mut W,
+    base_dir: &'a Path,
+    events: I,
+) -> Result<LineMap, Box<dyn Error>>
+where
+    I: Iterator<Item = (Event<'e>, Range<usize>)>,
+    W: Write,
+{
+    let events = events.map(|(event, range)| (event, Some(range.start)));
+    let (_, line_map) =
+        Pipeline::new().push_tty_with_offsets(settings, writer, base_dir, events)?;
+    Ok(line_map)
+}
+
+/// Write arbitrary events to a TTY.
+///
+/// Like [`push_tty`], but accept any sequence of `events`, not just the well
+/// formed events of a markdown parser: balance start and end tags first, and
+/// drop any end tag without a matching start.
+///
+/// This function never panics; it only fails if writing to `writer` fails.
+/// Use it to render untrusted or generated events, e.g. in fuzzers or
+/// servers.
+pub fn render_events_infallible<'a, 'e, W, I>(
+    settings: &Settings,
+    writer: &'a mut W,
+    base_dir: &'a Path,
+    events: I,
+) -> Result<(), Box<dyn Error>>
+where
+    I: Iterator<Item = Event<'e>>,
+    W: Write,
+{
+    push_tty(settings, writer, base_dir, balance::balance(events))
+}
+
+/// Parse markdown `source` with the given `options`, for `settings`.
+///
+/// If `settings` enable superscript and subscript parse `~x~` as subscript,
+/// and only `~~x~~` as strikethrough.
+pub fn parse<'a>(
+    source: &'a str,
+    options: Options,
+    settings: &Settings,
+) -> Box<dyn Iterator<Item = Event<'a>> + 'a> {
+    let parser = Parser::new_ext(source, options);
+    if settings.super_subscripts {
+        Box::new(scripts::subscripts_from_strikethrough(
+            source,
+            parser.into_offset_iter(),
+        ))
+    } else {
+        Box::new(parser)
+    }
+}
+
+/// The markdown extensions mdcat supports.
+pub(crate) fn markdown_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options
+}
+
+/// Render markdown `events` to a string.
+///
+/// Like [`push_tty`], but return the rendered text instead of writing it.
+/// `base_dir` denotes the base directory to resolve relative references
+/// against.
+pub fn render_events_to_string<'e, I>(
+    settings: &Settings,
+    base_dir: &Path,
+    events: I,
+) -> Result<String, Box<dyn Error>>
+where
+    I: Iterator<Item = Event<'e>>,
+{
+    let mut sink = Vec::new();
+    push_tty(settings, &mut sink, base_dir, events)?;
+    Ok(String::from_utf8(sink)?)
+}
+
+/// Render markdown `input` to a string.
+///
+/// Parse `input` with all extensions mdcat supports, resolve relative
+/// references against the current directory, and return the rendered text.
+pub fn render_to_string(input: &str, settings: &Settings) -> Result<String, Box<dyn Error>> {
+    let base_dir = std::env::current_dir()?;
+    let parser = parse(input, markdown_options(), settings);
+    render_events_to_string(settings, &base_dir, parser)
+}
+
+/// Write a footer with the `source` of a document to a TTY.
+///
+/// Write the `source`, e.g. the URL of a remote document, and the time the
+/// document was `fetched` at, if given, in a dimmed line, to keep the
+/// provenance of rendered documents in screenshots or pasted output.  Call
+/// this function after [`push_tty`].
+pub fn push_footer<W: Write>(
+    settings: &Settings,
+    writer: &mut W,
+    source: &str,
+    fetched: Option<SystemTime>,
+) -> Result<(), Box<dyn Error>> {
+    let mut context = Context::new(writer, settings, Path::new("/"));
+    context.write_footer(source, fetched)?;
+    Ok(())
+}
+
+/// Write the separator before a document with the given `name` to a TTY.
+///
+/// Write the separator of `settings` before the document, if `first` is
+/// false, i.e. if the document follows another document.  With
+/// [`DocumentSeparator::Filename`] write the `name` of the document even
+/// before the first document.
+pub fn push_separator<W: Write>(
+    settings: &Settings,
+    writer: &mut W,
+    name: &str,
+    first: bool,
+) -> Result<(), Box<dyn Error>> {
+    let context = Context::new(writer, settings, Path::new("/"));
+    context.write_separator(name, first)?;
+    Ok(())
+}
+
+/// Write markdown to a file.
+///
+/// Like [`push_tty`], but create or truncate the file at `path` and write to
+/// this file.  If `strip_escapes` is true strip all escape sequences from the
+/// output to write plain text, regardless of the terminal capabilities in
+/// `settings`.
+pub fn push_file<'e, P, I>(
+    settings: &Settings,
+    path: P,
+    base_dir: &Path,
+    events: I,
+    strip_escapes: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    I: Iterator<Item = Event<'e>>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    if strip_escapes {
+        let mut writer = StripEscapes::new(writer);
+        push_tty(settings, &mut writer, base_dir, events)?;
+        writer.flush()?;
+    } else {
+        push_tty(settings, &mut writer, base_dir, events)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// The theme to highlight code with.
+///
+/// Take the theme of `settings`, or Solarized (dark) if settings have no
+/// theme.  Load Solarized only once, and share it between all documents.
+pub(crate) fn highlighting_theme(settings: &Settings) -> &Theme {
+    static SOLARIZED: OnceLock<Theme> = OnceLock::new();
+    match settings.theme {
+        Some(ref theme) => theme.as_ref(),
+        None => SOLARIZED.get_or_init(|| {
+            ThemeSet::load_defaults()
+                .themes
+                .remove("Solarized (dark)")
+                .expect("Solarized (dark) is a default theme")
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
-    use pulldown_cmark::Parser;
+    use pulldown_cmark::{Options, Parser, Tag};
+
+    /// Settings for plain output without any terminal capabilities.
+    pub(crate) fn plain_settings() -> Settings {
+        Settings {
+            syntax_set: Arc::new(SyntaxSet::default()),
+            ..Settings::default()
+        }
+    }
 
     fn render_string(input: &str, settings: &Settings) -> Result<Vec<u8>, Box<dyn Error>> {
         let source = Parser::new(input);
@@ -100,22 +976,654 @@ mod tests {
         Ok(sink)
     }
 
+    /// Render `markdown` with `settings` to a string.
+    ///
+    /// Parse tables, footnotes and task lists, and panic if rendering fails.
+    pub(crate) fn render_with(settings: &Settings, markdown: &str) -> String {
+        let options =
+            Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_TASKLISTS;
+        let mut sink = Vec::new();
+        push_tty(
+            settings,
+            &mut sink,
+            Path::new("/"),
+            Parser::new_ext(markdown, options),
+        )
+        .unwrap();
+        String::from_utf8(sink).unwrap()
+    }
+
+    /// Write `text` in `style` to a string.
+    pub(crate) fn styled(style: Style, text: &str) -> String {
+        let mut buffer = Vec::new();
+        style.write_styled(&mut buffer, text).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
     #[test]
     #[allow(non_snake_case)]
     fn GH_49_format_no_colour_simple() {
-        let result = String::from_utf8(
-            render_string(
-                "_lorem_ **ipsum** dolor **sit** _amet_",
-                &Settings {
-                    resource_access: ResourceAccess::LocalOnly,
-                    syntax_set: SyntaxSet::default(),
-                    terminal_capabilities: TerminalCapabilities::none(),
-                    terminal_size: TerminalSize::default(),
-                },
-            )
-            .unwrap(),
+        let result = render_with(&plain_settings(), "_lorem_ **ipsum** dolor **sit** _amet_");
+        assert_eq!(result, "lorem ipsum dolor sit amet\n");
+    }
+
+    #[test]
+    fn render_arbitrary_events() {
+        use pulldown_cmark::{Alignment, CodeBlockKind, LinkType, Tag};
+        let events = vec![
+            Event::End(Tag::Paragraph),
+            Event::End(Tag::BlockQuote),
+            Event::Start(Tag::Item),
+            Event::Text("foo".into()),
+            Event::Start(Tag::Heading(u32::MAX)),
+            Event::End(Tag::Heading(1)),
+            Event::Start(Tag::List(Some(u64::MAX))),
+            Event::Start(Tag::Item),
+            Event::End(Tag::Item),
+            Event::Start(Tag::Item),
+            Event::End(Tag::List(None)),
+            Event::Start(Tag::TableCell),
+            Event::Start(Tag::Table(vec![Alignment::Center])),
+            Event::End(Tag::Emphasis),
+            Event::Start(Tag::Link(LinkType::Inline, "".into(), "".into())),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced("rust".into()))),
+            Event::Html("<\u{e9}\u{20ac}>".into()),
+            Event::End(Tag::FootnoteDefinition("x".into())),
+            Event::FootnoteReference("".into()),
+            Event::TaskListMarker(true),
+        ];
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            ..plain_settings()
+        };
+        for end in 0..=events.len() {
+            let prefix = || events[..end].iter().cloned();
+            let mut sink = Vec::new();
+            render_events_infallible(&settings, &mut sink, Path::new("/"), prefix()).unwrap();
+            // Even unbalanced events must not panic
+            push_tty(&settings, &mut sink, Path::new("/"), prefix()).unwrap();
+        }
+    }
+
+    #[test]
+    fn resolve_fragment_links() {
+        let markdown = "<!-- toc -->\n\n# Intro\n\nSee [below](#usage), [Usage](#usage) \
+                        and [x](#nope).\n\n# Usage\n";
+        let result = render_with(&plain_settings(), markdown);
+        assert!(
+            result.starts_with("\u{2022} Intro\n\u{2022} Usage\n"),
+            "{:?}",
+            result
+        );
+        assert!(
+            result.contains("See below (\u{a7} Usage), Usage and x[1]."),
+            "{:?}",
+            result
+        );
+        assert!(result.contains("[1]: #nope\n"), "{:?}", result);
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            permalink_base: Some(Url::parse("https://example.com/README.md").unwrap()),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(
+            result
+                .contains("See \x1b]8;;https://example.com/README.md#usage\x07below\x1b]8;;\x07,"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn collect_rendered_links() {
+        let markdown = "[a](http://a.example) <http://b.example> ![c](c.png)\n";
+        let mut sink = Vec::new();
+        let links = push_tty_with_links(
+            &plain_settings(),
+            &mut sink,
+            Path::new("/"),
+            Parser::new(markdown),
         )
         .unwrap();
-        assert_eq!(result, "lorem ipsum dolor sit amet\n");
+        let summary: Vec<_> = links
+            .iter()
+            .map(|link| {
+                let url = link.url.as_ref().map(|url| url.as_str());
+                (link.kind, link.destination.as_str(), url, link.placement)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    LinkKind::Link,
+                    "http://a.example",
+                    Some("http://a.example/"),
+                    LinkPlacement::Reference(1)
+                ),
+                (
+                    LinkKind::Link,
+                    "http://b.example",
+                    Some("http://b.example/"),
+                    LinkPlacement::Text
+                ),
+                (
+                    LinkKind::Image,
+                    "c.png",
+                    Some("file:///c.png"),
+                    LinkPlacement::Text
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn heading_permalinks() {
+        let markdown = "# Getting `started`!\n";
+        let permalink_base = Url::parse("https://example.com/doc.md").ok();
+        let settings = Settings {
+            permalink_base: permalink_base.clone(),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert_eq!(result, "\u{2504}Getting started!\n");
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                links: LinkCapability::OSC8(OSC8Links::for_localhost()),
+                ..TerminalCapabilities::none()
+            },
+            permalink_base,
+            ..plain_settings()
+        };
+        let mut sink = Vec::new();
+        let links =
+            push_tty_with_links(&settings, &mut sink, Path::new("/"), Parser::new(markdown))
+                .unwrap();
+        let result = String::from_utf8(sink).unwrap();
+        assert!(
+            result.contains("https://example.com/doc.md#getting-started\x07 \u{b6}"),
+            "{:?}",
+            result
+        );
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].kind, LinkKind::Permalink);
+        assert_eq!(links[0].destination, "#getting-started");
+    }
+
+    #[test]
+    fn debug_layout() {
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                width: 10,
+                ..TerminalSize::default()
+            },
+            debug_layout: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "a\n\n> b\n");
+        assert_eq!(
+            result,
+            "\u{b7}\u{b7}\u{b7}\u{b7}+\u{b7}\u{b7}\u{b7}\u{b7}1\n\
+             [paragraph: indent 0, width 10]\n\
+             a\n\
+             [block quote: indent 0, width 10]\n    \
+             [paragraph: indent 4, width 6]\n    \
+             b\n"
+        );
+    }
+
+    #[test]
+    fn limit_lines() {
+        let settings = |max_lines| Settings {
+            max_lines,
+            ..plain_settings()
+        };
+        let render = |max_lines| render_with(&settings(max_lines), "a\n\nb\n\nc\n");
+        assert_eq!(render(None), "a\n\nb\n\nc\n");
+        assert_eq!(render(Some(5)), "a\n\nb\n\nc\n");
+        assert_eq!(render(Some(2)), "a\n\n\u{2026} 3 more lines\n");
+        assert_eq!(render(Some(4)), "a\n\nb\n\n\u{2026} 1 more line\n");
+    }
+
+    #[test]
+    fn limit_output_bytes() {
+        let settings = |max_output_bytes| Settings {
+            max_output_bytes,
+            ..plain_settings()
+        };
+        let markdown = "a\n\nb\n\nc\n";
+        assert_eq!(render_with(&settings(Some(8)), markdown), "a\n\nb\n\nc\n");
+        let error = render_string(markdown, &settings(Some(7))).unwrap_err();
+        assert_eq!(error.to_string(), "Output exceeds maximum size of 7 bytes");
+        let error = crate::error::Error::from(error);
+        assert_eq!(error.kind(), crate::error::ErrorKind::Render);
+    }
+
+    #[test]
+    fn limit_render_time() {
+        let settings = |max_render_time| Settings {
+            max_render_time,
+            ..plain_settings()
+        };
+        let markdown = "* a\n* b\n\nc\n\nd\n";
+        let render = |max_render_time| render_with(&settings(max_render_time), markdown);
+        assert_eq!(render(Some(Duration::from_secs(60))), render(None));
+        assert_eq!(
+            render(Some(Duration::from_secs(0))),
+            "\u{2022} a\n\u{2022} b\n\n\u{2026} truncated, rendering took too long\n"
+        );
+    }
+
+    #[test]
+    fn cancel_rendering() {
+        let token = CancellationToken::new();
+        let settings = Settings {
+            cancellation: Some(token.clone()),
+            ..plain_settings()
+        };
+        assert!(render_string("a\n", &settings).is_ok());
+        token.cancel();
+        let error = crate::error::Error::from(render_string("a\n", &settings).unwrap_err());
+        assert_eq!(error.kind(), crate::error::ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn share_render_env() {
+        let env = RenderEnv::new(TerminalCapabilities::none(), TerminalSize::default());
+        let first = env.settings();
+        let second = env.clone().settings();
+        assert!(Arc::ptr_eq(&first.syntax_set, &second.syntax_set));
+        assert_eq!(
+            render_with(&first, "# Foo\n"),
+            render_with(&second, "# Foo\n")
+        );
+    }
+
+    #[test]
+    fn render_markdown_to_string() {
+        let settings = plain_settings();
+        let text = render_to_string("*foo* ~~bar~~\n\n- [x] baz\n", &settings).unwrap();
+        assert_eq!(text, "foo bar\n\n\u{2022} \u{2611} baz\n");
+        let events = Parser::new("# Foo");
+        let text = render_events_to_string(&settings, Path::new("/"), events).unwrap();
+        assert_eq!(text, "\u{2504}Foo\n");
+    }
+
+    #[test]
+    fn super_subscripts() {
+        let markdown = "H~2~O and 2^10^\n\n```\nx^2^\n```\n";
+        let result = render_with(&plain_settings(), markdown);
+        assert!(result.starts_with("H~2~O and 2^10^\n"), "{:?}", result);
+        let settings = Settings {
+            super_subscripts: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(
+            result.starts_with("H\u{2082}O and 2\u{b9}\u{2070}\n"),
+            "{:?}",
+            result
+        );
+        assert!(result.contains("x^2^"), "{:?}", result);
+    }
+
+    #[test]
+    fn abbreviations() {
+        let markdown = "*[HTML]: HyperText Markup Language\n*[W3C]: World Wide Web Consortium\n\n\
+                        HTML by the W3C.\n";
+        let settings = Settings {
+            abbreviations: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert_eq!(
+            result,
+            "HTML by the W3C.\n\n\
+             HTML: HyperText Markup Language\n\
+             W3C: World Wide Web Consortium\n"
+        );
+
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            ..settings
+        };
+        let result = render_with(&settings, markdown);
+        let underlined = styled(Style::new().underline(), "W3C");
+        assert_eq!(result.matches(&underlined).count(), 2, "{:?}", result);
+    }
+
+    #[test]
+    fn map_source_to_output_lines() {
+        let markdown = "# Foo\n\nBar\n\n- a\n- b\n";
+        let parser = Parser::new(markdown).into_offset_iter();
+        let mut sink = Vec::new();
+        let line_map =
+            push_tty_with_line_map(&plain_settings(), &mut sink, &Path::new("/"), parser).unwrap();
+        assert_eq!(
+            String::from_utf8(sink).unwrap(),
+            "\u{2504}Foo\n\nBar\n\n\u{2022} a\n\u{2022} b\n"
+        );
+        assert_eq!(line_map.entries(), &[(0, 0), (7, 2), (12, 4), (16, 5)]);
+        assert_eq!(line_map.line_at(9), Some(2));
+        assert_eq!(line_map.line_at(17), Some(5));
+    }
+
+    #[test]
+    fn map_source_to_output_lines_like_push_tty() {
+        let markdown = "---\ntitle: Foo\n---\n\n<!-- toc -->\n\n# Foo\n\nThe HTML spec.\n\n\
+                        *[HTML]: HyperText Markup Language\n\n- a\n";
+        let settings = Settings {
+            skip_frontmatter: true,
+            abbreviations: true,
+            ..plain_settings()
+        };
+        let mut expected = Vec::new();
+        push_tty(
+            &settings,
+            &mut expected,
+            &Path::new("/"),
+            Parser::new(markdown),
+        )
+        .unwrap();
+        let mut sink = Vec::new();
+        let parser = Parser::new(markdown).into_offset_iter();
+        let line_map =
+            push_tty_with_line_map(&settings, &mut sink, &Path::new("/"), parser).unwrap();
+        assert_eq!(sink, expected);
+
+        let output = String::from_utf8(sink).unwrap();
+        let line_of = |prefix: &str| output.lines().position(|line| line.starts_with(prefix));
+        let offset_of = |text: &str| markdown.find(text).unwrap();
+        assert_eq!(line_map.line_at(offset_of("title")), None);
+        assert_eq!(line_map.line_at(offset_of("<!--")), line_of("\u{2022} Foo"));
+        assert_eq!(line_map.line_at(offset_of("# Foo")), line_of("\u{2504}Foo"));
+        assert_eq!(line_map.line_at(offset_of("The")), line_of("The "));
+        assert_eq!(line_map.line_at(offset_of("- a")), line_of("\u{2022} a"));
+    }
+
+    #[test]
+    fn force_style() {
+        let settings = Settings {
+            force_style: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, "**Foo**");
+        assert_eq!(result, format!("{}\n", styled(Style::new().bold(), "Foo")));
+    }
+
+    #[test]
+    fn push_file_strips_escapes() {
+        let settings = Settings {
+            force_style: true,
+            ..plain_settings()
+        };
+        let path = std::env::temp_dir().join("mdcat-push-file-strips-escapes.txt");
+        let base_dir = Path::new("/");
+        push_file(&settings, &path, base_dir, Parser::new("**Foo**"), true).unwrap();
+        let result = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), "Foo\n");
+    }
+
+    #[test]
+    fn normalize_line_endings_in_code_blocks() {
+        let crlf = render_with(&plain_settings(), "```\r\nfoo\r\nbar\r\n```\r\n");
+        let lf = render_with(&plain_settings(), "```\nfoo\nbar\n```\n");
+        assert_eq!(crlf, lf);
+    }
+
+    #[test]
+    fn highlight_code_with_aliases_and_shebangs() {
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
+            ..plain_settings()
+        };
+        assert_eq!(
+            render_with(&settings, "```shell\necho foo\n```\n"),
+            render_with(&settings, "```sh\necho foo\n```\n")
+        );
+        assert_eq!(
+            render_with(&settings, "```foo\n#!/bin/sh\necho foo\n```\n"),
+            render_with(&settings, "```sh\n#!/bin/sh\necho foo\n```\n")
+        );
+    }
+
+    #[test]
+    fn sniff_indented_code() {
+        let mut settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
+            ..plain_settings()
+        };
+        let indented = "    #!/bin/sh\n    echo foo\n";
+        let fenced = render_with(&settings, "```sh\n#!/bin/sh\necho foo\n```\n");
+        assert_ne!(render_with(&settings, indented), fenced);
+        settings.sniff_code = true;
+        assert_eq!(render_with(&settings, indented), fenced);
+    }
+
+    #[test]
+    fn highlight_code_with_default_language() {
+        let mut settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
+            ..plain_settings()
+        };
+        let rust = render_with(&settings, "```rust\nfn main() {}\n```\n");
+        let untagged = "```\nfn main() {}\n```\n";
+        let indented = "    fn main() {}\n";
+        assert_ne!(render_with(&settings, untagged), rust);
+        settings.default_code_language = Some("rust".to_string());
+        assert_eq!(render_with(&settings, untagged), rust);
+        assert_eq!(render_with(&settings, indented), rust);
+    }
+
+    #[test]
+    fn push_footer() {
+        let fetched = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_586_597_400);
+        let footer = |fetched| {
+            let mut sink = Vec::new();
+            super::push_footer(&plain_settings(), &mut sink, "https://example.com", fetched)
+                .unwrap();
+            String::from_utf8(sink).unwrap()
+        };
+        assert_eq!(
+            footer(Some(fetched)),
+            "\nSource: https://example.com, fetched 2020-04-11 09:30:00 UTC\n"
+        );
+        assert_eq!(footer(None), "\nSource: https://example.com\n");
+    }
+
+    #[test]
+    fn push_separator() {
+        let separate = |document_separator, first| {
+            let settings = Settings {
+                document_separator,
+                ..plain_settings()
+            };
+            let mut sink = Vec::new();
+            super::push_separator(&settings, &mut sink, "foo.md", first).unwrap();
+            String::from_utf8(sink).unwrap()
+        };
+        let width = TerminalSize::default().width;
+        assert_eq!(separate(DocumentSeparator::None, false), "");
+        assert_eq!(separate(DocumentSeparator::Rule, true), "");
+        assert_eq!(
+            separate(DocumentSeparator::Rule, false),
+            format!("\n{}\n\n", "\u{2550}".repeat(width))
+        );
+        assert_eq!(
+            separate(DocumentSeparator::Filename, true),
+            "\u{2504}foo.md\n\n"
+        );
+        assert_eq!(
+            separate(DocumentSeparator::Filename, false),
+            "\n\u{2504}foo.md\n\n"
+        );
+        assert_eq!(separate(DocumentSeparator::FormFeed, true), "");
+        assert_eq!(separate(DocumentSeparator::FormFeed, false), "\x0c");
+    }
+
+    #[test]
+    fn highlight_code_with_theme_colours() {
+        let markdown = "```rust\nfn main() {}\n```\n";
+        let mut settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::TrueColor)),
+                ..TerminalCapabilities::ansi()
+            },
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert!(!result.contains("\x1b[38;2;"), "{:?}", result);
+
+        settings.theme = ThemeSet::load_defaults()
+            .themes
+            .remove("InspiredGitHub")
+            .map(Arc::new);
+        let result = render_with(&settings, markdown);
+        assert!(result.contains("\x1b[38;2;"), "{:?}", result);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn report_image_warnings() {
+        let warnings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = warnings.clone();
+        let settings = Settings {
+            terminal_capabilities: TerminalCapabilities {
+                image: ImageCapability::ITerm2(ITerm2Images),
+                ..TerminalCapabilities::none()
+            },
+            warnings: Some(WarningHandler::new(move |warning| {
+                collected.lock().unwrap().push(warning.clone())
+            })),
+            ..plain_settings()
+        };
+        let markdown = "![a](/does-not-exist.png) ![b](https://example.com/b.png)";
+        render_with(&settings, markdown);
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        match warnings[0] {
+            Warning::ImageFailed {
+                ref url,
+                ref causes,
+            } => {
+                assert_eq!(url.as_str(), "file:///does-not-exist.png");
+                assert!(!causes.is_empty());
+            }
+            ref other => panic!("Unexpected warning: {:?}", other),
+        }
+        assert_eq!(
+            warnings[1],
+            Warning::RemoteImageSkipped(Url::parse("https://example.com/b.png").unwrap())
+        );
+    }
+
+    #[test]
+    fn skip_frontmatter() {
+        let markdown = "---\nKey: value\n---\n\nText.\n";
+        let settings = Settings {
+            skip_frontmatter: true,
+            ..plain_settings()
+        };
+        let result = render_with(&settings, markdown);
+        assert_eq!(result, "Text.\n");
+    }
+
+    #[test]
+    fn render_rule_and_heading_without_skip_frontmatter() {
+        let markdown = "---\nKey: value\n---\n\nText.\n";
+        let result = render_with(&plain_settings(), markdown);
+        assert!(
+            result.contains("\u{2504}\u{2504}Key: value\n"),
+            "{:?}",
+            result
+        );
+        assert!(result.ends_with("Text.\n"), "{:?}", result);
+    }
+
+    #[test]
+    fn pipeline_passes() {
+        let pipeline = Pipeline::new()
+            .with_pass(PassPosition::First, |event| match event {
+                Event::Text(text) => vec![Event::Text(text.replace("hunter2", "***").into())],
+                event => vec![event],
+            })
+            .with_pass(PassPosition::Last, |event| match event {
+                Event::Start(Tag::Paragraph) => {
+                    vec![Event::Start(Tag::Paragraph), Event::Text("Note: ".into())]
+                }
+                event => vec![event],
+            });
+        let mut sink = Vec::new();
+        let markdown = "The password is hunter2.";
+        pipeline
+            .push_tty(
+                &plain_settings(),
+                &mut sink,
+                Path::new("/"),
+                Parser::new(markdown),
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(sink).unwrap(),
+            "Note: The password is ***.\n"
+        );
+    }
+
+    #[test]
+    fn pipeline_passes_see_whole_document_before_writing() {
+        use std::sync::atomic::AtomicUsize;
+
+        /// A writer which records how many events passed at its first write.
+        struct Probe {
+            passed: Arc<AtomicUsize>,
+            passed_at_first_write: Option<usize>,
+        }
+
+        impl Write for Probe {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.passed_at_first_write.is_none() {
+                    self.passed_at_first_write = Some(self.passed.load(Ordering::SeqCst));
+                }
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let passed = Arc::new(AtomicUsize::new(0));
+        let counter = passed.clone();
+        let pipeline = Pipeline::new().with_pass(PassPosition::Last, move |event| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            vec![event]
+        });
+        let markdown = "# Foo\n\nBar\n\nBaz\n";
+        let mut probe = Probe {
+            passed: passed.clone(),
+            passed_at_first_write: None,
+        };
+        pipeline
+            .push_tty(
+                &plain_settings(),
+                &mut probe,
+                Path::new("/"),
+                Parser::new(markdown),
+            )
+            .unwrap();
+        let total = Parser::new(markdown).count();
+        assert_eq!(passed.load(Ordering::SeqCst), total);
+        assert_eq!(probe.passed_at_first_write, Some(total));
     }
 }
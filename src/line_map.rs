@@ -0,0 +1,265 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Map offsets in the source of a document to lines of rendered output.
+
+use pulldown_cmark::{Event, Tag};
+use std::io::{Error, ErrorKind, Result, Write};
+
+/// A map from offsets in the source of a document to lines of output.
+///
+/// Maps the start of every block, e.g. headings, paragraphs, or list items, to
+/// the line of output the block starts at.  Editors can use this map to
+/// scroll a preview to the block at the cursor.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LineMap {
+    entries: Vec<(usize, usize)>,
+}
+
+impl LineMap {
+    /// Pairs of source offsets and output lines, ordered by source offset.
+    ///
+    /// Lines count from 0.
+    pub fn entries(&self) -> &[(usize, usize)] {
+        &self.entries
+    }
+
+    /// The output line of the block at the given source `offset`.
+    ///
+    /// Return the line of the last block which starts at or before `offset`,
+    /// or `None` if `offset` comes before the first block.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .take_while(|(start, _)| *start <= offset)
+            .last()
+            .map(|(_, line)| *line)
+    }
+
+    /// Map the block starting at the source `offset` to the output `line`.
+    pub(crate) fn insert(&mut self, offset: usize, line: usize) {
+        self.entries.push((offset, line));
+    }
+}
+
+/// Whether `event` starts a block to record in the line map.
+///
+/// Ignore tables and lists: we buffer tables, and list items start
+/// on the line after the start of the list.
+///
+/// Match all tags without a wildcard, to decide about new tags of future
+/// pulldown-cmark versions explicitly.
+pub(crate) fn starts_block(event: &Event<'_>) -> bool {
+    match event {
+        Event::Start(tag) => match tag {
+            Tag::Paragraph
+            | Tag::Heading(_)
+            | Tag::BlockQuote
+            | Tag::CodeBlock(_)
+            | Tag::Item
+            | Tag::FootnoteDefinition(_) => true,
+            Tag::List(_)
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow
+            | Tag::TableCell
+            | Tag::Emphasis
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Link(..)
+            | Tag::Image(..) => false,
+        },
+        _ => false,
+    }
+}
+
+/// A writer which counts the lines written.
+pub(crate) struct LineCounter<'a, W: Write> {
+    inner: &'a mut W,
+    /// The number of line breaks written so far.
+    pub lines: usize,
+}
+
+impl<'a, W: Write> LineCounter<'a, W> {
+    /// Count lines written to `inner`.
+    pub fn new(inner: &'a mut W) -> Self {
+        LineCounter { inner, lines: 0 }
+    }
+}
+
+impl<'a, W: Write> Write for LineCounter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|b| **b == b'\n').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer which drops all lines after a maximum number of lines.
+pub(crate) struct LineLimit<'a, W: Write> {
+    inner: &'a mut W,
+    max_lines: usize,
+    /// The number of line breaks written so far, including dropped ones.
+    lines: usize,
+    /// Whether the last line written has not ended yet.
+    partial: bool,
+}
+
+impl<'a, W: Write> LineLimit<'a, W> {
+    /// Write at most `max_lines` lines to `inner`.
+    pub fn new(inner: &'a mut W, max_lines: usize) -> Self {
+        LineLimit {
+            inner,
+            max_lines,
+            lines: 0,
+            partial: false,
+        }
+    }
+
+    /// The number of lines dropped so far.
+    pub fn dropped_lines(&self) -> usize {
+        let lines = if self.partial {
+            self.lines + 1
+        } else {
+            self.lines
+        };
+        lines.saturating_sub(self.max_lines)
+    }
+}
+
+impl<'a, W: Write> Write for LineLimit<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let remaining = self.max_lines.saturating_sub(self.lines);
+        // Write everything up to and including the last line break we may
+        // write, or all of buf if it has fewer line breaks.
+        let visible = if remaining == 0 {
+            0
+        } else {
+            buf.iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .nth(remaining - 1)
+                .map_or(buf.len(), |(index, _)| index + 1)
+        };
+        self.inner.write_all(&buf[..visible])?;
+        self.lines += buf.iter().filter(|b| **b == b'\n').count();
+        if let Some(last) = buf.last() {
+            self.partial = *last != b'\n';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer which fails once more than a maximum number of bytes were written.
+pub(crate) struct ByteLimit<'a, W: Write> {
+    inner: &'a mut W,
+    max_bytes: usize,
+    /// The number of bytes written so far.
+    bytes: usize,
+    /// Whether a write exceeded the maximum number of bytes.
+    exceeded: bool,
+}
+
+impl<'a, W: Write> ByteLimit<'a, W> {
+    /// Write at most `max_bytes` bytes to `inner`.
+    pub fn new(inner: &'a mut W, max_bytes: usize) -> Self {
+        ByteLimit {
+            inner,
+            max_bytes,
+            bytes: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Whether a write exceeded the maximum number of bytes.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<'a, W: Write> Write for ByteLimit<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.max_bytes < self.bytes + buf.len() {
+            self.exceeded = true;
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Output exceeds maximum size of {} bytes", self.max_bytes),
+            ));
+        }
+        let written = self.inner.write(buf)?;
+        self.bytes += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn line_at() {
+        let mut line_map = LineMap::default();
+        line_map.insert(0, 0);
+        line_map.insert(7, 2);
+        assert_eq!(line_map.line_at(0), Some(0));
+        assert_eq!(line_map.line_at(6), Some(0));
+        assert_eq!(line_map.line_at(100), Some(2));
+        line_map.entries.insert(0, (3, 0));
+        assert_eq!(line_map.line_at(1), None);
+    }
+
+    #[test]
+    fn count_lines() {
+        let mut sink = Vec::new();
+        let mut counter = LineCounter::new(&mut sink);
+        write!(counter, "foo\nbar\n\nbaz").unwrap();
+        assert_eq!(counter.lines, 3);
+    }
+
+    #[test]
+    fn limit_lines() {
+        let mut sink = Vec::new();
+        let mut limit = LineLimit::new(&mut sink, 2);
+        write!(limit, "foo\nbar").unwrap();
+        write!(limit, "\nbaz\nspam\n").unwrap();
+        write!(limit, "eggs").unwrap();
+        assert_eq!(limit.dropped_lines(), 3);
+        assert_eq!(String::from_utf8(sink).unwrap(), "foo\nbar\n");
+    }
+
+    #[test]
+    fn limit_bytes() {
+        let mut sink = Vec::new();
+        let mut limit = ByteLimit::new(&mut sink, 6);
+        write!(limit, "foo").unwrap();
+        write!(limit, "bar").unwrap();
+        assert!(!limit.exceeded());
+        assert!(write!(limit, "b").is_err());
+        assert!(limit.exceeded());
+        assert_eq!(String::from_utf8(sink).unwrap(), "foobar");
+    }
+}
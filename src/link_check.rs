@@ -0,0 +1,223 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Check whether links in documents are broken.
+
+use crate::resources::{check_url, timeout_until, HttpSettings};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Settings for checking links.
+#[derive(Debug, Clone)]
+pub struct LinkCheckSettings {
+    /// Settings for HTTP requests.
+    pub http: HttpSettings,
+    /// The maximum number of concurrent requests in total.
+    pub max_requests: usize,
+    /// The maximum number of concurrent requests to a single host.
+    ///
+    /// Keep this low, to not overload servers with requests.
+    pub max_requests_per_host: usize,
+    /// The timeout for every single request.
+    pub timeout: Duration,
+    /// The time budget for checking all links.
+    ///
+    /// Start no more requests once the budget is spent, and leave all links
+    /// not yet checked unchecked.
+    pub budget: Duration,
+}
+
+impl Default for LinkCheckSettings {
+    fn default() -> Self {
+        LinkCheckSettings {
+            http: HttpSettings::default(),
+            max_requests: 8,
+            max_requests_per_host: 2,
+            timeout: Duration::from_secs(10),
+            budget: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The result of checking a link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkStatus {
+    /// The link is fine.
+    Ok,
+    /// The link is broken, with the reason.
+    Broken(String),
+    /// The link was not checked, either because mdcat doesn't know how to
+    /// check it, or because the time budget was spent.
+    Unchecked,
+}
+
+/// The pending links, and the number of requests in flight per host.
+struct Queue {
+    pending: VecDeque<Url>,
+    in_flight: HashMap<String, usize>,
+}
+
+/// The host to limit concurrent requests for.
+fn host_of(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_ascii_lowercase()
+}
+
+/// Take the next link from `queue` whose host has less than `max_per_host`
+/// requests in flight.
+fn take_next(queue: &mut Queue, max_per_host: usize) -> Option<Url> {
+    let in_flight = &queue.in_flight;
+    let available = |url: &Url| in_flight.get(&host_of(url)).cloned().unwrap_or(0) < max_per_host;
+    let index = queue.pending.iter().position(available)?;
+    let url = queue.pending.remove(index)?;
+    *queue.in_flight.entry(host_of(&url)).or_insert(0) += 1;
+    Some(url)
+}
+
+/// Check `urls`, and return the status of every URL, in order.
+///
+/// Check every distinct URL only once, with at most
+/// `settings.max_requests` concurrent requests, and at most
+/// `settings.max_requests_per_host` concurrent requests to the same host.
+/// Leave URLs not checked within `settings.budget` unchecked, as well as URLs
+/// which are neither local files nor HTTP(S) URLs.
+pub fn check_links(urls: &[Url], settings: &LinkCheckSettings) -> Vec<(Url, LinkStatus)> {
+    let deadline = Instant::now() + settings.budget;
+    let mut pending = VecDeque::new();
+    for url in urls {
+        let supported = matches!(url.scheme(), "file" | "http" | "https");
+        if supported && !pending.contains(url) {
+            pending.push_back(url.clone());
+        }
+    }
+    let workers = settings.max_requests.max(1).min(pending.len());
+    let max_per_host = settings.max_requests_per_host.max(1);
+    let state = Arc::new((
+        Mutex::new(Queue {
+            pending,
+            in_flight: HashMap::new(),
+        }),
+        Condvar::new(),
+    ));
+    let results = Arc::new(Mutex::new(HashMap::new()));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let state = state.clone();
+            let results = results.clone();
+            let settings = settings.clone();
+            thread::spawn(move || {
+                let (queue, condvar) = &*state;
+                loop {
+                    let mut guard = queue.lock().unwrap();
+                    let url = loop {
+                        let now = Instant::now();
+                        if deadline <= now || guard.pending.is_empty() {
+                            return;
+                        }
+                        match take_next(&mut guard, max_per_host) {
+                            Some(url) => break url,
+                            None => {
+                                guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+                            }
+                        }
+                    };
+                    drop(guard);
+                    // Never wait for a request beyond the budget
+                    let timeout = timeout_until(Some(settings.timeout), deadline);
+                    let status = match check_url(&url, &settings.http, timeout) {
+                        Ok(_) => LinkStatus::Ok,
+                        Err(error) => LinkStatus::Broken(error.to_string()),
+                    };
+                    let mut guard = queue.lock().unwrap();
+                    if let Some(count) = guard.in_flight.get_mut(&host_of(&url)) {
+                        *count -= 1;
+                    }
+                    results.lock().unwrap().insert(url, status);
+                    condvar.notify_all();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        // Workers don't panic, except on poisoned locks, i.e. if another
+        // worker panicked before
+        handle.join().ok();
+    }
+    let results = results.lock().unwrap();
+    urls.iter()
+        .map(|url| {
+            let status = results.get(url).cloned().unwrap_or(LinkStatus::Unchecked);
+            (url.clone(), status)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[cfg(unix)]
+    fn check_local_files() {
+        let exists = Url::from_directory_path(env!("CARGO_MANIFEST_DIR"))
+            .unwrap()
+            .join("Cargo.toml")
+            .unwrap();
+        let missing = exists.join("does-not-exist.md").unwrap();
+        let mailto = Url::parse("mailto:foo@example.com").unwrap();
+        let urls = vec![exists.clone(), missing, mailto.clone(), exists.clone()];
+        let results = check_links(&urls, &LinkCheckSettings::default());
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], (exists.clone(), LinkStatus::Ok));
+        assert!(matches!(results[1].1, LinkStatus::Broken(_)));
+        assert_eq!(results[2], (mailto, LinkStatus::Unchecked));
+        assert_eq!(results[3], (exists, LinkStatus::Ok));
+    }
+
+    #[test]
+    fn leave_links_unchecked_without_budget() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let settings = LinkCheckSettings {
+            budget: Duration::from_secs(0),
+            ..LinkCheckSettings::default()
+        };
+        assert_eq!(
+            check_links(std::slice::from_ref(&url), &settings),
+            vec![(url, LinkStatus::Unchecked)]
+        );
+    }
+
+    #[test]
+    fn limit_requests_per_host() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let mut queue = Queue {
+            pending: vec![
+                url("https://example.com/a"),
+                url("https://EXAMPLE.com/b"),
+                url("https://example.org/c"),
+            ]
+            .into_iter()
+            .collect(),
+            in_flight: HashMap::new(),
+        };
+        assert_eq!(take_next(&mut queue, 1), Some(url("https://example.com/a")));
+        assert_eq!(take_next(&mut queue, 1), Some(url("https://example.org/c")));
+        assert_eq!(take_next(&mut queue, 1), None);
+        *queue.in_flight.get_mut("example.com").unwrap() -= 1;
+        assert_eq!(take_next(&mut queue, 1), Some(url("https://EXAMPLE.com/b")));
+    }
+}
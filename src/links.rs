@@ -0,0 +1,57 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Links and images in rendered documents.
+
+use url::Url;
+
+/// Whether a link is a link or an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A link.
+    Link,
+    /// An image.
+    Image,
+    /// The permalink of a heading.
+    Permalink,
+}
+
+/// How mdcat wrote a link or image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkPlacement {
+    /// Written inline, as a clickable link or an inline image.
+    Inline,
+    /// Written as reference with the given index, e.g. `[2]`, followed by the
+    /// link destination at the end of the section.
+    Reference(usize),
+    /// Written as plain text, e.g. for autolinks, or images the terminal
+    /// could not show.
+    Text,
+}
+
+/// A link or image in a rendered document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedLink {
+    /// Whether this is a link or an image.
+    pub kind: LinkKind,
+    /// The destination of the link, as written in the document.
+    pub destination: String,
+    /// The absolute URL of the destination.
+    ///
+    /// Resolve relative destinations against the base directory of the
+    /// document.  `None` if the destination is neither a URL nor a path.
+    pub url: Option<Url>,
+    /// How mdcat wrote the link.
+    pub placement: LinkPlacement,
+}
@@ -24,6 +24,11 @@ pub fn is_svg(mime: &Mime) -> bool {
     mime.type_() == mime::IMAGE && mime.subtype().as_str() == "svg"
 }
 
+/// Whether the given MIME type denotes a GIF image.
+pub fn is_gif(mime: &Mime) -> bool {
+    *mime == mime::IMAGE_GIF
+}
+
 /// Detect mime type with `file`.
 pub fn detect_mime_type(buffer: &[u8]) -> Result<Mime, Box<dyn std::error::Error>> {
     let mut process = Command::new("file")
@@ -73,6 +78,12 @@ mod tests {
         assert_eq!(result.unwrap(), mime::IMAGE_PNG);
     }
 
+    #[test]
+    fn is_gif() {
+        assert!(super::is_gif(&mime::IMAGE_GIF));
+        assert!(!super::is_gif(&mime::IMAGE_PNG));
+    }
+
     #[test]
     fn detect_mimetype_of_svg_image() {
         let data = include_bytes!("../sample/rust-logo.svg");
@@ -18,113 +18,1440 @@
 
 use clap::{value_t, values_t};
 use mdcat::Settings;
-use pulldown_cmark::{Options, Parser};
+use pulldown_cmark::{Event, Options, Parser, Tag};
 use std::error::Error;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
-use std::io::{stdin, stdout};
-use std::path::PathBuf;
+use std::io::{stdout, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
+use url::Url;
 
-use mdcat::{ResourceAccess, TerminalCapabilities, TerminalSize};
+use mdcat::{
+    set_clipboard, CodeOverflow, DocumentSeparator, FootnoteMarker, HeadingStyle, HttpAuth,
+    HttpHost, HttpSettings, ImageCache, ImageCapability, LinkCapability, LinkCheckSettings,
+    LinkKind, LinkStatus, LinkTitles, LocalFileAccess, MarkCapability, PageBreak, ParagraphIndent,
+    ParagraphStyle, ReferenceFlushPolicy, ReferenceNumbering, ReferenceOverflow, ResetGuard,
+    ResourceAccess, RuleStyle, StripEscapes, Style, StyleSheet, StyleTier, Symbols, TableStyle,
+    TerminalCapabilities, TerminalSize, TextAlignment, Warning, WarningHandler,
+};
 
-/// Read input for `filename`.
-///
-/// If `filename` is `-` read from standard input, otherwise try to open and
-/// read the given file.
-fn read_input<T: AsRef<str>>(filename: T) -> std::io::Result<(PathBuf, String)> {
-    let cd = std::env::current_dir()?;
-    let mut buffer = String::new();
+use crate::config::{
+    parse_colour, Config, HeadingConfig, HttpHostConfig, RuleConfig, SymbolsConfig, TableConfig,
+};
+use crate::input::{expand_globs, parse_encoding, read_input, InputOptions};
+use anstyle::{AnsiColor, Color};
+
+mod config;
+mod input;
+
+/// The markdown extensions mdcat enables.
+fn parser_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options
+}
 
-    if filename.as_ref() == "-" {
-        stdin().read_to_string(&mut buffer)?;
-        Ok((cd, buffer))
+/// Print or copy a code block, for `mdcat snippet`.
+fn print_snippet(matches: &clap::ArgMatches<'_>) -> Result<(), Box<dyn Error>> {
+    let filename = matches.value_of("file").unwrap_or("-");
+    let number = value_t!(matches, "number", usize)?;
+    let (_, input) = read_input(filename, InputOptions::default())?;
+    let snippets = mdcat::snippets(Parser::new_ext(&input, parser_options()));
+    let snippet = number
+        .checked_sub(1)
+        .and_then(|index| snippets.get(index))
+        .ok_or_else(|| format!("No snippet {}, found {} snippets", number, snippets.len()))?;
+    if matches.is_present("copy") {
+        set_clipboard(&mut stdout(), &snippet.code)?;
     } else {
-        let mut source = File::open(filename.as_ref())?;
-        source.read_to_string(&mut buffer)?;
-        let base_dir = cd
-            .join(filename.as_ref())
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or(cd);
-        Ok((base_dir, buffer))
+        stdout().write_all(snippet.code.as_bytes())?;
     }
+    Ok(())
 }
 
-fn process_file(
+/// Split a `filename` like `README.md#installation` into the name of the file
+/// and the slug of a section.
+///
+/// Do not split if a file with the whole `filename` exists.
+fn split_section(filename: &str) -> (&str, Option<&str>) {
+    match filename.rfind('#') {
+        Some(index) if !Path::new(filename).exists() => {
+            (&filename[..index], Some(&filename[index + 1..]))
+        }
+        _ => (filename, None),
+    }
+}
+
+fn process_file<W: Write>(
+    writer: &mut W,
     filename: &str,
+    input_options: InputOptions,
     settings: &Settings,
     dump_events: bool,
+    footer: bool,
+    deterministic: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let (base_dir, input) = read_input(filename)?;
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    let parser = Parser::new_ext(&input, options);
+    let (filename, section) = split_section(filename);
+    let (base_dir, input) = read_input(filename, input_options)?;
+    let fetched = SystemTime::now();
+    let events: Vec<Event> = mdcat::parse(&input, parser_options(), settings).collect();
+    let events = match section {
+        Some(slug) => mdcat::slug::section(events, slug)
+            .ok_or_else(|| format!("No section {} in {}", slug, filename))?,
+        None => events,
+    };
 
     if dump_events {
-        mdcat::dump_events(&mut std::io::stdout(), parser)?;
+        mdcat::dump_events(writer, events.into_iter())?;
+    } else {
+        let mut guard = ResetGuard::new(&mut *writer);
+        mdcat::push_tty(settings, &mut guard, &base_dir, events.into_iter())?;
+        guard.finish()?;
+        if footer {
+            let source = if filename == "-" {
+                "standard input".to_string()
+            } else if deterministic {
+                filename.to_string()
+            } else {
+                std::env::current_dir()?
+                    .join(filename)
+                    .display()
+                    .to_string()
+            };
+            let fetched = if deterministic { None } else { Some(fetched) };
+            mdcat::push_footer(settings, writer, &source, fetched)?;
+        }
+    }
+    Ok(())
+}
+
+/// Preview the file at `filename` for the previewer of a file manager.
+///
+/// Load syntax definitions and the theme with the given `theme_name` into
+/// `settings` only if the file has code blocks, to start as fast as possible.
+fn preview_file<W: Write>(
+    writer: &mut W,
+    filename: &str,
+    input_options: InputOptions,
+    theme_name: Option<&ThemeName>,
+    mut settings: Settings,
+) -> Result<(), Box<dyn Error>> {
+    let (base_dir, input) = read_input(filename, input_options)?;
+    let has_code_blocks = Parser::new_ext(&input, parser_options())
+        .any(|event| matches!(event, Event::Start(Tag::CodeBlock(_))));
+    if has_code_blocks {
+        settings.syntax_set = Arc::new(SyntaxSet::load_defaults_newlines());
+        if let Some(name) = theme_name {
+            settings.theme = load_theme(name)?.map(Arc::new);
+        }
+    }
+    let parser = mdcat::parse(&input, parser_options(), &settings);
+    let mut guard = ResetGuard::new(writer);
+    mdcat::push_tty(&settings, &mut guard, &base_dir, parser)?;
+    guard.finish()?;
+    Ok(())
+}
+
+/// How to report problems which do not stop rendering.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum WarningLevel {
+    /// Do not report problems at all.
+    Quiet,
+    /// Print a warning with the URL of the resource for every problem to
+    /// standard error.
+    Normal,
+    /// Print warnings with the URL and all causes of every problem to
+    /// standard error, and tell about images skipped in local mode.
+    Verbose,
+}
+
+/// A warning handler which prints warnings to standard error according to
+/// the warning `level`.
+fn warning_handler(level: WarningLevel) -> Option<WarningHandler> {
+    match level {
+        WarningLevel::Quiet => None,
+        WarningLevel::Normal => Some(WarningHandler::new(|warning| {
+            if let Warning::ImageFailed { url, .. } = warning {
+                eprintln!("Warning: Cannot show image {}", url);
+            }
+        })),
+        WarningLevel::Verbose => Some(WarningHandler::new(|warning| {
+            eprintln!("Warning: {}", warning);
+        })),
+    }
+}
+
+/// Check the links and images in the file at `filename`.
+///
+/// Render the file without output to find all links, and check them with
+/// `link_check`.  Write every broken link to `writer`, and return the number
+/// of broken links.  Check only links which `settings` permit access to, and
+/// never request any other link.
+fn check_file_links<W: Write>(
+    writer: &mut W,
+    filename: &str,
+    input_options: InputOptions,
+    settings: &Settings,
+    link_check: &LinkCheckSettings,
+    warnings: WarningLevel,
+) -> Result<usize, Box<dyn Error>> {
+    let (base_dir, input) = read_input(filename, input_options)?;
+    let parser = mdcat::parse(&input, parser_options(), settings);
+    let links = mdcat::push_tty_with_links(settings, &mut std::io::sink(), &base_dir, parser)?;
+    let mut urls: Vec<Url> = Vec::new();
+    for link in links {
+        match link.url {
+            Some(url) if link.kind != LinkKind::Permalink && !urls.contains(&url) => urls.push(url),
+            _ => {}
+        }
+    }
+    urls.retain(|url| settings.resource_access.permits(url));
+    let mut broken = 0;
+    for (url, status) in mdcat::check_links(&urls, link_check) {
+        match status {
+            LinkStatus::Ok => {}
+            LinkStatus::Broken(reason) => {
+                broken += 1;
+                writeln!(writer, "{}: {}: {}", filename, url, reason)?;
+            }
+            LinkStatus::Unchecked => {
+                if warnings == WarningLevel::Verbose {
+                    eprintln!("Warning: {}: Did not check {}", filename, url);
+                }
+            }
+        }
+    }
+    Ok(broken)
+}
+
+/// Open the terminal to read keys from, even if standard input is a file.
+fn open_terminal() -> std::io::Result<File> {
+    if cfg!(windows) {
+        File::open("CONIN$")
+    } else {
+        File::open("/dev/tty")
+    }
+}
+
+/// The terminal in raw mode, to read single key presses.
+///
+/// Restore the previous mode of the terminal when dropped, i.e. also when
+/// presenting slides fails or panics.
+struct RawMode {
+    /// The previous mode of the terminal, as printed by `stty -g`.
+    previous: Option<String>,
+}
+
+impl RawMode {
+    /// Disable line buffering, echo and signals of the terminal with `stty`.
+    ///
+    /// Do nothing on Windows, which has no `stty`; the Windows console keeps
+    /// buffering lines.
+    fn enable() -> Result<RawMode, Box<dyn Error>> {
+        if cfg!(windows) {
+            return Ok(RawMode { previous: None });
+        }
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(open_terminal()?)
+            .output()?;
+        let previous = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(RawMode {
+            previous: Some(previous),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(ref previous) = self.previous {
+            // We can't do anything about errors while dropping
+            let _ = stty(&[previous]);
+        }
+    }
+}
+
+/// Run `stty` with `args` on the terminal.
+fn stty(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("stty")
+        .args(args)
+        .stdin(open_terminal()?)
+        .status()?;
+    if status.success() {
+        Ok(())
     } else {
-        mdcat::push_tty(settings, &mut stdout(), &base_dir, parser)?;
+        Err(format!("stty failed with {}", status).into())
+    }
+}
+
+/// A key to navigate slides.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SlideKey {
+    /// Go to the next step or slide.
+    Next,
+    /// Go back to the previous slide.
+    Previous,
+    /// Stop presenting.
+    Quit,
+}
+
+/// Read single keys from `keys` until a key to navigate slides.
+///
+/// Space, Enter, `n` and `j` go to the next step or slide, `p` and `k` back
+/// to the previous slide, and `q`, Ctrl-C, Ctrl-D and the end of `keys` quit.
+/// Ignore all other keys, e.g. the bytes of escape sequences.
+fn read_slide_key<R: Read>(keys: &mut R) -> std::io::Result<SlideKey> {
+    let mut key = [0];
+    loop {
+        if keys.read(&mut key)? == 0 {
+            return Ok(SlideKey::Quit);
+        }
+        match key[0] {
+            b' ' | b'\r' | b'\n' | b'n' | b'j' => return Ok(SlideKey::Next),
+            b'p' | b'k' => return Ok(SlideKey::Previous),
+            // Raw mode turns Ctrl-C and Ctrl-D into plain bytes
+            b'q' | 0x03 | 0x04 => return Ok(SlideKey::Quit),
+            _ => {}
+        }
+    }
+}
+
+/// Read keys to navigate slides from `terminal` in a background thread.
+///
+/// Return a receiver for all keys, see [`read_slide_key`].  Stop reading
+/// after the first error, which the receiver gets as well, after quitting,
+/// and when the receiver is gone.
+fn read_keys_in_background(mut terminal: File) -> Receiver<std::io::Result<SlideKey>> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || loop {
+        let key = read_slide_key(&mut terminal);
+        let done = match key {
+            Ok(SlideKey::Next) | Ok(SlideKey::Previous) => false,
+            Ok(SlideKey::Quit) | Err(_) => true,
+        };
+        if sender.send(key).is_err() || done {
+            break;
+        }
+    });
+    receiver
+}
+
+/// Write a footer with the number of the slide, the number of slides and the
+/// `elapsed` time into the last line of the terminal.
+///
+/// Save and restore the cursor around the footer, to leave the cursor after
+/// the slide.
+fn write_slide_footer<W: Write>(
+    writer: &mut W,
+    height: usize,
+    slide: usize,
+    slides: usize,
+    elapsed: Duration,
+) -> std::io::Result<()> {
+    let seconds = elapsed.as_secs();
+    write!(
+        writer,
+        "\x1b7\x1b[{};1H\x1b[2K{}/{}  {:02}:{:02}\x1b8",
+        height,
+        slide,
+        slides,
+        seconds / 60,
+        seconds % 60
+    )
+}
+
+/// Present the file at `filename` as slides.
+///
+/// Clear the screen for every step of a slide, and center the slide
+/// vertically.  If `footer` is true show the number of the slide and the time
+/// since the start of the presentation in the last line, and update the time
+/// every second.  Put the terminal into raw mode, and read single keys after
+/// every step, see [`read_slide_key`].  Restore the terminal when done.
+///
+/// If `follow_size` is true poll the size of the terminal, and render the
+/// current step again for the new size when the terminal was resized.
+///
+/// Append the speaker notes of every slide to the file at `notes`, if any,
+/// e.g. to follow them with `tail -f` in another terminal.
+fn present_file<W: Write>(
+    writer: &mut W,
+    filename: &str,
+    input_options: InputOptions,
+    settings: &Settings,
+    notes: Option<&str>,
+    footer: bool,
+    follow_size: bool,
+) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let (base_dir, input) = read_input(filename, input_options)?;
+    let events: Vec<Event> = mdcat::parse(&input, parser_options(), settings).collect();
+    let slides = mdcat::slides::split_slides(events);
+    let _raw_mode = RawMode::enable()?;
+    let keys = read_keys_in_background(open_terminal()?);
+    let mut notes = notes
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose()?;
+    let mut settings = settings.clone();
+    let render = |settings: &Settings, events: &[Event]| -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut rendered = Vec::new();
+        mdcat::push_tty(settings, &mut rendered, &base_dir, events.iter().cloned())?;
+        Ok(rendered)
+    };
+    // Center the whole slide, to keep content in place across steps
+    let center = |settings: &Settings, slide: &[Event]| -> Result<usize, Box<dyn Error>> {
+        let lines = render(settings, slide)?
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count();
+        Ok(settings.terminal_size.height.saturating_sub(lines + 1) / 2)
+    };
+    let mut index = 0;
+    'slides: while index < slides.len() {
+        let slide = &slides[index];
+        if let Some(ref mut notes) = notes {
+            writeln!(notes, "--- Slide {}/{} ---", index + 1, slides.len())?;
+            for note in &slide.notes {
+                writeln!(notes, "{}\n", note)?;
+            }
+        }
+        let steps = slide.steps();
+        let mut padding = center(&settings, &slide.events)?;
+        let mut next = index + 1;
+        let mut step = 0;
+        'steps: while step < steps.len() {
+            let height = settings.terminal_size.height;
+            write!(writer, "\x1b[2J\x1b[H{}", "\n".repeat(padding))?;
+            writer.write_all(&render(&settings, &steps[step])?)?;
+            if footer {
+                let elapsed = started.elapsed();
+                write_slide_footer(writer, height, index + 1, slides.len(), elapsed)?;
+            }
+            writer.flush()?;
+            let key = loop {
+                // Wake up regularly to follow the terminal size and tick the
+                // clock in the footer while waiting for a key
+                match keys.recv_timeout(Duration::from_millis(250)) {
+                    Ok(key) => break key?,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if follow_size {
+                            let size = TerminalSize::detect();
+                            if let Some(size) = size.filter(|size| *size != settings.terminal_size)
+                            {
+                                settings.terminal_size = size;
+                                padding = center(&settings, &slide.events)?;
+                                continue 'steps;
+                            }
+                        }
+                        if footer {
+                            let elapsed = started.elapsed();
+                            write_slide_footer(writer, height, index + 1, slides.len(), elapsed)?;
+                            writer.flush()?;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break SlideKey::Quit,
+                }
+            };
+            match key {
+                SlideKey::Quit => break 'slides,
+                SlideKey::Previous => {
+                    next = index.saturating_sub(1);
+                    break;
+                }
+                SlideKey::Next => step += 1,
+            }
+        }
+        index = next;
     }
+    write!(writer, "\x1b[2J\x1b[H")?;
     Ok(())
 }
 
+/// Render the built-in self test document to `writer`.
+///
+/// Write the image of the document to a temporary directory to show it.
+fn render_self_test<W: Write>(writer: &mut W, settings: &Settings) -> Result<(), Box<dyn Error>> {
+    let base_dir = std::env::temp_dir().join("mdcat-self-test");
+    std::fs::create_dir_all(&base_dir)?;
+    std::fs::write(
+        base_dir.join("rust-logo.png"),
+        &include_bytes!("../sample/rust-logo-128x128.png")[..],
+    )?;
+    let parser = mdcat::parse(
+        include_str!("../sample/self-test.md"),
+        parser_options(),
+        settings,
+    );
+    mdcat::push_tty(settings, writer, &base_dir, parser)
+}
+
+/// Parse a URL, e.g. for `--permalinks`.
+fn parse_url(value: &str) -> clap::Result<Url> {
+    Url::parse(value).map_err(|error| {
+        clap::Error::with_description(
+            &format!("Invalid URL {}: {}", value, error),
+            clap::ErrorKind::InvalidValue,
+        )
+    })
+}
+
+/// Copy the permalink of a heading to the clipboard, for `--copy-anchor`.
+fn copy_anchor(matches: &clap::ArgMatches<'_>) -> Result<(), Box<dyn Error>> {
+    let base_url = parse_url(matches.value_of("permalinks").unwrap_or_default())?;
+    let slug = matches.value_of("copy_anchor").unwrap_or_default();
+    let url = mdcat::slug::permalink(&base_url, slug);
+    set_clipboard(&mut stdout(), url.as_str())?;
+    println!("{}", url);
+    Ok(())
+}
+
+/// Parse the value of `--page-break`.
+///
+/// Accept `none`, `form-feed`, or a number of blank lines.
+fn parse_page_break(value: &str) -> clap::Result<PageBreak> {
+    match value {
+        "none" => Ok(PageBreak::None),
+        "form-feed" => Ok(PageBreak::FormFeed),
+        lines => lines
+            .parse::<usize>()
+            .map(PageBreak::BlankLines)
+            .map_err(|_| {
+                clap::Error::with_description(
+                    &format!(
+                        "Invalid page break {}, expected none, form-feed or a number of lines",
+                        value
+                    ),
+                    clap::ErrorKind::InvalidValue,
+                )
+            }),
+    }
+}
+
+/// Parse the value of `--long-links`.
+fn parse_long_links(value: &str) -> clap::Result<ReferenceOverflow> {
+    match value {
+        "wrap" => Ok(ReferenceOverflow::Wrap),
+        "ellipsize" => Ok(ReferenceOverflow::Ellipsize),
+        "overflow" => Ok(ReferenceOverflow::Overflow),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid long links mode {}, expected wrap, ellipsize or overflow",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Parse the value of `--reference-numbering`.
+fn parse_reference_numbering(value: &str) -> clap::Result<ReferenceNumbering> {
+    match value {
+        "document" => Ok(ReferenceNumbering::Document),
+        "section" => Ok(ReferenceNumbering::Section),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid reference numbering {}, expected document or section",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Parse the value of `--flush-references`.
+fn parse_flush_references(value: &str) -> clap::Result<ReferenceFlushPolicy> {
+    match value {
+        "section" => Ok(ReferenceFlushPolicy::PerSection),
+        "end" => Ok(ReferenceFlushPolicy::EndOfDocument),
+        "paragraph" => Ok(ReferenceFlushPolicy::AfterParagraph),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid reference flush policy {}, expected section, end or paragraph",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Parse the value of `--link-titles`.
+fn parse_link_titles(value: &str) -> clap::Result<LinkTitles> {
+    match value {
+        "plain" => Ok(LinkTitles::Plain),
+        "quoted" => Ok(LinkTitles::Quoted),
+        "tooltip" => Ok(LinkTitles::Tooltip),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid link titles {}, expected plain, quoted or tooltip",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Parse the value of `--footnote-marks`.
+fn parse_footnote_marks(value: &str) -> clap::Result<FootnoteMarker> {
+    match value {
+        "superscript" => Ok(FootnoteMarker::Superscript),
+        "brackets" => Ok(FootnoteMarker::Bracketed),
+        "asterisks" => Ok(FootnoteMarker::Asterisks),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid footnote marks {}, expected superscript, brackets or asterisks",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Parse the value of `--separator`.
+fn parse_separator(value: &str) -> clap::Result<DocumentSeparator> {
+    match value {
+        "none" => Ok(DocumentSeparator::None),
+        "rule" => Ok(DocumentSeparator::Rule),
+        "filename" => Ok(DocumentSeparator::Filename),
+        "form-feed" => Ok(DocumentSeparator::FormFeed),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid separator {}, expected none, rule, filename or form-feed",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Flags which the configuration file can turn on, along with the name and the
+/// long name of the flag which turns them off again.
+const NEGATABLE_FLAGS: &[(&str, &str, &str)] = &[
+    ("dedent_code", "no_dedent_code", "no-dedent-code"),
+    (
+        "code_background",
+        "no_code_background",
+        "no-code-background",
+    ),
+    ("figure_numbers", "no_figure_numbers", "no-figure-numbers"),
+    ("animations", "no_animations", "no-animations"),
+    (
+        "double_height_headings",
+        "no_double_height_headings",
+        "no-double-height-headings",
+    ),
+    (
+        "super_subscripts",
+        "no_super_subscripts",
+        "no-super-subscripts",
+    ),
+    ("abbreviations", "no_abbreviations", "no-abbreviations"),
+    ("sniff_code", "no_sniff_code", "no-sniff-code"),
+    (
+        "number_snippets",
+        "no_number_snippets",
+        "no-number-snippets",
+    ),
+    (
+        "show_html_comments",
+        "no_show_html_comments",
+        "no-show-html-comments",
+    ),
+    ("bidi_isolates", "no_bidi_isolates", "no-bidi-isolates"),
+    ("copy_mode", "no_copy_mode", "no-copy-mode"),
+    ("raw_blocks", "no_raw_blocks", "no-raw-blocks"),
+    (
+        "banner_headings",
+        "no_banner_headings",
+        "no-banner-headings",
+    ),
+    (
+        "skip_frontmatter",
+        "no_skip_frontmatter",
+        "no-skip-frontmatter",
+    ),
+    ("local_only", "no_local_only", "no-local"),
+    (
+        "restrict_local_images",
+        "no_restrict_local_images",
+        "no-restrict-local-images",
+    ),
+    ("insecure", "no_insecure", "no-insecure"),
+];
+
+/// Whether the flag `name` in `matches` is on.
+///
+/// Take the last of the flag and its negation in `NEGATABLE_FLAGS`, or
+/// `config` if neither was given.
+fn flag_or_config(matches: &clap::ArgMatches<'_>, name: &str, config: bool) -> bool {
+    let negation = NEGATABLE_FLAGS
+        .iter()
+        .find(|(flag, _, _)| *flag == name)
+        .and_then(|(_, negation, _)| matches.index_of(negation));
+    match (matches.index_of(name), negation) {
+        (Some(on), Some(off)) => off < on,
+        (Some(_), None) => true,
+        (None, Some(_)) => false,
+        (None, None) => config,
+    }
+}
+
+/// Parse the value of the argument `name` in `matches`, if given.
+fn optional_value<T: std::str::FromStr>(
+    matches: &clap::ArgMatches<'_>,
+    name: &str,
+) -> clap::Result<Option<T>> {
+    if matches.is_present(name) {
+        value_t!(matches, name, T).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parse the value of `--long-code`.
+fn parse_long_code(value: &str) -> clap::Result<CodeOverflow> {
+    match value {
+        "wrap" => Ok(CodeOverflow::Wrap),
+        "truncate" => Ok(CodeOverflow::Truncate),
+        "overflow" => Ok(CodeOverflow::Overflow),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid long code mode {}, expected wrap, truncate or overflow",
+                value
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// The name of a theme for syntax highlighting.
+#[derive(Debug, Clone, PartialEq)]
+enum ThemeName {
+    /// A theme from `--theme`, `$MDCAT_THEME` or the configuration file,
+    /// which mdcat must know.
+    Required(String),
+    /// A theme from `$BAT_THEME`, which mdcat ignores if it does not know it,
+    /// because bat has themes of its own.
+    Optional(String),
+}
+
+/// Pick the name of the theme for syntax highlighting.
+///
+/// Take the name of the theme from `--theme`, `$MDCAT_THEME`, the
+/// configuration file or `$BAT_THEME`, in this order.  Return `None` if no
+/// theme was given.
+///
+/// If `deterministic` ignore the environment.
+fn pick_theme_name(
+    explicit: Option<&str>,
+    config: Option<&str>,
+    deterministic: bool,
+) -> Option<ThemeName> {
+    let environment = |key| {
+        if deterministic {
+            None
+        } else {
+            std::env::var(key).ok()
+        }
+    };
+    explicit
+        .map(ToOwned::to_owned)
+        .or_else(|| environment("MDCAT_THEME"))
+        .or_else(|| config.map(ToOwned::to_owned))
+        .map(ThemeName::Required)
+        .or_else(|| environment("BAT_THEME").map(ThemeName::Optional))
+}
+
+/// Load the theme with the given `name`.
+///
+/// Fail for unknown required themes, and ignore unknown optional themes.
+fn load_theme(name: &ThemeName) -> clap::Result<Option<Theme>> {
+    let mut themes = ThemeSet::load_defaults().themes;
+    match name {
+        ThemeName::Required(name) => themes.remove(name).map(Some).ok_or_else(|| {
+            let names: Vec<&str> = themes.keys().map(String::as_str).collect();
+            clap::Error::with_description(
+                &format!(
+                    "Unknown theme {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                ),
+                clap::ErrorKind::InvalidValue,
+            )
+        }),
+        ThemeName::Optional(name) => Ok(themes.remove(name)),
+    }
+}
+
+/// Parse the colour `value` from the configuration file, or use `default`.
+fn colour_from_config(value: Option<&str>, default: AnsiColor) -> clap::Result<Color> {
+    match value {
+        None => Ok(default.into()),
+        Some(value) => parse_colour(value).ok_or_else(|| {
+            clap::Error::with_description(
+                &format!(
+                    "Invalid colour {}, expected a colour name, a number or #rrggbb",
+                    value
+                ),
+                clap::ErrorKind::InvalidValue,
+            )
+        }),
+    }
+}
+
+/// Create a heading style from the heading style in `config`.
+fn heading_style_from_config(config: &HeadingConfig) -> clap::Result<HeadingStyle> {
+    let colour = colour_from_config(config.colour.as_deref(), AnsiColor::Blue)?;
+    let mut style = Style::new().fg(colour);
+    if config.bold.unwrap_or(true) {
+        style = style.bold();
+    }
+    if config.italic {
+        style = style.italic();
+    }
+    if config.underline {
+        style = style.underline();
+    }
+    Ok(HeadingStyle {
+        style,
+        uppercase: config.uppercase,
+        adornment: config.adornment.unwrap_or(true),
+        indent: config.indent,
+    })
+}
+
+/// Create a rule style from the rule style in `config`, based on `default`.
+fn rule_style_from_config(config: &RuleConfig, default: RuleStyle) -> clap::Result<RuleStyle> {
+    let colour = config
+        .colour
+        .as_deref()
+        .map(|value| colour_from_config(Some(value), AnsiColor::Green))
+        .transpose()?;
+    Ok(RuleStyle {
+        symbol: config.symbol.clone().unwrap_or(default.symbol),
+        style: colour.map_or(default.style, |colour| Style::new().fg(colour)),
+        width: config.width,
+        centered: config.centered,
+    })
+}
+
+/// Create a table style from the table style in `config`, based on `default`.
+fn table_style_from_config(config: &TableConfig, default: TableStyle) -> clap::Result<TableStyle> {
+    let stripe = config
+        .stripe
+        .as_deref()
+        .map(|value| colour_from_config(Some(value), AnsiColor::Black))
+        .transpose()?;
+    Ok(TableStyle {
+        stripe: stripe.map(|colour| Style::new().on(colour)),
+        ..default
+    })
+}
+
+/// Parse the symbol `key` in the configuration file, with a `default`.
+fn symbol_from_config(key: &str, value: Option<&str>, default: char) -> clap::Result<char> {
+    let value = match value {
+        None => return Ok(default),
+        Some(value) => value,
+    };
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(symbol), None) => Ok(symbol),
+        _ => Err(clap::Error::with_description(
+            &format!(
+                "Invalid symbol {} for {}, expected a single character",
+                value, key
+            ),
+            clap::ErrorKind::InvalidValue,
+        )),
+    }
+}
+
+/// Create symbols from the symbols in `config`.
+fn symbols_from_config(config: &SymbolsConfig) -> clap::Result<Symbols> {
+    let default = Symbols::default();
+    let junctions = match config.junctions.as_deref() {
+        None => default.junctions,
+        Some(value) => {
+            let chars: Vec<char> = value.chars().collect();
+            if chars.len() != 9 {
+                return Err(clap::Error::with_description(
+                    &format!(
+                        "Invalid junctions {}, expected exactly nine characters",
+                        value
+                    ),
+                    clap::ErrorKind::InvalidValue,
+                ));
+            }
+            let mut junctions = default.junctions;
+            junctions.copy_from_slice(&chars);
+            junctions
+        }
+    };
+    let symbol =
+        |key, value: &Option<String>, default| symbol_from_config(key, value.as_deref(), default);
+    Ok(Symbols {
+        heading: symbol("heading", &config.heading, default.heading)?,
+        bullet: symbol("bullet", &config.bullet, default.bullet)?,
+        checked: symbol("checked", &config.checked, default.checked)?,
+        unchecked: symbol("unchecked", &config.unchecked, default.unchecked)?,
+        ellipsis: symbol("ellipsis", &config.ellipsis, default.ellipsis)?,
+        continuation: symbol("continuation", &config.continuation, default.continuation)?,
+        horizontal: symbol("horizontal", &config.horizontal, default.horizontal)?,
+        vertical: symbol("vertical", &config.vertical, default.vertical)?,
+        junctions,
+    })
+}
+
+/// Parse the name of a preset style sheet.
+fn parse_style_sheet(name: &str) -> clap::Result<StyleSheet> {
+    StyleSheet::preset(name).ok_or_else(|| {
+        clap::Error::with_description(
+            &format!(
+                "Invalid style sheet {}, expected one of {}",
+                name,
+                StyleSheet::PRESETS.join(", ")
+            ),
+            clap::ErrorKind::InvalidValue,
+        )
+    })
+}
+
+/// Create a style sheet from the paragraph, heading, rule and table styles,
+/// and the symbols in `config`, based on the `preset` style sheet.
+fn style_sheet_from_config(config: &Config, preset: StyleSheet) -> clap::Result<StyleSheet> {
+    let paragraph = &config.paragraph;
+    let alignment = match paragraph.alignment.as_deref() {
+        None | Some("left") => TextAlignment::Left,
+        Some("justify") => TextAlignment::Justify,
+        Some(value) => {
+            return Err(clap::Error::with_description(
+                &format!("Invalid alignment {}, expected left or justify", value),
+                clap::ErrorKind::InvalidValue,
+            ))
+        }
+    };
+    let indent = match (paragraph.first_line_indent, paragraph.hanging_indent) {
+        (None, None) => ParagraphIndent::None,
+        (Some(columns), None) => ParagraphIndent::FirstLine(columns),
+        (None, Some(columns)) => ParagraphIndent::Hanging(columns),
+        (Some(_), Some(_)) => {
+            return Err(clap::Error::with_description(
+                "Use either first-line-indent or hanging-indent, not both",
+                clap::ErrorKind::ArgumentConflict,
+            ))
+        }
+    };
+    let mut headings = preset.headings;
+    for (level, heading) in &config.headings {
+        let index = match level.parse::<usize>() {
+            Ok(level @ 1..=6) => level - 1,
+            _ => {
+                return Err(clap::Error::with_description(
+                    &format!("Invalid heading level {}, expected 1 to 6", level),
+                    clap::ErrorKind::InvalidValue,
+                ))
+            }
+        };
+        headings[index] = heading_style_from_config(heading)?;
+    }
+    Ok(StyleSheet {
+        paragraph: ParagraphStyle { alignment, indent },
+        headings,
+        rule: rule_style_from_config(&config.rule, preset.rule)?,
+        table: table_style_from_config(&config.table, preset.table)?,
+        symbols: symbols_from_config(&config.symbols)?,
+        colours: preset.colours,
+    })
+}
+
+/// Create HTTP settings from the hosts in `config`.
+fn http_from_config(config: &[HttpHostConfig]) -> clap::Result<HttpSettings> {
+    let invalid = |message: String| {
+        Err(clap::Error::with_description(
+            &message,
+            clap::ErrorKind::InvalidValue,
+        ))
+    };
+    let mut hosts = Vec::with_capacity(config.len());
+    for host in config {
+        if host.host.is_empty() {
+            return invalid("Missing host in http table".to_string());
+        }
+        let auth = match (&host.token, &host.user, &host.password) {
+            (None, None, None) => None,
+            (Some(token), None, None) => Some(HttpAuth::Bearer(token.clone())),
+            (None, Some(user), password) => Some(HttpAuth::Basic(
+                user.clone(),
+                password.clone().unwrap_or_default(),
+            )),
+            (None, None, Some(_)) => {
+                return invalid(format!("Missing user for password of host {}", host.host))
+            }
+            _ => {
+                return invalid(format!(
+                    "Use either token or user and password for host {}, not both",
+                    host.host
+                ))
+            }
+        };
+        hosts.push(HttpHost {
+            host: host.host.clone(),
+            auth,
+            headers: host
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        });
+    }
+    Ok(HttpSettings {
+        hosts,
+        ..HttpSettings::default()
+    })
+}
+
+/// Quote `value` as JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Print the detected terminal capabilities and size, and the reasons.
+///
+/// Print JSON if `json` is true, and human-readable text otherwise.
+fn print_detection(arguments: &Arguments, size: TerminalSize, size_source: &str, json: bool) {
+    let capabilities = &arguments.terminal_capabilities;
+    let styles = match capabilities.style.tier() {
+        StyleTier::Dumb => "off",
+        StyleTier::Ansi16 => "16",
+        StyleTier::Ansi256 => "256",
+        StyleTier::TrueColor => "truecolor",
+        StyleTier::Full => "full",
+    };
+    let links = match capabilities.links {
+        LinkCapability::None => "off",
+        LinkCapability::OSC8(_) => "osc8",
+    };
+    let images = match capabilities.image {
+        ImageCapability::None => "off",
+        ImageCapability::Terminology(_) => "terminology",
+        ImageCapability::ITerm2(_) => "iterm2",
+        ImageCapability::Kitty(_) => "kitty",
+    };
+    let marks = match capabilities.marks {
+        MarkCapability::None => "off",
+        MarkCapability::ITerm2(_) => "iterm2",
+        MarkCapability::OSC133(_) => "osc133",
+    };
+    if json {
+        let reasons: Vec<String> = arguments
+            .detection_reasons
+            .iter()
+            .map(|reason| json_string(reason))
+            .collect();
+        println!(
+            "{{\"terminal\":{},\"styles\":{},\"links\":{},\"images\":{},\"marks\":{},\"size\":{{\"columns\":{},\"lines\":{},\"source\":{}}},\"reasons\":[{}]}}",
+            json_string(&capabilities.name),
+            json_string(styles),
+            json_string(links),
+            json_string(images),
+            json_string(marks),
+            arguments.columns,
+            size.height,
+            json_string(size_source),
+            reasons.join(",")
+        );
+    } else {
+        println!("Terminal: {}", capabilities.name);
+        println!("Styles:   {}", styles);
+        println!("Links:    {}", links);
+        println!("Images:   {}", images);
+        println!("Marks:    {}", marks);
+        println!(
+            "Size:     {}x{} ({})",
+            arguments.columns, size.height, size_source
+        );
+        println!("Reasons:");
+        for reason in &arguments.detection_reasons {
+            println!("  - {}", reason);
+        }
+    }
+}
+
 /// Represent command line arguments.
 struct Arguments {
     filenames: Vec<String>,
+    output: Option<String>,
+    input_options: InputOptions,
     terminal_capabilities: TerminalCapabilities,
+    detection_reasons: Vec<String>,
     resource_access: ResourceAccess,
     columns: usize,
+    lines: Option<usize>,
+    page_break: PageBreak,
+    margin: usize,
+    content_width: Option<usize>,
+    style_sheet: StyleSheet,
+    theme_name: Option<ThemeName>,
+    theme: Option<Arc<Theme>>,
+    reference_overflow: ReferenceOverflow,
+    tab_width: usize,
+    dedent_code: bool,
+    code_overflow: CodeOverflow,
+    code_background: bool,
+    figure_numbers: bool,
+    animations: bool,
+    max_inline_image_bytes: usize,
+    double_height_headings: bool,
+    footnote_marker: FootnoteMarker,
+    super_subscripts: bool,
+    abbreviations: bool,
+    force_style: bool,
+    sniff_code: bool,
+    default_code_language: Option<String>,
+    number_snippets: bool,
+    document_separator: DocumentSeparator,
+    permalink_base: Option<Url>,
+    show_html_comments: bool,
+    debug_layout: bool,
+    bidi_isolates: bool,
+    reference_numbering: ReferenceNumbering,
+    reference_flush: ReferenceFlushPolicy,
+    link_titles: LinkTitles,
+    copy_mode: bool,
+    raw_blocks: bool,
+    banner_headings: bool,
+    skip_frontmatter: bool,
+    warnings: WarningLevel,
+    http: HttpSettings,
+    local_file_access: LocalFileAccess,
+    max_render_time: Option<Duration>,
+    max_pending_links: Option<usize>,
+    max_nesting_depth: Option<usize>,
+    max_image_read_bytes: Option<usize>,
+    max_image_pixels: Option<u64>,
     dump_events: bool,
-    detect_only: bool,
+    footer: bool,
+    preview: bool,
+    previewer: bool,
+    slides: bool,
+    slide_notes: Option<String>,
+    slide_footer: bool,
+    follow_terminal_size: bool,
+    check_links: bool,
+    link_check_budget: Duration,
+    self_test: bool,
+    deterministic: bool,
+    detect: Option<String>,
     fail_fast: bool,
 }
 
 impl Arguments {
     /// Create command line arguments from matches.
-    fn from_matches(matches: &clap::ArgMatches<'_>) -> clap::Result<Self> {
-        let terminal_capabilities = if matches.is_present("no_colour") {
+    ///
+    /// Take settings not given explicitly on the command line from `config`.
+    fn from_matches(matches: &clap::ArgMatches<'_>, config: &Config) -> clap::Result<Self> {
+        let explicit = |name| 0 < matches.occurrences_of(name);
+
+        let (terminal_capabilities, detection_reasons) = if matches.is_present("no_colour") {
             // If the user disabled colours assume a dumb terminal
-            TerminalCapabilities::none()
+            let reason = "--no-colour disables all styles".to_string();
+            (TerminalCapabilities::none(), vec![reason])
         } else if matches.is_present("ansi_only") {
-            TerminalCapabilities::ansi()
+            let reason = "--ansi-only limits to standard ANSI formatting".to_string();
+            (TerminalCapabilities::ansi(), vec![reason])
+        } else if matches.is_present("deterministic") {
+            let reason = "--deterministic ignores the terminal".to_string();
+            (TerminalCapabilities::ansi(), vec![reason])
+        } else if matches.is_present("check_links") {
+            let reason = "--check-links renders no output".to_string();
+            (TerminalCapabilities::ansi(), vec![reason])
+        } else if matches.is_present("ansi") {
+            let (capabilities, mut reasons) = TerminalCapabilities::detect_for_and_explain(true);
+            reasons.insert(0, "--ansi forces styles if output is not a TTY".to_string());
+            (capabilities, reasons)
+        } else if matches.is_present("output") {
+            TerminalCapabilities::detect_for_and_explain(false)
         } else {
-            TerminalCapabilities::detect()
+            TerminalCapabilities::detect_for_and_explain(atty::is(atty::Stream::Stdout))
         };
+        let force_style = matches.is_present("ansi");
 
         // On Windows 10 we need to enable ANSI term explicitly.
         #[cfg(windows)]
         {
-            ansi_term::enable_ansi_support().ok();
+            anstyle_query::windows::enable_ansi_colors();
         }
 
-        let filenames = values_t!(matches, "filenames", String)?;
+        let filenames = expand_globs(values_t!(matches, "filenames", String)?);
+        let output = matches.value_of("output").map(str::to_string);
+        let input_options = InputOptions {
+            encoding: match matches.value_of("encoding").or(config.encoding.as_deref()) {
+                Some(label) => Some(parse_encoding(label)?),
+                None => None,
+            },
+            max_bytes: optional_value(matches, "max_input_bytes")?,
+        };
+        let max_pending_links = optional_value(matches, "max_pending_links")?;
+        let max_nesting_depth = optional_value(matches, "max_nesting_depth")?;
+        let max_image_read_bytes =
+            optional_value(matches, "max_image_read_bytes")?.or(config.max_image_read_bytes);
+        let max_image_pixels = optional_value(matches, "max_image_pixels")?;
         let dump_events = matches.is_present("dump_events");
-        let detect_only = matches.is_present("detect_only");
+        let footer = matches.is_present("footer");
+        let previewer = matches.is_present("previewer");
+        let slides = matches.is_present("slides");
+        let slide_notes = matches.value_of("slide_notes").map(ToOwned::to_owned);
+        let slide_footer = matches.is_present("slide_footer");
+        let check_links = matches.is_present("check_links");
+        let link_check_budget = if matches.is_present("link_check_budget") {
+            Duration::from_secs(value_t!(matches, "link_check_budget", u64)?)
+        } else {
+            LinkCheckSettings::default().budget
+        };
+        let preview = matches.is_present("preview") || previewer;
+        let self_test = matches.is_present("self_test");
+        let deterministic = matches.is_present("deterministic");
+        let detect = if matches.is_present("detect") {
+            Some(matches.value_of("detect").unwrap_or("text").to_string())
+        } else {
+            None
+        };
         let fail_fast = matches.is_present("fail_fast");
-        let columns = value_t!(matches, "columns", usize)?;
-        let resource_access = if matches.is_present("local_only") {
-            ResourceAccess::LocalOnly
+        let columns = if deterministic && !explicit("columns") {
+            TerminalSize::default().width
+        } else {
+            value_t!(matches, "columns", usize)?
+        };
+        let lines = if matches.is_present("lines") {
+            Some(value_t!(matches, "lines", usize)?)
+        } else {
+            None
+        };
+        let follow_terminal_size = !deterministic && !explicit("columns") && lines.is_none();
+        let page_break = match config.page_break {
+            Some(ref page_break) if !explicit("page_break") => parse_page_break(page_break)?,
+            _ => parse_page_break(matches.value_of("page_break").unwrap_or("none"))?,
+        };
+        let margin = match config.margin {
+            Some(margin) if !explicit("margin") => margin,
+            _ => value_t!(matches, "margin", usize)?,
+        };
+        let content_width = if matches.is_present("content_width") {
+            Some(value_t!(matches, "content_width", usize)?)
         } else {
-            ResourceAccess::RemoteAllowed
+            config.content_width
         };
+        let preset = match config.style_sheet {
+            Some(ref name) if !explicit("style_sheet") => parse_style_sheet(name)?,
+            _ => parse_style_sheet(matches.value_of("style_sheet").unwrap_or("default"))?,
+        };
+        let style_sheet = style_sheet_from_config(config, preset)?;
+        let theme_name = pick_theme_name(
+            matches.value_of("theme"),
+            config.theme.as_deref(),
+            deterministic,
+        );
+        // The previewer loads themes only if it needs them
+        let theme = match theme_name {
+            Some(ref name) if !previewer => load_theme(name)?.map(Arc::new),
+            _ => None,
+        };
+        let reference_overflow = match config.long_links {
+            Some(ref mode) if !explicit("long_links") => parse_long_links(mode)?,
+            _ => parse_long_links(matches.value_of("long_links").unwrap_or("wrap"))?,
+        };
+        let reference_numbering = match config.reference_numbering {
+            Some(ref mode) if !explicit("reference_numbering") => parse_reference_numbering(mode)?,
+            _ => parse_reference_numbering(
+                matches
+                    .value_of("reference_numbering")
+                    .unwrap_or("document"),
+            )?,
+        };
+        let link_titles = match config.link_titles {
+            Some(ref mode) if !explicit("link_titles") => parse_link_titles(mode)?,
+            _ => parse_link_titles(matches.value_of("link_titles").unwrap_or("plain"))?,
+        };
+        let reference_flush = match config.flush_references {
+            Some(ref mode) if !explicit("flush_references") => parse_flush_references(mode)?,
+            _ => parse_flush_references(matches.value_of("flush_references").unwrap_or("section"))?,
+        };
+        let tab_width = match config.tab_width {
+            Some(tab_width) if !explicit("tab_width") => tab_width,
+            _ => value_t!(matches, "tab_width", usize)?,
+        };
+        let dedent_code = flag_or_config(matches, "dedent_code", config.dedent_code);
+        let code_overflow = match config.long_code {
+            Some(ref mode) if !explicit("long_code") => parse_long_code(mode)?,
+            _ => parse_long_code(matches.value_of("long_code").unwrap_or("overflow"))?,
+        };
+        let code_background = flag_or_config(matches, "code_background", config.code_background);
+        let figure_numbers = flag_or_config(matches, "figure_numbers", config.figure_numbers);
+        let animations = flag_or_config(matches, "animations", config.animations);
+        let max_inline_image_bytes = match config.max_image_bytes {
+            Some(bytes) if !explicit("max_image_bytes") => bytes,
+            _ => value_t!(matches, "max_image_bytes", usize)?,
+        };
+        let double_height_headings = flag_or_config(
+            matches,
+            "double_height_headings",
+            config.double_height_headings,
+        );
+        let footnote_marker = match config.footnote_marks {
+            Some(ref marks) if !explicit("footnote_marks") => parse_footnote_marks(marks)?,
+            _ => parse_footnote_marks(matches.value_of("footnote_marks").unwrap_or("superscript"))?,
+        };
+        let super_subscripts = flag_or_config(matches, "super_subscripts", config.super_subscripts);
+        let abbreviations = flag_or_config(matches, "abbreviations", config.abbreviations);
+        let sniff_code = flag_or_config(matches, "sniff_code", config.sniff_code);
+        let default_code_language = match matches.value_of("default_code_language") {
+            Some(language) => Some(language.to_string()),
+            None => config.default_code_language.clone(),
+        };
+        let number_snippets = flag_or_config(matches, "number_snippets", config.number_snippets);
+        let document_separator = match config.separator {
+            Some(ref separator) if !explicit("separator") => parse_separator(separator)?,
+            _ => parse_separator(matches.value_of("separator").unwrap_or("none"))?,
+        };
+        let permalink_base = match matches.value_of("permalinks") {
+            Some(url) => Some(parse_url(url)?),
+            None => None,
+        };
+        let debug_layout = matches.is_present("debug_layout");
+        let show_html_comments =
+            flag_or_config(matches, "show_html_comments", config.show_html_comments);
+        let bidi_isolates = flag_or_config(matches, "bidi_isolates", config.bidi_isolates);
+        let copy_mode = flag_or_config(matches, "copy_mode", config.copy_mode);
+        let raw_blocks = flag_or_config(matches, "raw_blocks", config.raw_blocks);
+        let banner_headings = flag_or_config(matches, "banner_headings", config.banner_headings);
+        let skip_frontmatter = flag_or_config(matches, "skip_frontmatter", config.skip_frontmatter);
+        let mut http = http_from_config(&config.http)?;
+        if let Some(proxy) = matches.value_of("proxy").or(config.proxy.as_deref()) {
+            parse_url(proxy)?;
+            http.proxy = Some(proxy.to_string());
+        }
+        http.ca_bundle = matches
+            .value_of("ca_bundle")
+            .map(PathBuf::from)
+            .or_else(|| config.ca_bundle.clone());
+        http.insecure = flag_or_config(matches, "insecure", config.insecure);
+        let warnings = if matches.is_present("quiet") || previewer {
+            WarningLevel::Quiet
+        } else if matches.is_present("verbose") {
+            WarningLevel::Verbose
+        } else {
+            WarningLevel::Normal
+        };
+        let local_file_access = if flag_or_config(
+            matches,
+            "restrict_local_images",
+            config.restrict_local_images,
+        ) {
+            LocalFileAccess::BaseDirOnly
+        } else {
+            LocalFileAccess::Anywhere
+        };
+        let max_render_time = optional_value(matches, "max_render_time")?.map(Duration::from_secs);
+        let resource_access =
+            if flag_or_config(matches, "local_only", config.local) || previewer || deterministic {
+                ResourceAccess::LocalOnly
+            } else {
+                ResourceAccess::RemoteAllowed
+            };
 
         Ok(Arguments {
             filenames,
+            output,
+            input_options,
             columns,
+            lines,
+            page_break,
+            margin,
+            content_width,
+            style_sheet,
+            theme_name,
+            theme,
+            reference_overflow,
+            tab_width,
+            dedent_code,
+            code_overflow,
+            code_background,
+            figure_numbers,
+            animations,
+            max_inline_image_bytes,
+            double_height_headings,
+            footnote_marker,
+            super_subscripts,
+            abbreviations,
+            force_style,
+            sniff_code,
+            default_code_language,
+            number_snippets,
+            document_separator,
+            permalink_base,
+            show_html_comments,
+            debug_layout,
+            bidi_isolates,
+            reference_numbering,
+            reference_flush,
+            link_titles,
+            copy_mode,
+            raw_blocks,
+            banner_headings,
+            skip_frontmatter,
+            warnings,
+            http,
+            local_file_access,
+            max_render_time,
+            max_pending_links,
+            max_nesting_depth,
+            max_image_read_bytes,
+            max_image_pixels,
             resource_access,
             dump_events,
-            detect_only,
+            footer,
+            preview,
+            previewer,
+            slides,
+            slide_notes,
+            slide_footer,
+            follow_terminal_size,
+            check_links,
+            link_check_budget,
+            self_test,
+            deterministic,
+            detect,
             fail_fast,
             terminal_capabilities,
+            detection_reasons,
         })
     }
 }
 
 fn main() {
     use clap::*;
-    let size = TerminalSize::detect().unwrap_or_default();
+    let (size, size_source) = TerminalSize::detect_and_explain()
+        .unwrap_or_else(|| (TerminalSize::default(), "default size"));
     let columns = size.width.to_string();
     let app = app_from_crate!()
         // Merge flags and options w/ arguments together, include args in usage
@@ -143,6 +1470,11 @@ markdown documents for viewing in text terminals:
 • In some terminals: Inline images and inline links
 • In iTerm2: Jump marks for headings
 
+mdcat exits with 0 on success, with 1 for invalid arguments, and with the exit
+code of the first error otherwise: 2 for input or output errors, 3 for render
+errors, 4 if access to a file was denied, 5 for broken links, and 6 if
+rendering was cancelled.
+
 Copyright (C) 2018–2020 Sebastian Wiesner and contributors
 Licensed under the Apache License, Version 2.0
 Report issues to <https://github.com/lunaryorn/mdcat>.",
@@ -151,8 +1483,39 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
             Arg::with_name("filenames")
                 .multiple(true)
                 .help("The file to read.  If - read from standard input instead")
+                .long_help(
+                    "The file to read.  If - read from standard input instead.  Append #SLUG to \
+only show the section with the heading SLUG, as on GitHub, e.g. README.md#installation.",
+                )
                 .default_value("-"),
         )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write output to the given file instead of standard output")
+                .long_help(
+                    "Write output to the given file instead of standard output.  Write plain \
+text unless --ansi is given, to keep styles, e.g. to cache rendered documents.",
+                ),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .long("encoding")
+                .value_name("ENCODING")
+                .help("Read input in the given encoding, e.g. latin1 or utf-16le")
+                .long_help(
+                    "Read input in the given encoding, e.g. latin1 or utf-16le.  By default \
+take the encoding from a byte order mark, or read UTF-8, or Latin-1 if input is not valid UTF-8.",
+                ),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Read settings from the given file instead of ~/.config/mdcat/mdcat.toml"),
+        )
         .arg(
             Arg::with_name("no_colour")
                 .short("c")
@@ -166,28 +1529,483 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
                 .help("Maximum number of columns to use for output")
                 .default_value(&columns),
         )
+        .arg(
+            Arg::with_name("lines")
+                .long("lines")
+                .takes_value(true)
+                .help("Number of lines of the terminal, for --preview"),
+        )
+        .arg(
+            Arg::with_name("margin")
+                .long("margin")
+                .value_name("COLUMNS")
+                .help("Indent the whole document by the given number of columns")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("content_width")
+                .long("content-width")
+                .value_name("COLUMNS")
+                .help("Wrap text at the given width and center it in wider terminals"),
+        )
+        .arg(
+            Arg::with_name("long_links")
+                .long("long-links")
+                .value_name("MODE")
+                .help("How to write link references which do not fit into a line")
+                .possible_values(&["wrap", "ellipsize", "overflow"])
+                .default_value("wrap"),
+        )
+        .arg(
+            Arg::with_name("reference_numbering")
+                .long("reference-numbering")
+                .value_name("SCOPE")
+                .help("Number link references per document, or per level 1 and 2 section")
+                .possible_values(&["document", "section"])
+                .default_value("document"),
+        )
+        .arg(
+            Arg::with_name("flush_references")
+                .long("flush-references")
+                .value_name("WHEN")
+                .help("Write link references before each heading, at the end, or after paragraphs")
+                .possible_values(&["section", "end", "paragraph"])
+                .default_value("section"),
+        )
+        .arg(
+            Arg::with_name("link_titles")
+                .long("link-titles")
+                .value_name("MODE")
+                .help("How to write link titles: plain, quoted, or as tooltips of inline links")
+                .possible_values(&["plain", "quoted", "tooltip"])
+                .default_value("plain"),
+        )
+        .arg(
+            Arg::with_name("tab_width")
+                .long("tab-width")
+                .value_name("COLUMNS")
+                .help("Expand tabs in code blocks to the given width, or keep tabs if 0")
+                .default_value("4"),
+        )
+        .arg(
+            Arg::with_name("dedent_code")
+                .long("dedent-code")
+                .help("Remove indentation common to all lines of code blocks"),
+        )
+        .arg(
+            Arg::with_name("long_code")
+                .long("long-code")
+                .value_name("MODE")
+                .help("How to write lines of code which do not fit into a line")
+                .possible_values(&["wrap", "truncate", "overflow"])
+                .default_value("overflow"),
+        )
+        .arg(
+            Arg::with_name("code_background")
+                .long("code-background")
+                .help(
+                    "Paint code blocks on the background of the theme in 24-bit colour terminals",
+                ),
+        )
+        .arg(
+            Arg::with_name("figure_numbers")
+                .long("figure-numbers")
+                .help("Number images with captions as figures"),
+        )
+        .arg(
+            Arg::with_name("animations")
+                .long("animations")
+                .help("Play animated images if the terminal supports it"),
+        )
+        .arg(
+            Arg::with_name("max_image_bytes")
+                .long("max-image-bytes")
+                .value_name("BYTES")
+                .help("Scale down inline images to at most the given size of encoded data")
+                .default_value("1048576"),
+        )
+        .arg(
+            Arg::with_name("double_height_headings")
+                .long("double-height-headings")
+                .help("Write level-1 headings with double height, if the terminal supports it"),
+        )
+        .arg(
+            Arg::with_name("footnote_marks")
+                .long("footnote-marks")
+                .value_name("STYLE")
+                .help("How to mark footnotes")
+                .possible_values(&["superscript", "brackets", "asterisks"])
+                .default_value("superscript"),
+        )
+        .arg(
+            Arg::with_name("super_subscripts")
+                .long("super-subscripts")
+                .help("Write ^superscript^ and ~subscript~ text"),
+        )
+        .arg(
+            Arg::with_name("abbreviations")
+                .long("abbreviations")
+                .help("Underline *[abbreviations] and list them at the end"),
+        )
+        .arg(
+            Arg::with_name("sniff_code")
+                .long("sniff-code")
+                .help("Guess the language of indented code blocks to highlight them"),
+        )
+        .arg(
+            Arg::with_name("default_code_language")
+                .long("default-code-language")
+                .value_name("LANGUAGE")
+                .help("Highlight code blocks without a language as the given language"),
+        )
+        .arg(
+            Arg::with_name("number_snippets")
+                .long("number-snippets")
+                .help("Number code blocks, for use with mdcat snippet"),
+        )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .value_name("NAME")
+                .help("Highlight code with the given theme, e.g. \"Solarized (light)\"")
+                .long_help(
+                    "Highlight code with the given theme, e.g. \"Solarized (light)\".  \
+Default to $MDCAT_THEME or $BAT_THEME.  Without a theme highlight code with \
+the basic colours of the terminal.",
+                ),
+        )
+        .arg(
+            Arg::with_name("style_sheet")
+                .long("style-sheet")
+                .value_name("NAME")
+                .possible_values(&StyleSheet::PRESETS)
+                .default_value("default")
+                .help("Style text with the given preset style sheet")
+                .long_help(
+                    "Style text with the given preset style sheet.  colour-blind \
+avoids green, and uses colours which remain distinct with red-green colour \
+blindness.  Settings in the configuration file apply on top of the preset.",
+                ),
+        )
+        .arg(
+            Arg::with_name("page_break")
+                .long("page-break")
+                .value_name("KIND")
+                .help(
+                    "Break pages before level-1 headings: none, form-feed, \
+                     or a number of blank lines",
+                )
+                .default_value("none"),
+        )
+        .arg(
+            Arg::with_name("separator")
+                .long("separator")
+                .value_name("KIND")
+                .help("What to write between documents")
+                .possible_values(&["none", "rule", "filename", "form-feed"])
+                .default_value("none"),
+        )
+        .arg(
+            Arg::with_name("show_html_comments")
+                .long("show-html-comments")
+                .help("Show HTML comments dimmed instead of stripping them"),
+        )
+        .arg(
+            Arg::with_name("bidi_isolates")
+                .long("bidi-isolates")
+                .help("Isolate right-to-left text, for mixed-direction documents")
+                .long_help(
+                    "Put inline text with right-to-left letters, e.g. Arabic or Hebrew, into \
+Unicode directional isolates, so that terminals which reorder bidirectional text keep it apart \
+from surrounding text and decorations.",
+                ),
+        )
+        .arg(
+            Arg::with_name("copy_mode")
+                .long("copy-mode")
+                .help("Render without decorations, to copy text from the terminal")
+                .long_help(
+                    "Render without borders around code blocks and heading adornments, with \
+plain ASCII bullets and task markers, and without wrapping or truncating code, so that text \
+selected in the terminal pastes cleanly elsewhere.  Keep colours.",
+                ),
+        )
+        .arg(
+            Arg::with_name("banner_headings")
+                .long("banner-headings")
+                .help("Render level-1 headings as large ASCII-art banners")
+                .long_help(
+                    "Render level-1 headings as large ASCII-art banners, e.g. for slides.  \
+Write headings with characters the banner font lacks, or too wide for the terminal, as usual.",
+                ),
+        )
+        .arg(
+            Arg::with_name("skip_frontmatter")
+                .long("skip-frontmatter")
+                .help("Skip frontmatter at the beginning of documents")
+                .long_help(
+                    "Skip frontmatter at the beginning of documents, i.e. a block of key: value \
+lines between two --- lines.  Off by default, because valid CommonMark documents may begin with a \
+rule and a heading of such lines.",
+                ),
+        )
+        .arg(
+            Arg::with_name("raw_blocks")
+                .long("raw-blocks")
+                .help("Write code blocks tagged raw to the terminal as is")
+                .long_help(
+                    "Write the contents of fenced code blocks tagged raw byte for byte, e.g. \
+custom escape sequences or ANSI art, if the terminal supports styles.  Only enable this for \
+trusted documents: raw blocks can send arbitrary escape sequences to the terminal.",
+                ),
+        )
+        .arg(
+            Arg::with_name("permalinks")
+                .long("permalinks")
+                .value_name("URL")
+                .help("Link headings to their permalinks in the document at URL"),
+        )
+        .arg(
+            Arg::with_name("copy_anchor")
+                .long("copy-anchor")
+                .value_name("SLUG")
+                .requires("permalinks")
+                .help("Copy the permalink of the heading with SLUG to the clipboard, and exit"),
+        )
+        .arg(
+            Arg::with_name("footer")
+                .long("footer")
+                .help("Write the source of each document and the time it was read at the end"),
+        )
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .help("Stop after one screenful of output, e.g. for preview panes"),
+        )
+        .arg(Arg::with_name("previewer").long("previewer").help(
+            "Preview a single file in a file manager like lf, nnn or ranger: \
+                     Implies --preview and --local, and loads syntax definitions and \
+                     themes only if the file has code blocks.  Exit with 0 if mdcat previewed the \
+                     file, and with the exit code of the error otherwise",
+        ))
+        .arg(
+            Arg::with_name("slides")
+                .long("slides")
+                .help("Present a single file as slides")
+                .long_help(
+                    "Present a single file as slides, with a slide for every level-1 and \
+level-2 heading.  Clear the screen for every slide and center it vertically.  Press Space, \
+Enter, n or j for the next slide, p or k for the previous slide, and q to quit.  Render the \
+slide again when the terminal is resized, unless --columns or --lines is given.",
+                )
+                .conflicts_with_all(&["previewer", "dump_events"]),
+        )
+        .arg(
+            Arg::with_name("slide_footer")
+                .long("slide-footer")
+                .requires("slides")
+                .help("Show the number of the slide and the elapsed time in slides"),
+        )
+        .arg(
+            Arg::with_name("slide_notes")
+                .long("slide-notes")
+                .value_name("FILE")
+                .requires("slides")
+                .help("Append speaker notes of slides to FILE")
+                .long_help(
+                    "Append the speaker notes of every slide to FILE when presenting the \
+slide, e.g. to follow them with tail -f in another terminal.  Write speaker notes in \
+<!-- notes: ... --> comments.  Pause slides with <!-- pause -->, and reveal list items one \
+by one after <!-- incremental_lists: true -->.",
+                ),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Do not warn about images which fail to load"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .conflicts_with("quiet")
+                .help("Warn about images which fail to load with all causes")
+                .long_help(
+                    "Warn about images which fail to load with the URL and all causes of the \
+failure, and about remote images skipped with --local.",
+                ),
+        )
         .arg(
             Arg::with_name("local_only")
                 .short("l")
                 .long("local")
                 .help("Do not load remote resources like images"),
         )
+        .arg(
+            Arg::with_name("restrict_local_images")
+                .long("restrict-local-images")
+                .help("Only show local images in the directory of the document")
+                .long_help(
+                    "Only show local images in the directory of the document or its \
+subdirectories, e.g. to render untrusted documents without leaking arbitrary local files.",
+                ),
+        )
+        .arg(
+            Arg::with_name("proxy")
+                .long("proxy")
+                .value_name("URL")
+                .help("Fetch remote resources through the proxy at URL")
+                .long_help(
+                    "Fetch remote resources through the proxy at URL, e.g. \
+http://proxy.example.com:3128.  By default use the proxy from $HTTPS_PROXY, $HTTP_PROXY or \
+$ALL_PROXY, if any.  Never use a proxy for hosts in $NO_PROXY.",
+                ),
+        )
+        .arg(
+            Arg::with_name("ca_bundle")
+                .long("ca-bundle")
+                .value_name("FILE")
+                .help("Trust the CA certificates in FILE for remote resources")
+                .long_help(
+                    "Trust the CA certificates in the PEM file FILE in addition to the system \
+certificates when fetching remote resources over HTTPS, e.g. for intranets with private CAs.",
+                ),
+        )
+        .arg(
+            Arg::with_name("insecure")
+                .long("insecure")
+                .help("Accept invalid certificates for remote resources")
+                .long_help(
+                    "Accept invalid certificates, e.g. self-signed or expired ones, when fetching \
+remote resources over HTTPS.  This is insecure; prefer --ca-bundle.",
+                ),
+        )
+        .arg(
+            Arg::with_name("debug_layout")
+                .long("debug-layout")
+                .help("Annotate the indentation and width of each block, to debug layout"),
+        )
+        .arg(
+            Arg::with_name("check_links")
+                .long("check-links")
+                .conflicts_with_all(&["previewer", "slides", "dump_events"])
+                .help("Check links and images and exit")
+                .long_help(
+                    "Check links and images instead of rendering files, print all broken links, \
+and exit with 5 if any link is broken.  Check that local files exist, and send HEAD requests, \
+or GET requests if servers refuse HEAD, for remote links, with at most two concurrent requests \
+per host.  With --local check only local files.",
+                ),
+        )
+        .arg(
+            Arg::with_name("link_check_budget")
+                .long("link-check-budget")
+                .value_name("SECONDS")
+                .requires("check_links")
+                .help("Check links for at most SECONDS [default: 60]")
+                .long_help(
+                    "Check links for at most SECONDS, and leave the remaining links unchecked.  \
+Defaults to 60 seconds.  With --verbose warn about unchecked links.",
+                ),
+        )
         .arg(
             Arg::with_name("dump_events")
                 .long("dump-events")
                 .help("Dump Markdown parser events and exit")
                 .hidden(true),
         )
+        .arg(
+            Arg::with_name("max_render_time")
+                .long("max-render-time")
+                .value_name("SECONDS")
+                .help("Stop rendering after SECONDS")
+                .long_help(
+                    "Stop rendering after the first top-level block which ends after SECONDS, \
+and end with a notice, and give up on remote images after SECONDS, e.g. to never hang on \
+slow networks.",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_input_bytes")
+                .long("max-input-bytes")
+                .value_name("BYTES")
+                .help("Fail for input larger than BYTES")
+                .long_help(
+                    "Fail for input larger than BYTES, and stop decompressing gzip or zstd \
+compressed input after BYTES.",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_pending_links")
+                .long("max-pending-links")
+                .value_name("LINKS")
+                .help("Write pending reference links once LINKS are pending"),
+        )
+        .arg(
+            Arg::with_name("max_nesting_depth")
+                .long("max-nesting-depth")
+                .value_name("DEPTH")
+                .help("Skip block quotes and lists nested deeper than DEPTH"),
+        )
+        .arg(
+            Arg::with_name("max_image_read_bytes")
+                .long("max-image-read-bytes")
+                .value_name("BYTES")
+                .help("Show no images larger than BYTES")
+                .long_help(
+                    "Show no images larger than BYTES, and never read more than BYTES of an \
+image.",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_image_pixels")
+                .long("max-image-pixels")
+                .value_name("PIXELS")
+                .help("Show no images with more than PIXELS")
+                .long_help(
+                    "Show no images with more than PIXELS, i.e. width times height, and never \
+decode such images.",
+                ),
+        )
         .arg(
             Arg::with_name("fail_fast")
                 .long("fail")
                 .help("Exit immediately if any error occurs processing an input file"),
         )
         .arg(
-            Arg::with_name("detect_only")
-                .long("detect-only")
-                .help("Only detect the terminal type and exit")
-                .hidden(true),
+            Arg::with_name("deterministic")
+                .long("deterministic")
+                .help("Render the same output for the same input in every environment")
+                .long_help(
+                    "Render the same output for the same input in every environment.  Ignore \
+the terminal, the configuration file and $MDCAT_THEME and $BAT_THEME, render with basic ANSI styles at 80 columns unless \
+given otherwise, load no remote resources, and write no timestamps.",
+                ),
+        )
+        .arg(Arg::with_name("self_test").long("self-test").help(
+            "Print the detected terminal and render a built-in document with \
+                     all supported markup, to check what the terminal supports",
+        ))
+        .arg(
+            Arg::with_name("detect")
+                .long("detect")
+                .alias("detect-only")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .min_values(0)
+                .require_equals(true)
+                .help("Print the detected terminal and its capabilities, and exit")
+                .long_help(
+                    "Print the detected terminal, its capabilities and size, and the reasons \
+for each decision, and exit.  Print JSON with --detect=json.",
+                ),
+        )
+        .arg(
+            Arg::with_name("ansi")
+                .long("ansi")
+                .help("Style output even if it is not a TTY, e.g. for less -R")
+                .conflicts_with("no_colour"),
         )
         .arg(
             Arg::with_name("ansi_only")
@@ -195,48 +2013,356 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
                 .help("Limit to standard ANSI formatting")
                 .conflicts_with("no_colour")
                 .hidden(true),
+        )
+        .subcommand(
+            SubCommand::with_name("snippet")
+                .about("Print a code block of a document")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .help("The file to read.  If - read from standard input instead"),
+                )
+                .arg(
+                    Arg::with_name("number")
+                        .required(true)
+                        .help("The number of the code block, as shown by --number-snippets"),
+                )
+                .arg(
+                    Arg::with_name("copy")
+                        .long("copy")
+                        .help("Copy the code block to the clipboard of the terminal (OSC 52)"),
+                ),
         );
+    let app = NEGATABLE_FLAGS.iter().fold(app, |app, &(_, name, long)| {
+        app.arg(
+            Arg::with_name(name)
+                .long(long)
+                .help("Turn off the flag without no-, e.g. if the configuration file turns it on")
+                .hidden_short_help(true),
+        )
+    });
 
     let matches = app.get_matches();
-    let arguments = Arguments::from_matches(&matches).unwrap_or_else(|e| e.exit());
+    if matches.is_present("copy_anchor") {
+        if let Err(error) = copy_anchor(&matches) {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+    if let Some(matches) = matches.subcommand_matches("snippet") {
+        if let Err(error) = print_snippet(matches) {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+    let config = match matches.value_of("config") {
+        // Ignore the default configuration file for deterministic output
+        None if matches.is_present("deterministic") => Ok(Config::default()),
+        Some(path) => Config::load(Path::new(path)).map_err(|error| (path.into(), error)),
+        None => Config::load_default().map_err(|error| {
+            let path = Config::default_path().unwrap_or_default();
+            (path.display().to_string(), error)
+        }),
+    };
+    let config = config.unwrap_or_else(|(path, error)| {
+        eprintln!("Error: {}: {}", path, error);
+        std::process::exit(1);
+    });
+    let arguments = Arguments::from_matches(&matches, &config).unwrap_or_else(|e| e.exit());
 
-    if arguments.detect_only {
-        println!("Terminal: {}", arguments.terminal_capabilities.name);
+    let size_source = if matches.occurrences_of("columns") > 0 {
+        "--columns"
     } else {
+        size_source
+    };
+    if let Some(ref format) = arguments.detect {
+        print_detection(&arguments, size, size_source, format == "json");
+    } else {
+        if arguments.self_test {
+            print_detection(&arguments, size, size_source, false);
+            println!();
+        }
         let Arguments {
             filenames,
+            output,
+            input_options,
             dump_events,
+            footer,
+            preview,
+            previewer,
+            slides,
+            slide_notes,
+            slide_footer,
+            follow_terminal_size,
+            check_links,
+            link_check_budget,
+            self_test,
+            deterministic,
             fail_fast,
             terminal_capabilities,
             columns,
+            lines,
+            page_break,
+            margin,
+            content_width,
+            style_sheet,
+            theme_name,
+            theme,
+            reference_overflow,
+            tab_width,
+            dedent_code,
+            code_overflow,
+            code_background,
+            figure_numbers,
+            animations,
+            max_inline_image_bytes,
+            double_height_headings,
+            footnote_marker,
+            super_subscripts,
+            abbreviations,
+            force_style,
+            sniff_code,
+            default_code_language,
+            number_snippets,
+            document_separator,
+            permalink_base,
+            show_html_comments,
+            debug_layout,
+            bidi_isolates,
+            reference_numbering,
+            reference_flush,
+            link_titles,
+            copy_mode,
+            raw_blocks,
+            banner_headings,
+            skip_frontmatter,
+            warnings,
+            http,
+            local_file_access,
+            max_render_time,
+            max_pending_links,
+            max_nesting_depth,
+            max_image_read_bytes,
+            max_image_pixels,
             resource_access,
             ..
         } = arguments;
 
+        let lines = match lines {
+            Some(lines) => lines,
+            None if deterministic => TerminalSize::default().height,
+            None => size.height,
+        };
         let settings = Settings {
             terminal_capabilities,
             terminal_size: TerminalSize {
                 width: columns,
-                ..size
+                height: lines,
             },
             resource_access,
-            syntax_set: SyntaxSet::load_defaults_newlines(),
+            // The previewer loads syntax definitions only if it needs them
+            syntax_set: Arc::new(if previewer {
+                SyntaxSet::new()
+            } else {
+                SyntaxSet::load_defaults_newlines()
+            }),
+            theme,
+            page_break,
+            margin,
+            content_width,
+            style_sheet,
+            reference_overflow,
+            tab_width,
+            dedent_code,
+            code_overflow,
+            code_background,
+            figure_numbers,
+            animations,
+            max_inline_image_bytes,
+            image_cache: ImageCache::default(),
+            double_height_headings,
+            footnote_marker,
+            super_subscripts,
+            abbreviations,
+            force_style,
+            max_pending_links,
+            max_nesting_depth,
+            max_image_read_bytes,
+            max_image_pixels,
+            sniff_code,
+            default_code_language,
+            number_snippets,
+            document_separator,
+            // Leave room for the trailer
+            max_lines: if preview {
+                Some(lines.saturating_sub(1).max(1))
+            } else {
+                None
+            },
+            permalink_base,
+            show_html_comments,
+            debug_layout,
+            bidi_isolates,
+            reference_numbering,
+            reference_flush,
+            link_titles,
+            copy_mode,
+            raw_blocks,
+            banner_headings,
+            warnings: warning_handler(warnings),
+            http,
+            local_file_access,
+            max_output_bytes: None,
+            max_render_time,
+            cancellation: None,
+            skip_frontmatter,
+        };
+        let mut writer: Box<dyn Write> = match output {
+            Some(ref path) => {
+                let file = File::create(path).unwrap_or_else(|error| {
+                    let error: Box<dyn std::error::Error> = error.into();
+                    let error = mdcat::error::Error::from(error);
+                    eprintln!("Error: {}: {}", path, error);
+                    std::process::exit(error.exit_code());
+                });
+                let file = BufWriter::new(file);
+                if force_style {
+                    Box::new(file)
+                } else {
+                    Box::new(StripEscapes::new(file))
+                }
+            }
+            None => Box::new(stdout()),
         };
+        if self_test {
+            let code = match render_self_test(&mut writer, &settings)
+                .and_then(|_| writer.flush().map_err(Into::into))
+            {
+                Ok(_) => 0,
+                Err(error) => {
+                    let error = mdcat::error::Error::from(error);
+                    eprintln!("Error: {}", error);
+                    error.exit_code()
+                }
+            };
+            std::process::exit(code);
+        }
+        if slides {
+            let result = match filenames.as_slice() {
+                [filename] => present_file(
+                    &mut writer,
+                    filename,
+                    input_options,
+                    &settings,
+                    slide_notes.as_deref(),
+                    slide_footer,
+                    follow_terminal_size,
+                ),
+                _ => Err("--slides needs exactly one file".into()),
+            };
+            let code = match result.and_then(|_| writer.flush().map_err(Into::into)) {
+                Ok(_) => 0,
+                Err(error) => {
+                    let error = mdcat::error::Error::from(error);
+                    eprintln!("Error: {}", error);
+                    error.exit_code()
+                }
+            };
+            std::process::exit(code);
+        }
+        if check_links {
+            let link_check = LinkCheckSettings {
+                http: settings.http.clone(),
+                budget: link_check_budget,
+                ..LinkCheckSettings::default()
+            };
+            let mut broken = 0;
+            let mut code = 0;
+            for filename in &filenames {
+                match check_file_links(
+                    &mut writer,
+                    filename,
+                    input_options,
+                    &settings,
+                    &link_check,
+                    warnings,
+                ) {
+                    Ok(count) => broken += count,
+                    Err(error) => {
+                        let error = mdcat::error::Error::from(error);
+                        eprintln!("Error: {}: {}", filename, error);
+                        if code == 0 {
+                            code = error.exit_code();
+                        }
+                    }
+                }
+            }
+            if let Err(error) = writer.flush() {
+                eprintln!("Error: {}", error);
+                std::process::exit(mdcat::error::ErrorKind::Io.exit_code());
+            }
+            if code == 0 && 0 < broken {
+                code = mdcat::error::ErrorKind::BrokenLinks.exit_code();
+            }
+            std::process::exit(code);
+        }
+        if previewer {
+            let result = match filenames.as_slice() {
+                [filename] => preview_file(
+                    &mut writer,
+                    filename,
+                    input_options,
+                    theme_name.as_ref(),
+                    settings,
+                ),
+                _ => Err("--previewer needs exactly one file".into()),
+            };
+            let code = match result.and_then(|_| writer.flush().map_err(Into::into)) {
+                Ok(_) => 0,
+                Err(error) => {
+                    let error = mdcat::error::Error::from(error);
+                    eprintln!("Error: {}", error);
+                    error.exit_code()
+                }
+            };
+            std::process::exit(code);
+        }
         let exit_code = filenames
             .iter()
-            .try_fold(0, |code, filename| {
-                process_file(filename, &settings, dump_events)
+            .enumerate()
+            .try_fold(0, |code, (index, filename)| {
+                mdcat::push_separator(&settings, &mut writer, filename, index == 0)
+                    .and_then(|_| {
+                        process_file(
+                            &mut writer,
+                            filename,
+                            input_options,
+                            &settings,
+                            dump_events,
+                            footer,
+                            deterministic,
+                        )
+                    })
                     .map(|_| code)
                     .or_else(|error| {
+                        let error = mdcat::error::Error::from(error);
                         eprintln!("Error: {}: {}", filename, error);
                         if fail_fast {
                             Err(error)
+                        } else if code == 0 {
+                            Ok(error.exit_code())
                         } else {
-                            Ok(1)
+                            Ok(code)
                         }
                     })
             })
-            .unwrap_or(1);
+            .unwrap_or_else(|error| error.exit_code());
+        if let Err(error) = writer.flush() {
+            eprintln!("Error: {}", error);
+            std::process::exit(mdcat::error::ErrorKind::Io.exit_code());
+        }
         std::process::exit(exit_code);
     }
 }
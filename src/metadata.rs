@@ -0,0 +1,207 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extract metadata from documents without rendering them.
+
+use pulldown_cmark::{Event, Tag};
+
+/// Metadata of a document.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    /// The title of the document.
+    ///
+    /// The `title` of the frontmatter, or the text of the first level-1
+    /// heading.
+    pub title: Option<String>,
+    /// The description of the document.
+    ///
+    /// The `description` of the frontmatter, or the text of the first
+    /// paragraph.
+    pub description: Option<String>,
+    /// All keys and values of the frontmatter, in order.
+    pub frontmatter: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// Get the value of `key` in the frontmatter.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.frontmatter
+            .iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse the lines of `text` as frontmatter.
+///
+/// Return `None` if any line is not a `key: value` pair.
+fn parse_frontmatter(text: &str) -> Option<Vec<(String, String)>> {
+    text.lines()
+        .map(|line| {
+            let colon = line.find(':')?;
+            let key = line[..colon].trim();
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                return None;
+            }
+            let value = line[colon + 1..].trim();
+            let value = if 2 <= value.len() && value.starts_with('"') && value.ends_with('"') {
+                &value[1..value.len() - 1]
+            } else {
+                value
+            };
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Collect the text of events up to the end of the current `tag`.
+///
+/// Join lines with `line_break`.
+fn collect_text<'a, I>(events: &mut I, tag: &Tag<'a>, line_break: char) -> String
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::End(ref end) if end == tag => break,
+            Event::Text(part) | Event::Code(part) => text.push_str(&part),
+            Event::SoftBreak | Event::HardBreak => text.push(line_break),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Extract the metadata of a document from its `events`.
+///
+/// pulldown-cmark does not parse frontmatter, and instead reads a frontmatter
+/// block delimited by `---` as a rule followed by a level-2 heading.  Take
+/// such a heading at the very beginning of the document as frontmatter if all
+/// its lines are `key: value` pairs.
+pub fn metadata<'a, I>(events: I) -> Metadata
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut metadata = Metadata::default();
+    let mut events = events.peekable();
+    if let Some(Event::Rule) = events.peek() {
+        events.next();
+        if let Some(Event::Start(Tag::Heading(2))) = events.peek() {
+            events.next();
+            let text = collect_text(&mut events, &Tag::Heading(2), '\n');
+            if let Some(frontmatter) = parse_frontmatter(&text) {
+                metadata.frontmatter = frontmatter;
+                metadata.title = metadata.get("title").map(str::to_string);
+                metadata.description = metadata.get("description").map(str::to_string);
+            }
+        }
+    }
+    while metadata.title.is_none() || metadata.description.is_none() {
+        match events.next() {
+            None => break,
+            Some(Event::Start(tag @ Tag::Heading(1))) if metadata.title.is_none() => {
+                metadata.title = Some(collect_text(&mut events, &tag, ' '));
+            }
+            Some(Event::Start(tag @ Tag::Paragraph)) if metadata.description.is_none() => {
+                metadata.description = Some(collect_text(&mut events, &tag, ' '));
+            }
+            Some(_) => {}
+        }
+    }
+    metadata
+}
+
+/// Remove the frontmatter at the very beginning of `events`, if any.
+///
+/// Detect frontmatter like [`metadata`] does, and leave `events` as they are
+/// if they have no frontmatter.  Every event comes with a `T`, e.g. its offset
+/// in the source.
+pub(crate) fn strip_frontmatter<T>(mut events: Vec<(Event<'_>, T)>) -> Vec<(Event<'_>, T)> {
+    let heading = Tag::Heading(2);
+    let event = |index: usize| events.get(index).map(|(event, _)| event);
+    if event(0) != Some(&Event::Rule) || event(1) != Some(&Event::Start(heading.clone())) {
+        return events;
+    }
+    let end = match events
+        .iter()
+        .position(|(event, _)| *event == Event::End(heading.clone()))
+    {
+        Some(end) => end,
+        None => return events,
+    };
+    let mut frontmatter = events[2..=end].iter().map(|(event, _)| event.clone());
+    let text = collect_text(&mut frontmatter, &heading, '\n');
+    if parse_frontmatter(&text).is_some() {
+        events.drain(..=end);
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn metadata(markdown: &str) -> Metadata {
+        super::metadata(Parser::new(markdown))
+    }
+
+    #[test]
+    fn title_and_description_from_text() {
+        let result = metadata("## Intro\n\nSome `text`\nhere.\n\n# The *title*\n\nMore.\n");
+        assert_eq!(result.title.as_deref(), Some("The title"));
+        assert_eq!(result.description.as_deref(), Some("Some text here."));
+        assert!(result.frontmatter.is_empty());
+    }
+
+    #[test]
+    fn frontmatter() {
+        let markdown = "---\ntitle: \"Foo\"\nauthor: Bar\n---\n\n# Heading\n\nText.\n";
+        let result = metadata(markdown);
+        assert_eq!(result.title.as_deref(), Some("Foo"));
+        assert_eq!(result.description.as_deref(), Some("Text."));
+        assert_eq!(result.get("author"), Some("Bar"));
+        assert_eq!(
+            result.frontmatter,
+            vec![
+                ("title".to_string(), "Foo".to_string()),
+                ("author".to_string(), "Bar".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_frontmatter() {
+        let events = |markdown| {
+            Parser::new(markdown)
+                .map(|event| (event, ()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            super::strip_frontmatter(events("---\ntitle: Foo\n---\n\nText.\n")),
+            events("Text.\n")
+        );
+        let markdown = "---\nNot frontmatter\n---\n";
+        assert_eq!(super::strip_frontmatter(events(markdown)), events(markdown));
+    }
+
+    #[test]
+    fn no_frontmatter() {
+        let result = metadata("---\nNot frontmatter\n---\n");
+        assert!(result.frontmatter.is_empty());
+        assert_eq!(result.title, None);
+    }
+}
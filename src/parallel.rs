@@ -0,0 +1,151 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Render many documents in parallel.
+
+use crate::error::{Error, ErrorKind};
+use crate::{parse, push_tty, Settings};
+use pulldown_cmark::Options;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A document to render with [`render_parallel`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// The base directory to resolve relative links and images against.
+    pub base_dir: PathBuf,
+    /// The markdown source of the document.
+    pub markdown: String,
+}
+
+/// The result of rendering a document in a worker thread.
+///
+/// Errors are not `Send`, so take their kind and message only.
+type WorkerResult = Result<Vec<u8>, (ErrorKind, String)>;
+
+/// Render a single `document`.
+fn render(settings: &Settings, options: Options, document: &Document) -> WorkerResult {
+    let mut buffer = Vec::new();
+    let parser = parse(&document.markdown, options, settings);
+    match push_tty(settings, &mut buffer, &document.base_dir, parser) {
+        Ok(_) => Ok(buffer),
+        Err(error) => {
+            let error = Error::from(error);
+            Err((error.kind(), error.to_string()))
+        }
+    }
+}
+
+/// Render `documents` in parallel, each into a buffer of its own.
+///
+/// Parse documents with the given markdown `options`, and render them with at
+/// most `threads` worker threads, which share `settings`, in particular the
+/// loaded syntax set, the theme, and the image cache.
+///
+/// Return the output of every document, or the error which stopped rendering
+/// it, in the order of `documents`.
+pub fn render_parallel(
+    settings: Arc<Settings>,
+    options: Options,
+    documents: Vec<Document>,
+    threads: usize,
+) -> Vec<Result<Vec<u8>, Error>> {
+    let count = documents.len();
+    let queue: Arc<Mutex<VecDeque<_>>> =
+        Arc::new(Mutex::new(documents.into_iter().enumerate().collect()));
+    let results: Arc<Mutex<Vec<Option<WorkerResult>>>> = Arc::new(Mutex::new(vec![None; count]));
+    let handles: Vec<_> = (0..threads.max(1).min(count))
+        .map(|_| {
+            let settings = settings.clone();
+            let queue = queue.clone();
+            let results = results.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().pop_front();
+                match next {
+                    Some((index, document)) => {
+                        let result = render(&settings, options, &document);
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                    None => break,
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        // Documents whose worker panicked have no result; we report them below
+        handle.join().ok();
+    }
+    let mut results = results.lock().unwrap();
+    results
+        .drain(..)
+        .map(|result| match result {
+            Some(Ok(buffer)) => Ok(buffer),
+            Some(Err((kind, message))) => Err(Error::new(kind, message)),
+            None => Err(Error::new(ErrorKind::Render, "Rendering panicked")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{plain_settings, render_with};
+    use crate::CancellationToken;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn render_documents_in_parallel() {
+        let documents: Vec<Document> = (0..5)
+            .map(|n| Document {
+                base_dir: std::path::PathBuf::from("/"),
+                markdown: format!("# Document {}\n\nSome *text* {}.\n", n, n),
+            })
+            .collect();
+        let settings = plain_settings();
+        let expected: Vec<String> = documents
+            .iter()
+            .map(|document| render_with(&settings, &document.markdown))
+            .collect();
+        let options = pulldown_cmark::Options::empty();
+        let results = render_parallel(Arc::new(settings), options, documents, 3);
+        let results: Vec<String> = results
+            .into_iter()
+            .map(|result| String::from_utf8(result.unwrap()).unwrap())
+            .collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn report_errors_of_parallel_rendering() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let settings = Settings {
+            cancellation: Some(token),
+            ..plain_settings()
+        };
+        let document = Document {
+            base_dir: std::path::PathBuf::from("/"),
+            markdown: "Foo\n".to_string(),
+        };
+        let options = pulldown_cmark::Options::empty();
+        let results = render_parallel(Arc::new(settings), options, vec![document; 2], 4);
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let kind = result.unwrap_err().kind();
+            assert_eq!(kind, crate::error::ErrorKind::Cancelled);
+        }
+    }
+}
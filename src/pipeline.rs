@@ -0,0 +1,100 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Passes over events before rendering.
+
+use pulldown_cmark::Event;
+
+/// An event which passes through a [`Pipeline`].
+pub type PassEvent<'a> = Event<'a>;
+
+/// A pass which turns every event into any number of events.
+type Pass = Box<dyn for<'a> Fn(PassEvent<'a>) -> Vec<PassEvent<'a>>>;
+
+/// Where to run a pass, relative to the built-in passes of mdcat.
+///
+/// mdcat first removes frontmatter and inserts tables of contents, then
+/// extracts abbreviations if enabled, and then turns superscripts and subscripts into events, if
+/// enabled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PassPosition {
+    /// Before all built-in passes, on the events of the parser.
+    First,
+    /// After removing frontmatter and inserting tables of contents.
+    AfterTableOfContents,
+    /// After extracting abbreviations.
+    AfterAbbreviations,
+    /// After all built-in passes, right before rendering.
+    Last,
+}
+
+/// Passes over events before rendering, in addition to the built-in passes.
+///
+/// Use passes to rewrite documents before rendering, e.g. to censor secrets,
+/// rewrite text or inject banners, and render with [`Pipeline::push_tty`].
+///
+/// Passes do not stream: tables of contents, abbreviations and links to
+/// headings need the whole document, so mdcat collects all events of a
+/// document and runs every pass over all of them before it writes anything.
+/// Memory is thus bounded per document only; use [`Renderer`](crate::Renderer)
+/// to render events as they come, without any passes.
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<(PassPosition, Pass)>,
+}
+
+impl Pipeline {
+    /// A pipeline with only the built-in passes.
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Add a `pass` at the given `position`.
+    ///
+    /// Run passes at the same position in the order they were added.
+    pub fn with_pass<F>(mut self, position: PassPosition, pass: F) -> Pipeline
+    where
+        F: for<'a> Fn(PassEvent<'a>) -> Vec<PassEvent<'a>> + 'static,
+    {
+        self.passes.push((position, Box::new(pass)));
+        self
+    }
+
+    /// Run all passes at `position` over `events`.
+    ///
+    /// Every event comes with a `T`, e.g. its offset in the source; all events
+    /// a pass turns an event into take the `T` of that event.
+    pub(crate) fn run<'a, T: Clone>(
+        &self,
+        position: PassPosition,
+        mut events: Vec<(PassEvent<'a>, T)>,
+    ) -> Vec<(PassEvent<'a>, T)> {
+        for (_, pass) in self.passes.iter().filter(|(p, _)| *p == position) {
+            events = events
+                .into_iter()
+                .flat_map(|(event, t)| pass(event).into_iter().map(move |event| (event, t.clone())))
+                .collect();
+        }
+        events
+    }
+}
+
+impl std::fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let positions: Vec<_> = self.passes.iter().map(|(position, _)| position).collect();
+        f.debug_struct("Pipeline")
+            .field("passes", &positions)
+            .finish()
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Render markdown event by event.
+
+use crate::context_write::{write_event, Context};
+use crate::error::{Error, ErrorKind};
+use crate::{check_cancelled, Settings};
+use pulldown_cmark::Event;
+use std::error::Error as StdError;
+use std::io::Write;
+use std::path::Path;
+
+/// Render markdown to a TTY event by event.
+///
+/// A low-level alternative to [`push_tty`](crate::push_tty) for consumers
+/// which receive a document piece by piece, e.g. chat UIs or REPLs: create a
+/// renderer, push every event as it arrives, and finish the renderer at the
+/// end of the document to write pending links.
+///
+/// The renderer writes events as they come, so it cannot look ahead: unlike
+/// `push_tty` it neither strips front matter, nor inserts a table of contents,
+/// nor renders abbreviations, superscript or subscript, nor resolves links to
+/// headings of the document.  It also ignores `settings.max_lines`,
+/// `settings.max_output_bytes` and `settings.max_render_time`, but stops if
+/// `settings.cancellation` is cancelled.
+pub struct Renderer<'a, 'e, W: Write> {
+    /// The settings to render with.
+    settings: &'a Settings,
+    /// The rendering context, or `None` if rendering failed before.
+    context: Option<Context<'a, 'e, W>>,
+}
+
+impl<'a, 'e, W: Write> Renderer<'a, 'e, W> {
+    /// Create a renderer which writes to `writer`.
+    ///
+    /// Render with the given `settings`, and resolve relative references
+    /// against `base_dir`.
+    pub fn new(settings: &'a Settings, writer: &'a mut W, base_dir: &'a Path) -> Self {
+        Renderer {
+            settings,
+            context: Some(Context::new(writer, settings, base_dir)),
+        }
+    }
+
+    /// Render the next `event` of the document.
+    ///
+    /// Fail if writing fails, or if rendering was cancelled.  Once this
+    /// method failed the renderer is broken, and fails on every further event.
+    pub fn push_event(&mut self, event: Event<'e>) -> Result<(), Box<dyn StdError>> {
+        let context = self.context.take().ok_or_else(failed_before)?;
+        check_cancelled(self.settings)?;
+        self.context = Some(write_event(context, event)?);
+        Ok(())
+    }
+
+    /// Finish the document.
+    ///
+    /// Write all links still pending, e.g. reference links at the end of the
+    /// document.
+    pub fn finish(self) -> Result<(), Box<dyn StdError>> {
+        let mut context = self.context.ok_or_else(failed_before)?;
+        context.write_pending_links()?;
+        Ok(())
+    }
+}
+
+/// The error for a renderer which failed before.
+fn failed_before() -> Error {
+    Error::new(ErrorKind::Render, "Rendering failed before")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{plain_settings, render_with};
+    use crate::CancellationToken;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    #[test]
+    fn render_event_by_event() {
+        let settings = plain_settings();
+        let markdown = "# Foo\n\nSome *text* with a [link][1].\n\n[1]: https://example.com\n";
+        let mut sink = Vec::new();
+        let mut renderer = Renderer::new(&settings, &mut sink, Path::new("/"));
+        for event in Parser::new(markdown) {
+            renderer.push_event(event).unwrap();
+        }
+        renderer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(sink).unwrap(),
+            render_with(&settings, markdown)
+        );
+    }
+
+    #[test]
+    fn fail_renderer_after_cancellation() {
+        let token = CancellationToken::new();
+        let settings = Settings {
+            cancellation: Some(token.clone()),
+            ..plain_settings()
+        };
+        let mut sink = Vec::new();
+        let mut renderer = Renderer::new(&settings, &mut sink, Path::new("/"));
+        renderer.push_event(Event::Text("foo".into())).unwrap();
+        token.cancel();
+        assert!(renderer.push_event(Event::Text("bar".into())).is_err());
+        assert!(renderer.finish().is_err());
+    }
+}
@@ -14,9 +14,12 @@
 
 //! Access to resources referenced from markdown documents.
 
+use crate::CancellationToken;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use url::Url;
 
 /// What kind of resources mdcat may access when rendering.
@@ -42,18 +45,279 @@ impl ResourceAccess {
     }
 }
 
+/// Which local files mdcat may access when rendering.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LocalFileAccess {
+    /// Access all local files.
+    Anywhere,
+    /// Access only files inside the base directory of a document.
+    BaseDirOnly,
+}
+
+impl LocalFileAccess {
+    /// Whether to permit access to `url` for a document in `base_dir`.
+    ///
+    /// Permit all URLs which are no local files, and leave these to
+    /// [`ResourceAccess`].  Resolve symlinks and `..` before comparing paths,
+    /// and deny access to local files which do not exist.
+    pub fn permits(self, url: &Url, base_dir: &Path) -> bool {
+        match self {
+            LocalFileAccess::Anywhere => true,
+            LocalFileAccess::BaseDirOnly => match url.to_file_path() {
+                Ok(path) if url.scheme() == "file" => {
+                    match (path.canonicalize(), base_dir.canonicalize()) {
+                        (Ok(path), Ok(base_dir)) => path.starts_with(base_dir),
+                        _ => false,
+                    }
+                }
+                _ => true,
+            },
+        }
+    }
+}
+
+/// How to authenticate HTTP requests.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpAuth {
+    /// Send a bearer token, e.g. a personal access token.
+    Bearer(String),
+    /// Send a user name and a password.
+    Basic(String, String),
+}
+
+/// Authentication and headers for HTTP requests to a single host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpHost {
+    /// The name of the host, e.g. `raw.githubusercontent.com`.
+    pub host: String,
+    /// How to authenticate requests to the host, if at all.
+    pub auth: Option<HttpAuth>,
+    /// Additional headers to send to the host, as pairs of names and values.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Settings for HTTP requests for remote resources.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HttpSettings {
+    /// Authentication and headers per host.
+    pub hosts: Vec<HttpHost>,
+    /// The proxy for all HTTP(S) requests, e.g. `http://proxy.example.com:3128`.
+    ///
+    /// If `None` use the proxy from the standard `$HTTPS_PROXY`, `$HTTP_PROXY`
+    /// and `$ALL_PROXY` environment variables, if any.  Never use a proxy for
+    /// hosts listed in `$NO_PROXY`.
+    pub proxy: Option<String>,
+    /// A file with additional trusted CA certificates in PEM format.
+    pub ca_bundle: Option<PathBuf>,
+    /// Whether to accept invalid certificates for HTTPS requests.
+    ///
+    /// This makes HTTPS requests insecure; use only as a last resort, e.g. in
+    /// intranets with self-signed certificates.
+    pub insecure: bool,
+    /// The timeout for HTTP(S) requests, if any.
+    pub timeout: Option<Duration>,
+    /// A token to abort HTTP(S) requests with, if any.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl HttpSettings {
+    /// The headers to send with a request for `url`.
+    ///
+    /// Take the headers of all hosts which match the host of `url`, and add
+    /// an `Authorization` header, but only for HTTPS URLs, to never send
+    /// credentials in clear text.
+    fn headers(&self, url: &Url) -> Vec<(String, String)> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return Vec::new(),
+        };
+        let mut headers = Vec::new();
+        for entry in self
+            .hosts
+            .iter()
+            .filter(|entry| entry.host.eq_ignore_ascii_case(host))
+        {
+            let authorization = match entry.auth {
+                Some(_) if url.scheme() != "https" => None,
+                Some(HttpAuth::Bearer(ref token)) => Some(format!("Bearer {}", token)),
+                Some(HttpAuth::Basic(ref user, ref password)) => Some(format!(
+                    "Basic {}",
+                    base64::encode(format!("{}:{}", user, password))
+                )),
+                None => None,
+            };
+            if let Some(authorization) = authorization {
+                headers.push(("Authorization".to_string(), authorization));
+            }
+            headers.extend(entry.headers.iter().cloned());
+        }
+        headers
+    }
+
+    /// Whether to bypass any proxy for a request to `url`.
+    fn bypasses_proxy(&self, url: &Url) -> bool {
+        let no_proxy = std::env::var("NO_PROXY").or_else(|_| std::env::var("no_proxy"));
+        match (no_proxy, url.host_str()) {
+            (Ok(no_proxy), Some(host)) => no_proxy_matches(&no_proxy, host),
+            _ => false,
+        }
+    }
+}
+
+/// The timeout for a request which must finish before `deadline`.
+///
+/// Return `timeout`, but never more than the time remaining until `deadline`.
+pub(crate) fn timeout_until(timeout: Option<Duration>, deadline: Instant) -> Duration {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    timeout.map_or(remaining, |timeout| timeout.min(remaining))
+}
+
+/// Whether `host` matches the `no_proxy` list.
+///
+/// `no_proxy` is a comma-separated list of host names, which match the host
+/// itself and all its subdomains, with an optional leading dot, or `*` to match
+/// all hosts.
+fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+/// A reader which fails once its token is cancelled.
+struct Cancellable<R: Read> {
+    inner: R,
+    token: Option<CancellationToken>,
+}
+
+impl<R: Read> Cancellable<R> {
+    /// Read from `inner` until `token` is cancelled, if any.
+    fn new(inner: R, token: Option<CancellationToken>) -> Self {
+        Cancellable { inner, token }
+    }
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.token {
+            // Not Interrupted, because read_to_end retries interrupted reads
+            Some(ref token) if token.is_cancelled() => {
+                Err(Error::new(ErrorKind::Other, "Request cancelled"))
+            }
+            _ => self.inner.read(buf),
+        }
+    }
+}
+
 /// Whether `url` is readable as local file:.
 fn is_local(url: &Url) -> bool {
     url.scheme() == "file" && url.to_file_path().is_ok()
 }
 
+/// Read all of `source`, but fail if it has more than `max_bytes`.
+fn read_limited<R: Read>(
+    source: R,
+    url: &Url,
+    max_bytes: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut buffer = Vec::new();
+    match max_bytes {
+        None => {
+            let mut source = source;
+            source.read_to_end(&mut buffer)?;
+        }
+        Some(max_bytes) => {
+            source.take(max_bytes as u64 + 1).read_to_end(&mut buffer)?;
+            if max_bytes < buffer.len() {
+                return Err(too_large(url, max_bytes).into());
+            }
+        }
+    }
+    Ok(buffer)
+}
+
+/// An error for a resource at `url` with more than `max_bytes`.
+fn too_large(url: &Url, max_bytes: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{} exceeds maximum size of {} bytes", url, max_bytes),
+    )
+}
+
+/// Split a `bundle` of PEM certificates into single certificates.
+#[cfg(feature = "reqwest")]
+fn pem_certificates(bundle: &str) -> Vec<&str> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certificates = Vec::new();
+    let mut rest = bundle;
+    while let Some(start) = rest.find(BEGIN) {
+        match rest[start..].find(END) {
+            Some(end) => {
+                let end = start + end + END.len();
+                certificates.push(&rest[start..end]);
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+    certificates
+}
+
+/// Read the CA bundle at `path`.
+fn read_ca_bundle(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Failed to read CA bundle {}: {}", path.display(), error),
+        )
+    })
+}
+
+/// Create a HTTP client for requests to `url`.
 #[cfg(feature = "reqwest")]
-fn fetch_http(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let mut response = reqwest::blocking::get(url.clone())?;
+fn http_client(
+    url: &Url,
+    http: &HttpSettings,
+) -> Result<reqwest::blocking::Client, Box<dyn std::error::Error>> {
+    use reqwest::{Certificate, Proxy};
+    let mut builder = reqwest::blocking::Client::builder();
+    // reqwest uses proxies from the environment by default, but doesn't
+    // support $NO_PROXY, so check it ourselves.  For HTTPS URLs reqwest tunnels
+    // through the proxy with CONNECT.
+    if http.bypasses_proxy(url) {
+        builder = builder.no_proxy();
+    } else if let Some(ref proxy) = http.proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+    if let Some(ref path) = http.ca_bundle {
+        let bundle = read_ca_bundle(path)?;
+        for certificate in pem_certificates(&bundle) {
+            builder = builder.add_root_certificate(Certificate::from_pem(certificate.as_bytes())?);
+        }
+    }
+    Ok(builder.danger_accept_invalid_certs(http.insecure).build()?)
+}
+
+#[cfg(feature = "reqwest")]
+fn fetch_http(
+    url: &Url,
+    max_bytes: Option<usize>,
+    http: &HttpSettings,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut request = http_client(url, http)?.get(url.clone());
+    for (name, value) in http.headers(url) {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    if let Some(timeout) = http.timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send()?;
     if response.status().is_success() {
-        let mut buffer = Vec::new();
-        response.read_to_end(&mut buffer)?;
-        Ok(buffer)
+        let response = Cancellable::new(response, http.cancellation.clone());
+        read_limited(response, url, max_bytes)
     } else {
         Err(Error::new(
             ErrorKind::Other,
@@ -63,15 +327,104 @@ fn fetch_http(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     }
 }
 
+#[cfg(feature = "reqwest")]
+fn check_http(
+    url: &Url,
+    http: &HttpSettings,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = http_client(url, http)?;
+    let send = |mut request: reqwest::blocking::RequestBuilder| {
+        for (name, value) in http.headers(url) {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request.timeout(timeout).send()
+    };
+    let response = send(client.head(url.clone()))?;
+    if response.status().is_success() {
+        return Ok(());
+    }
+    // Some servers refuse HEAD requests; retry with a GET, but don't read the
+    // body
+    let response = send(client.get(url.clone()))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("HTTP error status {} by GET {}", response.status(), url),
+        )
+        .into())
+    }
+}
+
+/// Quote `value` as string in a curl configuration file.
+#[cfg(not(feature = "reqwest"))]
+fn curl_config_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Format `duration` as seconds for curl.
+#[cfg(not(feature = "reqwest"))]
+fn curl_seconds(duration: Duration) -> String {
+    format!("{}.{:03}", duration.as_secs(), duration.subsec_millis())
+}
+
+/// Run curl with `args` for `url`.
+///
+/// Pass proxy and TLS settings and headers through standard input, to keep
+/// credentials out of the process list.  Fail if curl fails.
 #[cfg(not(feature = "reqwest"))]
-fn fetch_http(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let output = std::process::Command::new("curl")
-        .arg("-fsSL")
+fn curl(
+    url: &Url,
+    http: &HttpSettings,
+    args: &[&str],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use std::process::Stdio;
+    let mut child = std::process::Command::new("curl")
+        .args(args)
+        .arg("--config")
+        .arg("-")
         .arg(url.to_string())
-        .output()?;
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // curl uses proxies from the environment by default, and tunnels HTTPS
+        // through proxies with CONNECT
+        if http.bypasses_proxy(url) {
+            writeln!(stdin, "noproxy = \"*\"")?;
+        } else if let Some(ref proxy) = http.proxy {
+            writeln!(stdin, "proxy = {}", curl_config_string(proxy))?;
+        }
+        if let Some(ref path) = http.ca_bundle {
+            // Fail early and with a clear message if we can't read the bundle
+            read_ca_bundle(path)?;
+            let path = path.to_string_lossy();
+            writeln!(stdin, "cacert = {}", curl_config_string(&path))?;
+        }
+        if http.insecure {
+            writeln!(stdin, "insecure")?;
+        }
+        for (name, value) in http.headers(url) {
+            let header = curl_config_string(&format!("{}: {}", name, value));
+            writeln!(stdin, "header = {}", header)?;
+        }
+    }
+    let mut stdout = Vec::new();
+    if let Some(source) = child.stdout.take() {
+        let result = Cancellable::new(source, http.cancellation.clone()).read_to_end(&mut stdout);
+        if let Err(error) = result {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(error.into());
+        }
+    }
+    let output = child.wait_with_output()?;
 
     if output.status.success() {
-        Ok(output.stdout)
+        Ok(stdout)
     } else {
         Err(Error::new(
             ErrorKind::Other,
@@ -85,6 +438,44 @@ fn fetch_http(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     }
 }
 
+#[cfg(not(feature = "reqwest"))]
+fn fetch_http(
+    url: &Url,
+    max_bytes: Option<usize>,
+    http: &HttpSettings,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let max_bytes_arg = max_bytes.map(|max_bytes| max_bytes.to_string());
+    let timeout_arg = http.timeout.map(curl_seconds);
+    let mut args = vec!["-fsSL"];
+    if let Some(ref max_bytes) = max_bytes_arg {
+        args.extend_from_slice(&["--max-filesize", max_bytes]);
+    }
+    if let Some(ref timeout) = timeout_arg {
+        args.extend_from_slice(&["--max-time", timeout]);
+    }
+    let contents = curl(url, http, &args)?;
+    match max_bytes {
+        Some(max_bytes) if max_bytes < contents.len() => Err(too_large(url, max_bytes).into()),
+        _ => Ok(contents),
+    }
+}
+
+#[cfg(not(feature = "reqwest"))]
+fn check_http(
+    url: &Url,
+    http: &HttpSettings,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let timeout = curl_seconds(timeout);
+    let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+    let args = ["-fsSL", "--max-time", &timeout, "--output", null];
+    curl(url, http, &[&args[..], &["--head"]].concat())
+        // Some servers refuse HEAD requests; retry with a GET for the first
+        // byte
+        .or_else(|_| curl(url, http, &[&args[..], &["--range", "0-0"]].concat()))
+        .map(|_| ())
+}
+
 /// Read the contents of the given `url` if supported.
 ///
 /// Fail if we don’t know how to read from `url`, or if we fail to read from
@@ -93,21 +484,59 @@ fn fetch_http(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
 /// We currently support `file:` URLs which the underlying operation system can
 /// read (local on UNIX, UNC paths on Windows), and HTTP(S) URLs if enabled at
 /// build system.
-pub fn read_url(url: &Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+///
+/// If `max_bytes` is given fail if the contents of `url` exceed this size,
+/// without reading more than `max_bytes` into memory.  Send requests for HTTP
+/// URLs according to `http`.
+pub fn read_url(
+    url: &Url,
+    max_bytes: Option<usize>,
+    http: &HttpSettings,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     match url.scheme() {
         "file" => match url.to_file_path() {
-            Ok(path) => {
-                let mut buffer = Vec::new();
-                File::open(path)?.read_to_end(&mut buffer)?;
-                Ok(buffer)
-            }
+            Ok(path) => read_limited(File::open(path)?, url, max_bytes),
             Err(_) => Err(Error::new(
                 ErrorKind::InvalidInput,
                 format!("Remote file: URL {} not supported", url),
             )
             .into()),
         },
-        "http" | "https" => fetch_http(url),
+        "http" | "https" => fetch_http(url, max_bytes, http),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Protocol of URL {} not supported", url),
+        )
+        .into()),
+    }
+}
+
+/// Check whether the resource at `url` exists.
+///
+/// Check that local files exist, and send HEAD requests for HTTP(S) URLs, and
+/// fall back to GET requests if the server refuses HEAD, with the given
+/// `timeout` for each request.  Fail if the resource does not exist, or if we
+/// don't know how to check `url`.
+pub(crate) fn check_url(
+    url: &Url,
+    http: &HttpSettings,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match url.scheme() {
+        "file" => match url.to_file_path() {
+            Ok(ref path) if path.exists() => Ok(()),
+            Ok(path) => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} does not exist", path.display()),
+            )
+            .into()),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Remote file: URL {} not supported", url),
+            )
+            .into()),
+        },
+        "http" | "https" => check_http(url, http, timeout),
         _ => Err(Error::new(
             ErrorKind::InvalidInput,
             format!("Protocol of URL {} not supported", url),
@@ -121,6 +550,22 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn timeout_until_deadline() {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(
+            timeout_until(Some(Duration::from_secs(1)), deadline),
+            Duration::from_secs(1)
+        );
+        assert!(timeout_until(Some(Duration::from_secs(120)), deadline) <= Duration::from_secs(60));
+        assert!(timeout_until(None, deadline) <= Duration::from_secs(60));
+        let past = Instant::now();
+        assert_eq!(
+            timeout_until(Some(Duration::from_secs(1)), past),
+            Duration::from_secs(0)
+        );
+    }
+
     #[test]
     #[cfg(unix)]
     fn resource_access_permits_local_resource() {
@@ -144,12 +589,130 @@ mod tests {
         assert!(ResourceAccess::RemoteAllowed.permits(&resource));
     }
 
+    #[test]
+    fn local_file_access_permits_files_in_base_dir() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let base_dir = root.join("src");
+        let file = |path: &Path| Url::from_file_path(path).unwrap();
+        let inside = file(&base_dir.join("lib.rs"));
+        let outside = file(&root.join("Cargo.toml"));
+        let escaping = file(&base_dir.join("..").join("Cargo.toml"));
+        let missing = file(&base_dir.join("does-not-exist.rs"));
+        let remote = Url::parse("https://example.com/image.png").unwrap();
+        for url in &[&inside, &outside, &escaping, &missing, &remote] {
+            assert!(LocalFileAccess::Anywhere.permits(url, &base_dir));
+        }
+        let access = LocalFileAccess::BaseDirOnly;
+        assert!(access.permits(&inside, &base_dir));
+        assert!(!access.permits(&outside, &base_dir));
+        assert!(!access.permits(&escaping, &base_dir));
+        assert!(!access.permits(&missing, &base_dir));
+        assert!(access.permits(&remote, &base_dir));
+    }
+
+    #[test]
+    fn cancel_reading() {
+        let token = CancellationToken::new();
+        let mut reader = Cancellable::new(&b"foo"[..], Some(token.clone()));
+        let mut buffer = [0; 2];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 2);
+        token.cancel();
+        assert!(reader.read(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn http_headers_for_host() {
+        let http = HttpSettings {
+            hosts: vec![
+                HttpHost {
+                    host: "example.com".to_string(),
+                    auth: Some(HttpAuth::Basic("user".to_string(), "pass".to_string())),
+                    headers: vec![("X-Foo".to_string(), "bar".to_string())],
+                },
+                HttpHost {
+                    host: "example.org".to_string(),
+                    auth: Some(HttpAuth::Bearer("token".to_string())),
+                    headers: Vec::new(),
+                },
+            ],
+            proxy: None,
+            ca_bundle: None,
+            insecure: false,
+            timeout: None,
+            cancellation: None,
+        };
+        let headers = |url: &str| http.headers(&Url::parse(url).unwrap());
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            headers("https://EXAMPLE.com/image.png"),
+            vec![
+                header("Authorization", "Basic dXNlcjpwYXNz"),
+                header("X-Foo", "bar")
+            ]
+        );
+        assert_eq!(
+            headers("https://example.org/image.png"),
+            vec![header("Authorization", "Bearer token")]
+        );
+        // Never send credentials over plain HTTP
+        assert_eq!(
+            headers("http://example.com/image.png"),
+            vec![header("X-Foo", "bar")]
+        );
+        assert_eq!(headers("https://example.net/image.png"), Vec::new());
+    }
+
+    #[test]
+    fn no_proxy_matches_host_and_subdomains() {
+        let no_proxy = "localhost, .example.com,EXAMPLE.org";
+        assert!(no_proxy_matches(no_proxy, "localhost"));
+        assert!(no_proxy_matches(no_proxy, "example.com"));
+        assert!(no_proxy_matches(no_proxy, "images.Example.com"));
+        assert!(!no_proxy_matches(no_proxy, "notexample.com"));
+        assert!(no_proxy_matches(no_proxy, "example.org"));
+        assert!(!no_proxy_matches(no_proxy, "example.net"));
+        assert!(no_proxy_matches("*", "example.net"));
+        assert!(!no_proxy_matches("", "example.net"));
+    }
+
+    #[test]
+    #[cfg(feature = "reqwest")]
+    fn pem_certificates_in_bundle() {
+        let bundle = "# First\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                      # Second\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nCCCC\n";
+        assert_eq!(
+            pem_certificates(bundle),
+            vec![
+                "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+                "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----"
+            ]
+        );
+    }
+
+    #[test]
+    fn read_url_with_max_bytes() {
+        let path = std::env::current_dir().unwrap().join("Cargo.toml");
+        let url = Url::from_file_path(&path).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(
+            read_url(&url, Some(size), &HttpSettings::default())
+                .unwrap()
+                .len(),
+            size
+        );
+        let error = read_url(&url, Some(size - 1), &HttpSettings::default())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("exceeds maximum size"), "{}", error);
+    }
+
     #[test]
     fn read_url_with_http_url_fails_when_status_404() {
         let url = "https://eu.httpbin.org/status/404"
             .parse::<url::Url>()
             .unwrap();
-        let result = read_url(&url);
+        let result = read_url(&url, None, &HttpSettings::default());
         assert!(result.is_err(), "Unexpected success: {:?}", result);
         let error = result.unwrap_err().to_string();
         if cfg!(feature = "reqwest") {
@@ -176,7 +739,7 @@ mod tests {
         let url = "https://eu.httpbin.org/bytes/100"
             .parse::<url::Url>()
             .unwrap();
-        let result = read_url(&url);
+        let result = read_url(&url, None, &HttpSettings::default());
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
         assert_eq!(result.unwrap().len(), 100);
     }
@@ -0,0 +1,285 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Superscript and subscript text.
+//!
+//! Support the `^superscript^` and `~subscript~` extensions, which
+//! pulldown-cmark does not parse.
+
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::iter::Peekable;
+use std::ops::Range;
+
+/// The superscript variant of `c`, if Unicode has one.
+pub fn superscript(c: char) -> Option<char> {
+    let superscript = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        _ => return None,
+    };
+    Some(superscript)
+}
+
+/// The subscript variant of `c`, if Unicode has one.
+pub fn subscript(c: char) -> Option<char> {
+    let subscript = match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    };
+    Some(subscript)
+}
+
+/// Write `text` in superscript or subscript.
+///
+/// Map every character with `script`; if any character has no mapping write
+/// `text` in parenthesis after `fallback` instead, e.g. `^(text)`.
+fn write_script<F>(result: &mut String, text: &str, script: F, fallback: char)
+where
+    F: Fn(char) -> Option<char>,
+{
+    match text.chars().map(script).collect::<Option<String>>() {
+        Some(mapped) => result.push_str(&mapped),
+        None => {
+            result.push(fallback);
+            result.push('(');
+            result.push_str(text);
+            result.push(')');
+        }
+    }
+}
+
+/// Replace `^superscript^` and `~subscript~` in `text`.
+///
+/// Like Pandoc, only consider spans which are not empty and contain no
+/// whitespace.
+pub fn replace_scripts(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(&['^', '~'][..]) {
+        result.push_str(&rest[..start]);
+        let delimiter = rest[start..].chars().next().unwrap();
+        let after = &rest[start + 1..];
+        match after.find(delimiter) {
+            Some(end) if 0 < end && !after[..end].contains(char::is_whitespace) => {
+                if delimiter == '^' {
+                    write_script(&mut result, &after[..end], superscript, '^');
+                } else {
+                    write_script(&mut result, &after[..end], subscript, '_');
+                }
+                rest = &after[end + 1..];
+            }
+            _ => {
+                result.push(delimiter);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Turn strikethrough between single tildes back into text.
+///
+/// With strikethrough enabled pulldown-cmark parses `~x~` like `~~x~~`, so
+/// subscript never reaches the text of events.  Take `events` along with their
+/// offsets in `source`, as from `pulldown_cmark::Parser::into_offset_iter`, and
+/// replace the start and end of every strikethrough delimited by a single
+/// tilde with a `~` text event, for [`with_scripts`] to pick up.
+pub fn subscripts_from_strikethrough<'a, I>(
+    source: &'a str,
+    events: I,
+) -> impl Iterator<Item = Event<'a>>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    events.map(move |(event, range)| match event {
+        Event::Start(Tag::Strikethrough) | Event::End(Tag::Strikethrough)
+            if is_single_tilde(&source[range.start..]) =>
+        {
+            Event::Text(CowStr::Borrowed("~"))
+        }
+        event => event,
+    })
+}
+
+/// Whether `text` starts with a single tilde.
+fn is_single_tilde(text: &str) -> bool {
+    text.starts_with('~') && !text.starts_with("~~")
+}
+
+/// Replace superscript and subscript in the text of `events`.
+///
+/// Join adjacent text events first, because pulldown-cmark splits text at
+/// `~` and `^`.  Leave text in code blocks alone.
+///
+/// Every event comes with a `T`, e.g. its offset in the source; joined text
+/// takes the `T` of its first event.
+pub fn with_scripts<'a, T, I>(events: I) -> WithScripts<I>
+where
+    I: Iterator<Item = (Event<'a>, T)>,
+{
+    WithScripts {
+        events: events.peekable(),
+        inside_code_block: false,
+    }
+}
+
+/// An iterator over events with superscript and subscript text.
+///
+/// See [`with_scripts`].
+pub struct WithScripts<I: Iterator> {
+    events: Peekable<I>,
+    inside_code_block: bool,
+}
+
+impl<'a, T, I: Iterator<Item = (Event<'a>, T)>> Iterator for WithScripts<I> {
+    type Item = (Event<'a>, T);
+
+    fn next(&mut self) -> Option<(Event<'a>, T)> {
+        match self.events.next()? {
+            (Event::Text(text), t) if !self.inside_code_block => {
+                let mut text = text.into_string();
+                while let Some((Event::Text(next), _)) = self.events.peek() {
+                    text.push_str(next);
+                    self.events.next();
+                }
+                Some((Event::Text(CowStr::from(replace_scripts(&text))), t))
+            }
+            (event, t) => {
+                match event {
+                    Event::Start(Tag::CodeBlock(_)) => self.inside_code_block = true,
+                    Event::End(Tag::CodeBlock(_)) => self.inside_code_block = false,
+                    _ => {}
+                }
+                Some((event, t))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{CowStr, Event, Options, Parser};
+
+    fn render_text(markdown: &str) -> Vec<Event<'_>> {
+        let parser = Parser::new_ext(markdown, crate::markdown_options()).into_offset_iter();
+        let events = super::subscripts_from_strikethrough(markdown, parser);
+        super::with_scripts(events.map(|event| (event, ())))
+            .map(|(event, _)| event)
+            .filter(|event| matches!(event, Event::Text(_) | Event::Start(_)))
+            .collect()
+    }
+
+    #[test]
+    fn subscript_with_strikethrough_enabled() {
+        use pulldown_cmark::Tag::*;
+        assert_eq!(
+            render_text("H~2~O"),
+            vec![
+                Event::Start(Paragraph),
+                Event::Text(CowStr::Borrowed("H₂O"))
+            ]
+        );
+        assert_eq!(
+            render_text("~~gone~~"),
+            vec![
+                Event::Start(Paragraph),
+                Event::Start(Strikethrough),
+                Event::Text(CowStr::Borrowed("gone"))
+            ]
+        );
+        assert!(crate::markdown_options().contains(Options::ENABLE_STRIKETHROUGH));
+    }
+
+    #[test]
+    fn replace_scripts() {
+        assert_eq!(super::replace_scripts("H~2~O"), "H₂O");
+        assert_eq!(super::replace_scripts("2^10^ bytes"), "2¹⁰ bytes");
+        assert_eq!(super::replace_scripts("x^2q^"), "x^(2q)");
+        assert_eq!(super::replace_scripts("CO~2(g)~"), "CO_(2(g))");
+        assert_eq!(super::replace_scripts("a ^ b ^ c"), "a ^ b ^ c");
+        assert_eq!(super::replace_scripts("^^"), "^^");
+        assert_eq!(super::replace_scripts("~foo"), "~foo");
+    }
+}
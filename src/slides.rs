@@ -0,0 +1,241 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Split documents into slides for presentations.
+//!
+//! Control presentations with directives in HTML comments:
+//!
+//! - `<!-- pause -->` pauses before the following content of a slide.
+//! - `<!-- notes: … -->` adds speaker notes to a slide.
+//! - `<!-- incremental_lists: true -->` pauses before every list item but
+//!   the first one of each list, until `<!-- incremental_lists: false -->`.
+
+use crate::balance::balance;
+use crate::html;
+use pulldown_cmark::{Event, Tag};
+
+/// A single slide of a presentation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Slide<'a> {
+    /// The events of the slide.
+    pub events: Vec<Event<'a>>,
+    /// The indices of the events to pause before, in order.
+    pub pauses: Vec<usize>,
+    /// The speaker notes of the slide.
+    pub notes: Vec<String>,
+}
+
+impl<'a> Slide<'a> {
+    /// The steps to reveal this slide in.
+    ///
+    /// Return the events up to every pause, with all tags closed, and finally
+    /// all events of the slide.
+    pub fn steps(&self) -> Vec<Vec<Event<'a>>> {
+        let mut steps: Vec<Vec<Event<'a>>> = self
+            .pauses
+            .iter()
+            .filter(|pause| 0 < **pause && **pause < self.events.len())
+            .map(|pause| balance(self.events[..*pause].iter().cloned()).collect())
+            .collect();
+        steps.dedup();
+        steps.push(self.events.clone());
+        steps
+    }
+}
+
+/// A directive for presentations in an HTML comment.
+#[derive(Debug, PartialEq)]
+enum Directive {
+    /// Pause before the following events.
+    Pause,
+    /// Add speaker notes.
+    Notes(String),
+    /// Whether to pause before list items.
+    IncrementalLists(bool),
+}
+
+/// Parse the directive in the HTML comment `html`.
+fn parse_directive(html: &str) -> Option<Directive> {
+    let html = html.trim();
+    if html.len() < 7 || !html.starts_with("<!--") || !html.ends_with("-->") {
+        return None;
+    }
+    let mut parts = html[4..html.len() - 3].trim().splitn(2, ':');
+    let name = parts.next()?.trim();
+    match (name, parts.next().map(str::trim)) {
+        ("pause", None) => Some(Directive::Pause),
+        ("notes", Some(notes)) => Some(Directive::Notes(notes.to_string())),
+        ("incremental_lists", Some("true")) => Some(Directive::IncrementalLists(true)),
+        ("incremental_lists", Some("false")) => Some(Directive::IncrementalLists(false)),
+        _ => None,
+    }
+}
+
+/// Split `events` into slides at level-1 and level-2 headings.
+///
+/// Start a new slide at every level-1 or level-2 heading outside of other
+/// blocks, e.g. block quotes or lists.  Put events before the first of these
+/// headings onto a slide of their own, unless there are none.
+///
+/// Take directives out of the events of slides, and apply them to the slide
+/// they appear in.
+pub fn split_slides(events: Vec<Event<'_>>) -> Vec<Slide<'_>> {
+    let mut slides = Vec::new();
+    let mut current = Slide::default();
+    let mut depth = 0usize;
+    let mut incremental_lists = false;
+    // Whether the next item is the first one, for every open list
+    let mut first_items = Vec::new();
+    // The text of the current HTML comment, if it spans multiple events
+    let mut comment: Option<String> = None;
+    for event in events {
+        let event = match (comment.take(), event) {
+            (Some(mut text), Event::Html(html)) => {
+                text.push_str(&html);
+                if !html.contains("-->") {
+                    comment = Some(text);
+                    continue;
+                }
+                Event::Html(text.into())
+            }
+            (Some(text), event) => {
+                current.events.push(Event::Html(text.into()));
+                event
+            }
+            (None, Event::Html(html)) if html::starts_comment(&html) && !html.contains("-->") => {
+                comment = Some(html.into_string());
+                continue;
+            }
+            (None, event) => event,
+        };
+        if let Event::Html(ref html) = event {
+            if let Some(directive) = parse_directive(html) {
+                match directive {
+                    Directive::Pause => current.pauses.push(current.events.len()),
+                    Directive::Notes(notes) => current.notes.push(notes),
+                    Directive::IncrementalLists(enabled) => incremental_lists = enabled,
+                }
+                continue;
+            }
+        }
+        match event {
+            Event::Start(Tag::Heading(level)) if depth == 0 && level <= 2 => {
+                if !current.events.is_empty() {
+                    slides.push(std::mem::take(&mut current));
+                }
+                depth += 1;
+            }
+            Event::Start(Tag::List(_)) => {
+                first_items.push(true);
+                depth += 1;
+            }
+            Event::Start(Tag::Item) => {
+                if let Some(first) = first_items.last_mut() {
+                    if incremental_lists && !*first {
+                        current.pauses.push(current.events.len());
+                    }
+                    *first = false;
+                }
+                depth += 1;
+            }
+            Event::End(Tag::List(_)) => {
+                first_items.pop();
+                depth = depth.saturating_sub(1);
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        current.events.push(event);
+    }
+    if let Some(text) = comment {
+        current.events.push(Event::Html(text.into()));
+    }
+    if !current.events.is_empty() {
+        slides.push(current);
+    }
+    slides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn events(markdown: &str) -> Vec<Event<'_>> {
+        Parser::new(markdown).collect()
+    }
+
+    #[test]
+    fn split_at_headings() {
+        let markdown = "Title\n\n# One\n\nText\n\n### Nested\n\n## Two\n\n> # Quoted\n";
+        let slides = split_slides(events(markdown));
+        assert_eq!(
+            slides,
+            vec![
+                Slide {
+                    events: events("Title\n"),
+                    ..Slide::default()
+                },
+                Slide {
+                    events: events("# One\n\nText\n\n### Nested\n"),
+                    ..Slide::default()
+                },
+                Slide {
+                    events: events("## Two\n\n> # Quoted\n"),
+                    ..Slide::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pauses_and_notes() {
+        let markdown = "# One\n\nA\n\n<!-- pause -->\n\nB\n\n<!-- notes: Say\nhello -->\n";
+        let slides = split_slides(events(markdown));
+        assert_eq!(
+            slides,
+            vec![Slide {
+                events: events("# One\n\nA\n\nB\n"),
+                pauses: vec![6],
+                notes: vec!["Say\nhello".to_string()],
+            }]
+        );
+        assert_eq!(
+            slides[0].steps(),
+            vec![events("# One\n\nA\n"), events("# One\n\nA\n\nB\n")]
+        );
+    }
+
+    #[test]
+    fn incremental_lists() {
+        let markdown = "* a\n* b\n\n<!-- incremental_lists: true -->\n\n1. c\n2. d\n";
+        let slides = split_slides(events(markdown));
+        assert_eq!(slides.len(), 1);
+        assert_eq!(slides[0].pauses, vec![12]);
+        assert_eq!(
+            slides[0].steps(),
+            vec![
+                events("* a\n* b\n\n1. c\n"),
+                events("* a\n* b\n\n1. c\n2. d\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn no_slides_without_events() {
+        assert_eq!(split_slides(Vec::new()), Vec::new());
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Slugs and permalinks of headings.
+//!
+//! Create slugs like GitHub does, so that links to sections of documents on
+//! GitHub work in mdcat, too.
+
+use pulldown_cmark::{Event, Tag};
+use std::collections::HashMap;
+use url::Url;
+
+/// Turn the `text` of a heading into a slug, e.g. `getting-started`.
+///
+/// Lowercase all letters, replace spaces with dashes, and drop all
+/// punctuation except for dashes and underscores, like GitHub.  Do not make
+/// slugs unique; use a [`Slugger`] for this.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| {
+            if c == ' ' {
+                Some('-')
+            } else if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Create unique slugs for all headings of a document.
+///
+/// Like GitHub, add a counter to repeated slugs, i.e. `foo`, `foo-1`, `foo-2`,
+/// etc.
+#[derive(Debug, Default, Clone)]
+pub struct Slugger {
+    /// The number of repetitions of every slug so far.
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// Create a slugger for a new document.
+    pub fn new() -> Slugger {
+        Slugger::default()
+    }
+
+    /// Create a unique slug for the next heading with the given `text`.
+    pub fn slug(&mut self, text: &str) -> String {
+        let original = slugify(text);
+        let mut slug = original.clone();
+        while self.occurrences.contains_key(&slug) {
+            let count = self.occurrences.entry(original.clone()).or_insert(0);
+            *count += 1;
+            slug = format!("{}-{}", original, count);
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// A heading of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    /// The level of the heading, from 1 to 6.
+    pub level: u32,
+    /// The text of the heading, without any markup.
+    pub text: String,
+    /// The unique slug of the heading.
+    pub slug: String,
+}
+
+/// Collect all headings in `events`, with unique slugs.
+pub fn headings<'a, 'e: 'a, I>(events: I) -> Vec<Heading>
+where
+    I: IntoIterator<Item = &'a Event<'e>>,
+{
+    let mut slugger = Slugger::new();
+    let mut headings = Vec::new();
+    let mut current: Option<(u32, String)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level)) => current = Some((*level, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, ref mut heading)) = current {
+                    heading.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(_)) => {
+                if let Some((level, text)) = current.take() {
+                    let slug = slugger.slug(&text);
+                    headings.push(Heading { level, text, slug });
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// Select the section with the given `slug` from `events`.
+///
+/// Return the heading with `slug` and all events up to the next heading of the
+/// same or a higher level, or `None` if no heading has `slug`.
+pub fn section<'a>(events: Vec<Event<'a>>, slug: &str) -> Option<Vec<Event<'a>>> {
+    let mut slugger = Slugger::new();
+    // The index and level of the heading with `slug`
+    let mut start: Option<(usize, u32)> = None;
+    let mut heading: Option<(usize, u32, String)> = None;
+    let mut end = events.len();
+    for (index, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Heading(level)) => match start {
+                Some((_, start_level)) if *level <= start_level => {
+                    end = index;
+                    break;
+                }
+                _ => heading = Some((index, *level, String::new())),
+            },
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, ref mut heading_text)) = heading {
+                    heading_text.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(_)) => {
+                if let Some((heading_index, level, text)) = heading.take() {
+                    if slugger.slug(&text) == slug && start.is_none() {
+                        start = Some((heading_index, level));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let (start, _) = start?;
+    Some(events.into_iter().take(end).skip(start).collect())
+}
+
+/// The permalink of the heading with the given `slug` in the document at
+/// `base_url`.
+pub fn permalink(base_url: &Url, slug: &str) -> Url {
+    let mut url = base_url.clone();
+    url.set_fragment(Some(slug));
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn slugify_headings() {
+        assert_eq!(slugify("Getting started"), "getting-started");
+        assert_eq!(slugify(" What's new in 0.16? "), "whats-new-in-016");
+        assert_eq!(slugify("snake_case and-dashes"), "snake_case-and-dashes");
+        assert_eq!(slugify("Ümlaut"), "ümlaut");
+    }
+
+    #[test]
+    fn unique_slugs() {
+        let mut slugger = Slugger::new();
+        assert_eq!(slugger.slug("Foo"), "foo");
+        assert_eq!(slugger.slug("foo"), "foo-1");
+        assert_eq!(slugger.slug("Foo 1"), "foo-1-1");
+        assert_eq!(slugger.slug("Foo"), "foo-2");
+        assert_eq!(slugger.slug("Bar"), "bar");
+    }
+
+    #[test]
+    fn collect_headings() {
+        use pulldown_cmark::Parser;
+        let events: Vec<Event> = Parser::new("# A `b`\n\ntext\n\n## A b\n").collect();
+        let heading = |level, text: &str, slug: &str| Heading {
+            level,
+            text: text.to_string(),
+            slug: slug.to_string(),
+        };
+        assert_eq!(
+            headings(&events),
+            vec![heading(1, "A b", "a-b"), heading(2, "A b", "a-b-1")]
+        );
+    }
+
+    #[test]
+    fn select_section() {
+        use pulldown_cmark::Parser;
+        let markdown = "# A\n\n## B\n\nb\n\n### C\n\n## B\n\nd\n";
+        let section = |slug| {
+            section(Parser::new(markdown).collect(), slug).map(|events| {
+                events
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        Event::Text(text) => Some(text.into_string()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            section("b"),
+            Some(vec!["B".to_string(), "b".into(), "C".into()])
+        );
+        assert_eq!(section("b-1"), Some(vec!["B".to_string(), "d".into()]));
+        assert_eq!(section("c"), Some(vec!["C".to_string()]));
+        assert_eq!(section("e"), None);
+    }
+
+    #[test]
+    fn permalink_with_slug() {
+        let base_url = Url::parse("https://example.com/README.md#old").unwrap();
+        assert_eq!(
+            permalink(&base_url, "getting-started").as_str(),
+            "https://example.com/README.md#getting-started"
+        );
+    }
+}
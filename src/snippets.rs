@@ -0,0 +1,86 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extract code blocks from documents.
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+
+/// A code block in a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// The language of the code block, if any.
+    pub language: Option<String>,
+    /// The code, as in the document.
+    pub code: String,
+}
+
+/// Extract all code blocks from `events`, in order.
+///
+/// Snippets count from 1 in the output of mdcat if
+/// [`Settings::number_snippets`](crate::Settings::number_snippets) is enabled;
+/// the n-th snippet has index n - 1 in the returned list.
+pub fn snippets<'a, I>(events: I) -> Vec<Snippet>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut snippets = Vec::new();
+    let mut current: Option<Snippet> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(ref name) if !name.is_empty() => Some(name.to_string()),
+                    _ => None,
+                };
+                current = Some(Snippet {
+                    language,
+                    code: String::new(),
+                });
+            }
+            Event::Text(text) => {
+                if let Some(ref mut snippet) = current {
+                    snippet.code.push_str(&text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => snippets.extend(current.take()),
+            _ => {}
+        }
+    }
+    snippets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    #[test]
+    fn extract_snippets() {
+        let markdown = "Text `code`\n\n```rust\nfn main() {}\n```\n\n    $ cargo run\n";
+        assert_eq!(
+            snippets(Parser::new(markdown)),
+            vec![
+                Snippet {
+                    language: Some("rust".to_string()),
+                    code: "fn main() {}\n".to_string()
+                },
+                Snippet {
+                    language: None,
+                    code: "$ cargo run\n".to_string()
+                }
+            ]
+        );
+    }
+}
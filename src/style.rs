@@ -0,0 +1,183 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Text styles.
+
+use anstyle::{Color, Effects};
+use std::io::{Result, Write};
+
+/// The style of text: Its colours and attributes.
+///
+/// Convert from and to [anstyle] styles for interoperability with other
+/// crates.
+///
+/// [anstyle]: https://docs.rs/anstyle
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Style(anstyle::Style);
+
+impl Style {
+    /// A style without any colours or attributes.
+    pub fn new() -> Style {
+        Style(anstyle::Style::new())
+    }
+
+    /// This style with the given foreground `colour`.
+    pub fn fg<C: Into<Color>>(self, colour: C) -> Style {
+        Style(self.0.fg_color(Some(colour.into())))
+    }
+
+    /// This style with the given background `colour`.
+    pub fn on<C: Into<Color>>(self, colour: C) -> Style {
+        Style(self.0.bg_color(Some(colour.into())))
+    }
+
+    /// This style with the given `colour` for underlines.
+    pub fn underline_colour<C: Into<Color>>(self, colour: C) -> Style {
+        Style(self.0.underline_color(Some(colour.into())))
+    }
+
+    /// This style in bold.
+    pub fn bold(self) -> Style {
+        Style(self.0.bold())
+    }
+
+    /// This style dimmed.
+    pub fn dimmed(self) -> Style {
+        Style(self.0.dimmed())
+    }
+
+    /// This style in italic.
+    pub fn italic(self) -> Style {
+        Style(self.0.italic())
+    }
+
+    /// This style underlined.
+    pub fn underline(self) -> Style {
+        Style(self.0.underline())
+    }
+
+    /// This style struck through.
+    pub fn strikethrough(self) -> Style {
+        Style(self.0.strikethrough())
+    }
+
+    /// This style in italic or upright, according to `italic`.
+    pub fn with_italic(self, italic: bool) -> Style {
+        let effects = self.0.get_effects().set(Effects::ITALIC, italic);
+        Style(self.0.effects(effects))
+    }
+
+    /// The foreground colour of this style.
+    pub fn get_foreground(&self) -> Option<Color> {
+        self.0.get_fg_color()
+    }
+
+    /// The background colour of this style.
+    pub fn get_background(&self) -> Option<Color> {
+        self.0.get_bg_color()
+    }
+
+    /// The underline colour of this style.
+    pub fn get_underline_colour(&self) -> Option<Color> {
+        self.0.get_underline_color()
+    }
+
+    /// This style with all colours mapped by `f`.
+    pub fn map_colours<F: Fn(Color) -> Color>(self, f: F) -> Style {
+        Style(
+            self.0
+                .fg_color(self.0.get_fg_color().map(&f))
+                .bg_color(self.0.get_bg_color().map(&f))
+                .underline_color(self.0.get_underline_color().map(&f)),
+        )
+    }
+
+    /// This style without underline colour.
+    pub fn without_underline_colour(self) -> Style {
+        Style(self.0.underline_color(None))
+    }
+
+    /// Write `text` in this style to `writer`.
+    ///
+    /// Write plain text if this style has no colours and attributes.
+    pub fn write_styled<W: Write>(&self, writer: &mut W, text: &str) -> Result<()> {
+        if self.0.is_plain() {
+            write!(writer, "{}", text)
+        } else {
+            write!(
+                writer,
+                "{}{}{}",
+                self.0.render(),
+                text,
+                self.0.render_reset()
+            )
+        }
+    }
+}
+
+impl From<anstyle::Style> for Style {
+    fn from(style: anstyle::Style) -> Style {
+        Style(style)
+    }
+}
+
+impl From<Style> for anstyle::Style {
+    fn from(style: Style) -> anstyle::Style {
+        style.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anstyle::AnsiColor;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn write_plain_text_without_style() {
+        let mut buffer = Vec::new();
+        Style::new().write_styled(&mut buffer, "foo").unwrap();
+        assert_eq!(buffer, b"foo");
+    }
+
+    #[test]
+    fn write_styled_text() {
+        let mut buffer = Vec::new();
+        let style = Style::new().fg(AnsiColor::Blue).bold();
+        style.write_styled(&mut buffer, "foo").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "\x1b[1m\x1b[34mfoo\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn toggle_italic() {
+        let style = Style::new().fg(AnsiColor::Green);
+        assert_eq!(style.with_italic(true), style.italic());
+        assert_eq!(style.italic().with_italic(false), style);
+    }
+
+    #[test]
+    fn map_colours() {
+        let style = Style::new().fg(AnsiColor::Red).on(AnsiColor::Blue).bold();
+        assert_eq!(
+            style.map_colours(|_| AnsiColor::Green.into()),
+            Style::new()
+                .fg(AnsiColor::Green)
+                .on(AnsiColor::Green)
+                .bold()
+        );
+    }
+}
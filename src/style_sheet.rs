@@ -0,0 +1,303 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Style sheets to control the appearance of rendered markdown.
+
+use crate::Style;
+use anstyle::{AnsiColor, Color};
+
+/// How to align text in paragraphs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextAlignment {
+    /// Align text to the left.
+    Left,
+    /// Align text to both edges, by stretching the spaces between words.
+    ///
+    /// Only applies if text gets wrapped, i.e. with a content width.  We never
+    /// justify the last line of a paragraph.
+    Justify,
+}
+
+/// How to indent paragraphs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParagraphIndent {
+    /// Do not indent paragraphs.
+    None,
+    /// Indent the first line of paragraphs by the given number of columns.
+    FirstLine(usize),
+    /// Indent all but the first line of paragraphs by the given number of
+    /// columns.
+    Hanging(usize),
+}
+
+/// The style of paragraphs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParagraphStyle {
+    /// How to align text.
+    pub alignment: TextAlignment,
+    /// How to indent lines.
+    pub indent: ParagraphIndent,
+}
+
+impl Default for ParagraphStyle {
+    fn default() -> ParagraphStyle {
+        ParagraphStyle {
+            alignment: TextAlignment::Left,
+            indent: ParagraphIndent::None,
+        }
+    }
+}
+
+/// The style of headings.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HeadingStyle {
+    /// The style of the heading text.
+    pub style: Style,
+    /// Whether to write the heading text in uppercase.
+    pub uppercase: bool,
+    /// Whether to adorn the heading with one `┄` per level.
+    pub adornment: bool,
+    /// The number of columns to indent the heading by.
+    pub indent: usize,
+}
+
+impl Default for HeadingStyle {
+    fn default() -> HeadingStyle {
+        HeadingStyle {
+            style: Style::new().fg(AnsiColor::Blue).bold(),
+            uppercase: false,
+            adornment: true,
+            indent: 0,
+        }
+    }
+}
+
+/// The style of rules, aka thematic breaks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleStyle {
+    /// The symbol to repeat along the rule.
+    pub symbol: String,
+    /// The style of the rule.
+    pub style: Style,
+    /// The number of times to repeat `symbol`, or `None` to fill the width.
+    pub width: Option<usize>,
+    /// Whether to center the rule.
+    pub centered: bool,
+}
+
+impl Default for RuleStyle {
+    fn default() -> RuleStyle {
+        RuleStyle {
+            symbol: "\u{2550}".to_string(),
+            style: Style::new().fg(AnsiColor::Green),
+            width: None,
+            centered: false,
+        }
+    }
+}
+
+/// The style of tables.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TableStyle {
+    /// The style of the cells in the header row.
+    pub header: Style,
+    /// The style of the borders between cells.
+    pub border: Style,
+    /// The style of every other row in the table body, to stripe rows.
+    ///
+    /// Do not stripe rows if `None`.
+    pub stripe: Option<Style>,
+}
+
+impl Default for TableStyle {
+    fn default() -> TableStyle {
+        TableStyle {
+            header: Style::new().bold(),
+            border: Style::new().fg(AnsiColor::Green),
+            stripe: None,
+        }
+    }
+}
+
+/// The colours of inline markup and decorations.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Colours {
+    /// The colour of links, link references and footnote markers.
+    pub link: Color,
+    /// The colour of inline code, and of code blocks without highlighting.
+    pub code: Color,
+    /// The colour of inline and block HTML.
+    pub html: Color,
+    /// The colour of the borders around code blocks.
+    pub border: Color,
+    /// The colours of block quotes.
+    ///
+    /// Alternate between both colours for nested block quotes.
+    pub quotes: [Color; 2],
+}
+
+impl Default for Colours {
+    fn default() -> Colours {
+        Colours {
+            link: AnsiColor::Blue.into(),
+            code: AnsiColor::Yellow.into(),
+            html: AnsiColor::Green.into(),
+            border: AnsiColor::Green.into(),
+            quotes: [AnsiColor::Green.into(), AnsiColor::Cyan.into()],
+        }
+    }
+}
+
+/// Symbols to decorate rendered markdown with.
+///
+/// Replace individual symbols for fonts which lack some of the default
+/// glyphs.  Every symbol should take exactly one column.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Symbols {
+    /// The adornment of headings, repeated once per level.
+    pub heading: char,
+    /// The bullet of unordered list items.
+    pub bullet: char,
+    /// The marker of checked task list items.
+    pub checked: char,
+    /// The marker of unchecked task list items.
+    pub unchecked: char,
+    /// The ellipsis to mark truncated text.
+    pub ellipsis: char,
+    /// The marker of continued lines in wrapped code blocks.
+    pub continuation: char,
+    /// The horizontal line of borders around code blocks and tables.
+    pub horizontal: char,
+    /// The vertical line of table borders.
+    pub vertical: char,
+    /// The corners and junctions of table borders.
+    ///
+    /// Left, middle and right junctions of the top, middle and bottom borders,
+    /// in this order.
+    pub junctions: [char; 9],
+}
+
+impl Default for Symbols {
+    fn default() -> Symbols {
+        Symbols {
+            heading: '\u{2504}',
+            bullet: '\u{2022}',
+            checked: '\u{2611}',
+            unchecked: '\u{2610}',
+            ellipsis: '\u{2026}',
+            continuation: '\u{21aa}',
+            horizontal: '\u{2500}',
+            vertical: '\u{2502}',
+            junctions: [
+                '\u{250c}', '\u{252c}', '\u{2510}', '\u{251c}', '\u{253c}', '\u{2524}', '\u{2514}',
+                '\u{2534}', '\u{2518}',
+            ],
+        }
+    }
+}
+
+/// A style sheet for rendering.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StyleSheet {
+    /// The style of paragraphs.
+    pub paragraph: ParagraphStyle,
+    /// The styles of headings, from level 1 to level 6.
+    pub headings: [HeadingStyle; 6],
+    /// The style of rules.
+    pub rule: RuleStyle,
+    /// The style of tables.
+    pub table: TableStyle,
+    /// The symbols to decorate markdown with.
+    pub symbols: Symbols,
+    /// The colours of inline markup and decorations.
+    pub colours: Colours,
+}
+
+impl StyleSheet {
+    /// The style of headings of the given `level`.
+    ///
+    /// Use the style of level 6 for deeper levels.
+    pub fn heading(&self, level: u32) -> &HeadingStyle {
+        let index = match level {
+            0 => 0,
+            1..=6 => level - 1,
+            _ => 5,
+        };
+        &self.headings[index as usize]
+    }
+
+    /// A style sheet for users with red-green colour blindness.
+    ///
+    /// Avoid green altogether, and use blue, cyan, yellow and magenta, which
+    /// remain distinct with deuteranopia and protanopia.
+    pub fn colour_blind() -> StyleSheet {
+        let colours = Colours {
+            link: AnsiColor::Blue.into(),
+            code: AnsiColor::Yellow.into(),
+            html: AnsiColor::Magenta.into(),
+            border: AnsiColor::Cyan.into(),
+            quotes: [AnsiColor::Cyan.into(), AnsiColor::Yellow.into()],
+        };
+        StyleSheet {
+            rule: RuleStyle {
+                style: Style::new().fg(AnsiColor::Cyan),
+                ..RuleStyle::default()
+            },
+            table: TableStyle {
+                border: Style::new().fg(AnsiColor::Cyan),
+                ..TableStyle::default()
+            },
+            colours,
+            ..StyleSheet::default()
+        }
+    }
+
+    /// The names of all preset style sheets.
+    pub const PRESETS: [&'static str; 2] = ["default", "colour-blind"];
+
+    /// The preset style sheet with the given `name`.
+    ///
+    /// See [`StyleSheet::PRESETS`] for all names; return `None` for other
+    /// names.
+    pub fn preset(name: &str) -> Option<StyleSheet> {
+        match name {
+            "default" => Some(StyleSheet::default()),
+            "colour-blind" => Some(StyleSheet::colour_blind()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{plain_settings, render_with};
+    use crate::{Settings, TerminalCapabilities};
+
+    #[test]
+    fn colour_blind_style_sheet_avoids_green() {
+        let markdown = "> Quote\n>\n> > Nested\n\n<b>HTML</b>\n\n---\n\n    code\n";
+        let mut settings = Settings {
+            terminal_capabilities: TerminalCapabilities::ansi(),
+            ..plain_settings()
+        };
+        let green = "\x1b[32m";
+        let result = render_with(&settings, markdown);
+        assert!(result.contains(green), "{:?}", result);
+
+        settings.style_sheet = StyleSheet::preset("colour-blind").unwrap();
+        let result = render_with(&settings, markdown);
+        assert!(!result.contains(green), "{:?}", result);
+    }
+}
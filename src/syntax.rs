@@ -0,0 +1,132 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Find syntaxes for code blocks.
+
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// Common tokens of fenced code blocks which syntect does not know, and the
+/// tokens syntect knows for them.
+const ALIASES: &[(&str, &str)] = &[
+    ("bash-session", "sh"),
+    ("c++", "cpp"),
+    ("console", "sh"),
+    ("csharp", "cs"),
+    ("cxx", "cpp"),
+    ("golang", "go"),
+    ("htm", "html"),
+    ("jsonc", "json"),
+    ("jsx", "js"),
+    ("node", "js"),
+    ("objc", "m"),
+    ("plain", "txt"),
+    ("py3", "py"),
+    ("python3", "py"),
+    ("shell", "sh"),
+    ("shell-session", "sh"),
+    ("text", "txt"),
+    ("xhtml", "html"),
+    ("zsh", "sh"),
+];
+
+/// Keywords at the start of code, and the tokens of the syntax they indicate.
+const KEYWORDS: &[(&str, &str)] = &[
+    ("#include <", "c"),
+    ("$ ", "sh"),
+    ("--- a/", "diff"),
+    ("<!DOCTYPE html", "html"),
+    ("<!doctype html", "html"),
+    ("<html", "html"),
+    ("diff --git ", "diff"),
+    ("fn main()", "rust"),
+    ("package main", "go"),
+    ("use std::", "rust"),
+];
+
+/// Find the syntax for the `token` of a fenced code block.
+///
+/// Look up `token` in `syntax_set`, and try the known aliases of `token` if
+/// `syntax_set` does not know it.
+pub fn find_syntax_by_token<'s>(
+    syntax_set: &'s SyntaxSet,
+    token: &str,
+) -> Option<&'s SyntaxReference> {
+    syntax_set.find_syntax_by_token(token).or_else(|| {
+        let token = token.to_lowercase();
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == token)
+            .and_then(|(_, token)| syntax_set.find_syntax_by_token(token))
+    })
+}
+
+/// Guess the syntax of code from its `first_line`.
+///
+/// Match shebangs, e.g. `#!/bin/sh`, and other marks at the start of code,
+/// e.g. `<?xml` or `<?php`, and then some keywords which typically start code
+/// in a language, e.g. `package main` for Go.
+pub fn find_syntax_by_first_line<'s>(
+    syntax_set: &'s SyntaxSet,
+    first_line: &str,
+) -> Option<&'s SyntaxReference> {
+    syntax_set
+        .find_syntax_by_first_line(first_line)
+        .or_else(|| {
+            KEYWORDS
+                .iter()
+                .find(|(keyword, _)| first_line.starts_with(keyword))
+                .and_then(|(_, token)| syntax_set.find_syntax_by_token(token))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn name_by_token(token: &str) -> Option<String> {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        find_syntax_by_token(&syntax_set, token).map(|syntax| syntax.name.clone())
+    }
+
+    #[test]
+    fn find_syntax_by_alias() {
+        assert_eq!(name_by_token("rust").as_deref(), Some("Rust"));
+        assert_eq!(
+            name_by_token("shell").as_deref(),
+            Some("Bourne Again Shell (bash)")
+        );
+        assert_eq!(name_by_token("Golang").as_deref(), Some("Go"));
+        assert_eq!(name_by_token("no-such-language"), None);
+    }
+
+    #[test]
+    fn find_syntax_by_shebang() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let syntax = find_syntax_by_first_line(&syntax_set, "#!/usr/bin/env python\n");
+        assert_eq!(syntax.map(|syntax| syntax.name.as_str()), Some("Python"));
+        let syntax = find_syntax_by_first_line(&syntax_set, "<?xml version=\"1.0\"?>\n");
+        assert_eq!(syntax.map(|syntax| syntax.name.as_str()), Some("XML"));
+    }
+
+    #[test]
+    fn find_syntax_by_keyword() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let syntax = find_syntax_by_first_line(&syntax_set, "package main\n");
+        assert_eq!(syntax.map(|syntax| syntax.name.as_str()), Some("Go"));
+        let syntax = find_syntax_by_first_line(&syntax_set, "diff --git a/foo b/foo\n");
+        assert_eq!(syntax.map(|syntax| syntax.name.as_str()), Some("Diff"));
+        assert!(find_syntax_by_first_line(&syntax_set, "Lorem ipsum\n").is_none());
+    }
+}
@@ -13,22 +13,236 @@
 // limitations under the License.
 
 //! Standard ANSI styling.
+//!
+//! Degrade styles to what the terminal supports.
 
-use ansi_term::Style;
+use crate::Style;
+use anstyle::{Ansi256Color, AnsiColor, Color};
 use std::io::{Result, Write};
 
+/// The styles a terminal supports, from least to most capable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StyleTier {
+    /// The terminal supports no styles at all.
+    Dumb,
+    /// The terminal supports the 16 basic ANSI colours.
+    Ansi16,
+    /// The terminal supports the 256 colours of the xterm palette.
+    Ansi256,
+    /// The terminal supports 24-bit colours.
+    TrueColor,
+    /// The terminal supports 24-bit colours and extended text attributes,
+    /// such as underline colours.
+    Full,
+}
+
+impl StyleTier {
+    /// Detect the style tier of the current terminal.
+    ///
+    /// Terminals with 24-bit colours advertise support by setting `$COLORTERM`
+    /// to `truecolor` or `24bit`, and terminals with 256 colours by a `$TERM`
+    /// ending in `256color`.
+    pub fn detect() -> StyleTier {
+        StyleTier::detect_and_explain().0
+    }
+
+    /// Detect the style tier of the current terminal, and explain why.
+    ///
+    /// See [`StyleTier::detect`].
+    pub fn detect_and_explain() -> (StyleTier, String) {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            let reason = format!("$COLORTERM is {}", colorterm);
+            (StyleTier::TrueColor, reason)
+        } else if term.ends_with("256color") {
+            let reason = format!("$TERM {} ends with 256color", term);
+            (StyleTier::Ansi256, reason)
+        } else {
+            let reason = "neither $COLORTERM nor $TERM advertise more than 16 colours";
+            (StyleTier::Ansi16, reason.to_string())
+        }
+    }
+
+    /// Degrade `style` to what this tier supports.
+    pub fn degrade(self, style: &Style) -> Style {
+        match self {
+            StyleTier::Dumb => Style::new(),
+            StyleTier::Full => *style,
+            StyleTier::TrueColor => style.without_underline_colour(),
+            tier => style
+                .map_colours(|colour| tier.degrade_colour(colour))
+                .without_underline_colour(),
+        }
+    }
+
+    /// Degrade a `colour` to what this tier supports.
+    fn degrade_colour(self, colour: Color) -> Color {
+        match (self, colour) {
+            (StyleTier::Ansi16, Color::Ansi256(Ansi256Color(index))) if 16 <= index => {
+                let (r, g, b) = palette_rgb(index);
+                nearest_basic_colour(r, g, b)
+            }
+            (StyleTier::Ansi16, Color::Rgb(rgb)) => nearest_basic_colour(rgb.0, rgb.1, rgb.2),
+            (StyleTier::Ansi256, Color::Rgb(rgb)) => {
+                Ansi256Color(nearest_palette_index(rgb.0, rgb.1, rgb.2)).into()
+            }
+            (_, colour) => colour,
+        }
+    }
+}
+
+/// The RGB values of the 16 basic colours, per xterm defaults.
+const BASIC_COLOURS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// The levels of each component in the colour cube of the xterm palette.
+const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+/// The RGB values of a colour of the xterm palette.
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => BASIC_COLOURS[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// The squared distance between two RGB colours.
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The index of the colour in `indexes` of the xterm palette nearest to the given RGB colour.
+fn nearest_index<I: Iterator<Item = u8>>(indexes: I, rgb: (u8, u8, u8)) -> u8 {
+    indexes
+        .min_by_key(|index| distance(palette_rgb(*index), rgb))
+        .unwrap_or(0)
+}
+
+/// The colour of the xterm palette nearest to the given RGB colour.
+///
+/// Ignore the 16 basic colours, because terminal themes often change them.
+fn nearest_palette_index(r: u8, g: u8, b: u8) -> u8 {
+    nearest_index(16..=255, (r, g, b))
+}
+
+/// The basic colour nearest to the given RGB colour.
+fn nearest_basic_colour(r: u8, g: u8, b: u8) -> Color {
+    match nearest_index(0..=15, (r, g, b)) {
+        0 => AnsiColor::Black.into(),
+        1 => AnsiColor::Red.into(),
+        2 => AnsiColor::Green.into(),
+        3 => AnsiColor::Yellow.into(),
+        4 => AnsiColor::Blue.into(),
+        5 => AnsiColor::Magenta.into(),
+        6 => AnsiColor::Cyan.into(),
+        7 => AnsiColor::White.into(),
+        index => Ansi256Color(index).into(),
+    }
+}
+
 /// Access to a terminal’s basic ANSI styling functionality.
-#[derive(Debug)]
-pub struct AnsiStyle;
+#[derive(Debug, Clone)]
+pub struct AnsiStyle {
+    /// The styles the terminal supports.
+    pub tier: StyleTier,
+}
 
 impl AnsiStyle {
+    /// ANSI styling with the styles of the given `tier`.
+    pub fn new(tier: StyleTier) -> AnsiStyle {
+        AnsiStyle { tier }
+    }
+
     /// Write styled text to the given writer.
+    ///
+    /// Degrade `style` to the tier of this terminal first.
     pub fn write_styled<W: Write, V: AsRef<str>>(
         &self,
         write: &mut W,
         style: &Style,
         text: V,
     ) -> Result<()> {
-        write!(write, "{}", style.paint(text.as_ref()))
+        self.tier.degrade(style).write_styled(write, text.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anstyle::RgbColor;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn palette_rgb() {
+        assert_eq!(super::palette_rgb(9), (0xff, 0x00, 0x00));
+        assert_eq!(super::palette_rgb(16), (0x00, 0x00, 0x00));
+        assert_eq!(super::palette_rgb(196), (0xff, 0x00, 0x00));
+        assert_eq!(super::palette_rgb(231), (0xff, 0xff, 0xff));
+        assert_eq!(super::palette_rgb(232), (0x08, 0x08, 0x08));
+        assert_eq!(super::palette_rgb(255), (0xee, 0xee, 0xee));
+    }
+
+    #[test]
+    fn degrade_rgb_colours() {
+        let style = Style::new()
+            .fg(RgbColor(0xff, 0x00, 0x00))
+            .on(RgbColor(0x00, 0x2b, 0x36));
+        assert_eq!(StyleTier::TrueColor.degrade(&style), style);
+        assert_eq!(
+            StyleTier::Ansi256.degrade(&style),
+            Style::new().fg(Ansi256Color(196)).on(Ansi256Color(234))
+        );
+        assert_eq!(
+            StyleTier::Ansi16.degrade(&style),
+            Style::new().fg(Ansi256Color(9)).on(AnsiColor::Black)
+        );
+        assert_eq!(StyleTier::Dumb.degrade(&style), Style::new());
+    }
+
+    #[test]
+    fn keep_basic_colours() {
+        let style = Style::new().fg(AnsiColor::Yellow).bold();
+        assert_eq!(StyleTier::Ansi16.degrade(&style), style);
+        let style = Style::new().fg(Ansi256Color(13));
+        assert_eq!(StyleTier::Ansi16.degrade(&style), style);
+    }
+
+    #[test]
+    fn underline_colours_need_full_tier() {
+        let style = Style::new().underline().underline_colour(AnsiColor::Blue);
+        assert_eq!(StyleTier::Full.degrade(&style), style);
+        assert_eq!(
+            StyleTier::TrueColor.degrade(&style),
+            Style::new().underline()
+        );
     }
 }
@@ -0,0 +1,292 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache rendered images.
+
+use super::kitty::KittyImage;
+use super::ImageSize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use url::Url;
+
+/// The key of a cached image: Its URL, the time it was last modified, and the
+/// requested size.
+///
+/// Only local files have a modification time.
+type CacheKey = (Url, Option<SystemTime>, ImageSize);
+
+/// Get the cache key for `url` and `size`.
+fn cache_key(url: &Url, size: ImageSize) -> CacheKey {
+    let modified = url
+        .to_file_path()
+        .ok()
+        .and_then(|path| std::fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok());
+    (url.clone(), modified, size)
+}
+
+/// The number of images an image cache holds by default.
+const DEFAULT_CAPACITY: usize = 64;
+
+/// The entries of a cache.
+#[derive(Debug)]
+struct Entries<V> {
+    /// The maximum number of values.
+    capacity: usize,
+    /// Counts uses of the cache, to find the least recently used value.
+    clock: u64,
+    /// Values along with the time of their last use.
+    values: HashMap<CacheKey, (u64, V)>,
+}
+
+impl<V: Clone> Entries<V> {
+    /// Get the value for `key`, and mark it as recently used.
+    fn get(&mut self, key: &CacheKey) -> Option<V> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.values.get_mut(key).map(|(last_used, value)| {
+            *last_used = clock;
+            value.clone()
+        })
+    }
+
+    /// Insert `value` for `key`.
+    ///
+    /// Replace values for the same URL and size but a different modification
+    /// time, and evict the least recently used value if the cache is full.
+    fn insert(&mut self, key: CacheKey, value: V) {
+        let (ref url, _, size) = key;
+        self.values
+            .retain(|(other_url, _, other_size), _| !(other_url == url && *other_size == size));
+        while self.capacity <= self.values.len() {
+            let oldest = self
+                .values
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.values.remove(&oldest),
+                None => break,
+            };
+        }
+        if 0 < self.capacity {
+            self.clock += 1;
+            self.values.insert(key, (self.clock, value));
+        }
+    }
+}
+
+/// A cache of values by URL, which holds a limited number of values.
+///
+/// Evict the least recently used value when full.  Clones share their entries.
+#[derive(Debug)]
+struct Cache<V> {
+    entries: Arc<Mutex<Entries<V>>>,
+}
+
+impl<V> Cache<V> {
+    /// A cache for at most `capacity` values.
+    fn with_capacity(capacity: usize) -> Self {
+        Cache {
+            entries: Arc::new(Mutex::new(Entries {
+                capacity,
+                clock: 0,
+                values: HashMap::new(),
+            })),
+        }
+    }
+}
+
+impl<V> Default for Cache<V> {
+    fn default() -> Self {
+        Cache::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl<V> Clone for Cache<V> {
+    fn clone(&self) -> Self {
+        Cache {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<V: Clone> Cache<V> {
+    /// Get the cached value for `url` and `size`, or create and cache it with
+    /// `f`.
+    ///
+    /// Do not cache errors.
+    fn get_or_insert_with<F>(&self, url: &Url, size: ImageSize, f: F) -> Result<V, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<V, Box<dyn Error>>,
+    {
+        let key = cache_key(url, size);
+        if let Some(value) = self.entries.lock().unwrap().get(&key) {
+            return Ok(value);
+        }
+        let value = f()?;
+        self.entries.lock().unwrap().insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+/// A cache of rendered images.
+///
+/// Caches images ready for display, keyed by URL and, for local files, by
+/// modification time, so that rendering a document again skips reading,
+/// decoding and encoding its images.
+///
+/// Keep the cache around to render a document repeatedly, e.g. in a
+/// previewer.  Clones of a cache share their entries, e.g. to render many
+/// documents with the same cache.
+///
+/// The cache holds a limited number of images, 64 by default, and evicts the
+/// least recently used image when full.
+#[derive(Debug, Default, Clone)]
+pub struct ImageCache {
+    iterm2: Cache<Vec<u8>>,
+    kitty: Cache<KittyImage>,
+}
+
+impl ImageCache {
+    /// A cache for at most `capacity` images per terminal protocol.
+    ///
+    /// A capacity of 0 disables the cache.
+    pub fn with_capacity(capacity: usize) -> ImageCache {
+        ImageCache {
+            iterm2: Cache::with_capacity(capacity),
+            kitty: Cache::with_capacity(capacity),
+        }
+    }
+
+    /// Get the cached iTerm2 image for `url`, or render and cache it with `f`.
+    pub(crate) fn iterm2<F>(&self, url: &Url, f: F) -> Result<Vec<u8>, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<Vec<u8>, Box<dyn Error>>,
+    {
+        // iTerm2 scales images itself
+        self.iterm2.get_or_insert_with(url, ImageSize::default(), f)
+    }
+
+    /// Get the cached kitty image for `url` and `size`, or render and cache
+    /// it with `f`.
+    pub(crate) fn kitty<F>(
+        &self,
+        url: &Url,
+        size: ImageSize,
+        f: F,
+    ) -> Result<KittyImage, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<KittyImage, Box<dyn Error>>,
+    {
+        self.kitty.get_or_insert_with(url, size, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cache_values_by_url() {
+        let cache: Cache<u32> = Cache::default();
+        let url = Url::parse("https://example.com/foo.png").unwrap();
+        assert_eq!(
+            cache
+                .get_or_insert_with(&url, ImageSize::default(), || Ok(1))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            cache
+                .get_or_insert_with(&url, ImageSize::default(), || Ok(2))
+                .unwrap(),
+            1
+        );
+        let other = Url::parse("https://example.com/bar.png").unwrap();
+        assert_eq!(
+            cache
+                .get_or_insert_with(&other, ImageSize::default(), || Ok(3))
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn evict_least_recently_used_values() {
+        let cache: Cache<u32> = Cache::with_capacity(2);
+        let size = ImageSize::default();
+        let url = |name| Url::parse(&format!("https://example.com/{}.png", name)).unwrap();
+        cache.get_or_insert_with(&url("a"), size, || Ok(1)).unwrap();
+        cache.get_or_insert_with(&url("b"), size, || Ok(2)).unwrap();
+        // Use a, to make b the least recently used value
+        cache
+            .get_or_insert_with(&url("a"), size, || Ok(10))
+            .unwrap();
+        cache.get_or_insert_with(&url("c"), size, || Ok(3)).unwrap();
+        assert_eq!(cache.entries.lock().unwrap().values.len(), 2);
+        assert_eq!(
+            cache
+                .get_or_insert_with(&url("a"), size, || Ok(10))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            cache
+                .get_or_insert_with(&url("b"), size, || Ok(20))
+                .unwrap(),
+            20
+        );
+        assert_eq!(cache.entries.lock().unwrap().values.len(), 2);
+    }
+
+    #[test]
+    fn replace_values_for_other_modification_times() {
+        let cache: Cache<u32> = Cache::with_capacity(10);
+        let url = Url::parse("https://example.com/foo.png").unwrap();
+        let size = ImageSize::default();
+        let mut entries = cache.entries.lock().unwrap();
+        entries.insert((url.clone(), Some(SystemTime::UNIX_EPOCH), size), 1);
+        entries.insert((url.clone(), Some(SystemTime::now()), size), 2);
+        entries.insert((url, None, size), 3);
+        assert_eq!(entries.values.len(), 1);
+    }
+
+    #[test]
+    fn disable_cache_without_capacity() {
+        let cache: Cache<u32> = Cache::with_capacity(0);
+        let url = Url::parse("https://example.com/foo.png").unwrap();
+        let size = ImageSize::default();
+        cache.get_or_insert_with(&url, size, || Ok(1)).unwrap();
+        assert_eq!(cache.get_or_insert_with(&url, size, || Ok(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn do_not_cache_errors() {
+        let cache: Cache<u32> = Cache::default();
+        let url = Url::parse("https://example.com/foo.png").unwrap();
+        assert!(cache
+            .get_or_insert_with(&url, ImageSize::default(), || Err("failed".into()))
+            .is_err());
+        assert_eq!(
+            cache
+                .get_or_insert_with(&url, ImageSize::default(), || Ok(2))
+                .unwrap(),
+            2
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Restore the state of terminals after failures.
+
+use std::io::{Result, Write};
+
+/// Reset all styles, close any open OSC 8 link and show the cursor.
+const RESET: &[u8] = b"\x1b[0m\x1b]8;;\x07\x1b[?25h";
+
+/// A writer which resets the terminal unless writing finished.
+///
+/// If dropped before [`ResetGuard::finish`], e.g. because rendering failed
+/// or panicked in the middle of a document, reset all styles, close any open
+/// OSC 8 link and show the cursor again, so that a failed render never leaves
+/// the terminal in a broken state.  Do nothing if no escape sequence was
+/// written at all, to keep plain text output plain.
+#[derive(Debug)]
+pub struct ResetGuard<W: Write> {
+    inner: W,
+    wrote_escapes: bool,
+    finished: bool,
+}
+
+impl<W: Write> ResetGuard<W> {
+    /// Guard the terminal behind `inner`.
+    pub fn new(inner: W) -> ResetGuard<W> {
+        ResetGuard {
+            inner,
+            wrote_escapes: false,
+            finished: false,
+        }
+    }
+
+    /// Finish writing successfully, and do not reset the terminal.
+    pub fn finish(mut self) -> Result<()> {
+        self.finished = true;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for ResetGuard<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.wrote_escapes |= buf[..written].contains(&0x1b);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ResetGuard<W> {
+    fn drop(&mut self) {
+        if !self.finished && self.wrote_escapes {
+            // We can't do anything about errors while dropping
+            let _ = self.inner.write_all(RESET);
+            let _ = self.inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reset_after_failure() {
+        let mut sink = Vec::new();
+        {
+            let mut guard = ResetGuard::new(&mut sink);
+            guard.write_all(b"\x1b[1mfoo").unwrap();
+        }
+        assert_eq!(sink, b"\x1b[1mfoo\x1b[0m\x1b]8;;\x07\x1b[?25h");
+    }
+
+    #[test]
+    fn no_reset_after_finish() {
+        let mut sink = Vec::new();
+        let mut guard = ResetGuard::new(&mut sink);
+        guard.write_all(b"\x1b[1mfoo\x1b[0m").unwrap();
+        guard.finish().unwrap();
+        assert_eq!(sink, b"\x1b[1mfoo\x1b[0m");
+    }
+
+    #[test]
+    fn no_reset_for_plain_text() {
+        let mut sink = Vec::new();
+        {
+            let mut guard = ResetGuard::new(&mut sink);
+            guard.write_all(b"foo").unwrap();
+        }
+        assert_eq!(sink, b"foo");
+    }
+}
@@ -14,12 +14,10 @@
 
 //! Tools for syntax highlighting.
 
-use super::ansi::AnsiStyle;
-use ansi_term::Colour;
-use std::io::{Result, Write};
+use anstyle::{Ansi256Color, AnsiColor, Color, RgbColor};
 use syntect::highlighting::{FontStyle, Style};
 
-/// Write regions as ANSI 8-bit coloured text.
+/// Convert a highlighting style to an ANSI 8-bit colour style.
 ///
 /// We use this function to simplify syntax highlighting to 8-bit ANSI values
 /// which every theme provides.  Contrary to 24 bit colours this gives us a good
@@ -32,45 +30,74 @@ use syntect::highlighting::{FontStyle, Style};
 /// change depending on light or dark Solarized; to address both light and dark
 /// backgrounds we must map all base colours to the default terminal colours.
 ///
-/// Furthermore we completely ignore any background colour settings, to avoid
-/// conflicts with the terminal colour themes.
-pub fn write_as_ansi<W: Write>(
-    writer: &mut W,
-    ansi: &AnsiStyle,
-    regions: &[(Style, &str)],
-) -> Result<()> {
-    for &(style, text) in regions {
-        let rgb = {
-            let fg = style.foreground;
-            (fg.r, fg.g, fg.b)
-        };
-        let mut ansi_style = ansi_term::Style::new();
-        match rgb {
-            // base03, base02, base01, base00, base0, base1, base2, and base3
-            (0x00, 0x2b, 0x36)
-            | (0x07, 0x36, 0x42)
-            | (0x58, 0x6e, 0x75)
-            | (0x65, 0x7b, 0x83)
-            | (0x83, 0x94, 0x96)
-            | (0x93, 0xa1, 0xa1)
-            | (0xee, 0xe8, 0xd5)
-            | (0xfd, 0xf6, 0xe3) => ansi_style.foreground = None,
-            (0xb5, 0x89, 0x00) => ansi_style.foreground = Some(Colour::Yellow),
-            (0xcb, 0x4b, 0x16) => ansi_style.foreground = Some(Colour::Fixed(9)), // Bright red
-            (0xdc, 0x32, 0x2f) => ansi_style.foreground = Some(Colour::Red),
-            (0xd3, 0x36, 0x82) => ansi_style.foreground = Some(Colour::Purple),
-            (0x6c, 0x71, 0xc4) => ansi_style.foreground = Some(Colour::Fixed(13)), // Bright purple
-            (0x26, 0x8b, 0xd2) => ansi_style.foreground = Some(Colour::Blue),
-            (0x2a, 0xa1, 0x98) => ansi_style.foreground = Some(Colour::Cyan),
-            (0x85, 0x99, 0x00) => ansi_style.foreground = Some(Colour::Green),
-            (r, g, b) => panic!("Unexpected RGB colour: #{:2>0x}{:2>0x}{:2>0x}", r, g, b),
-        };
-        let font = style.font_style;
-        ansi_style.is_bold = font.contains(FontStyle::BOLD);
-        ansi_style.is_italic = font.contains(FontStyle::ITALIC);
-        ansi_style.is_underline = font.contains(FontStyle::UNDERLINE);
-        ansi.write_styled(writer, &ansi_style, text)?;
+/// Furthermore we ignore the background colour of the highlighting style, to
+/// avoid conflicts with the terminal colour themes.  Instead we use the given
+/// `background`, if any.
+pub fn to_ansi(style: Style, background: Option<Color>) -> crate::Style {
+    let rgb = {
+        let fg = style.foreground;
+        (fg.r, fg.g, fg.b)
+    };
+    let foreground: Option<Color> = match rgb {
+        // base03, base02, base01, base00, base0, base1, base2, and base3
+        (0x00, 0x2b, 0x36)
+        | (0x07, 0x36, 0x42)
+        | (0x58, 0x6e, 0x75)
+        | (0x65, 0x7b, 0x83)
+        | (0x83, 0x94, 0x96)
+        | (0x93, 0xa1, 0xa1)
+        | (0xee, 0xe8, 0xd5)
+        | (0xfd, 0xf6, 0xe3) => None,
+        (0xb5, 0x89, 0x00) => Some(AnsiColor::Yellow.into()),
+        (0xcb, 0x4b, 0x16) => Some(Ansi256Color(9).into()), // Bright red
+        (0xdc, 0x32, 0x2f) => Some(AnsiColor::Red.into()),
+        (0xd3, 0x36, 0x82) => Some(AnsiColor::Magenta.into()),
+        (0x6c, 0x71, 0xc4) => Some(Ansi256Color(13).into()), // Bright purple
+        (0x26, 0x8b, 0xd2) => Some(AnsiColor::Blue.into()),
+        (0x2a, 0xa1, 0x98) => Some(AnsiColor::Cyan.into()),
+        (0x85, 0x99, 0x00) => Some(AnsiColor::Green.into()),
+        (r, g, b) => panic!("Unexpected RGB colour: #{:2>0x}{:2>0x}{:2>0x}", r, g, b),
+    };
+    let mut ansi_style = crate::Style::new();
+    if let Some(foreground) = foreground {
+        ansi_style = ansi_style.fg(foreground);
     }
+    if let Some(background) = background {
+        ansi_style = ansi_style.on(background);
+    }
+    let font = style.font_style;
+    if font.contains(FontStyle::BOLD) {
+        ansi_style = ansi_style.bold();
+    }
+    if font.contains(FontStyle::ITALIC) {
+        ansi_style = ansi_style.italic();
+    }
+    if font.contains(FontStyle::UNDERLINE) {
+        ansi_style = ansi_style.underline();
+    }
+    ansi_style
+}
 
-    Ok(())
+/// Convert a highlighting style to a 24-bit colour style.
+///
+/// Use the foreground colour and the font style of the highlighting style as
+/// is, but ignore its background colour in favour of the given `background`,
+/// if any.
+pub fn to_rgb(style: Style, background: Option<Color>) -> crate::Style {
+    let fg = style.foreground;
+    let mut rgb_style = crate::Style::new().fg(RgbColor(fg.r, fg.g, fg.b));
+    if let Some(background) = background {
+        rgb_style = rgb_style.on(background);
+    }
+    let font = style.font_style;
+    if font.contains(FontStyle::BOLD) {
+        rgb_style = rgb_style.bold();
+    }
+    if font.contains(FontStyle::ITALIC) {
+        rgb_style = rgb_style.italic();
+    }
+    if font.contains(FontStyle::UNDERLINE) {
+        rgb_style = rgb_style.underline();
+    }
+    rgb_style
 }
@@ -20,8 +20,10 @@
 //! See <https://www.iterm2.com> for more information.
 
 use super::osc::write_osc;
+use super::ImageSize;
+use crate::convert;
 use crate::magic;
-use crate::resources::read_url;
+use crate::resources::{read_url, HttpSettings};
 use std::error::Error;
 use std::ffi::OsStr;
 use std::io::{self, Write};
@@ -38,7 +40,7 @@ pub fn is_iterm2() -> bool {
 }
 
 /// Iterm2 marks.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ITerm2Marks;
 
 impl ITerm2Marks {
@@ -49,29 +51,33 @@ impl ITerm2Marks {
 }
 
 /// Iterm2 inline iamges.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ITerm2Images;
 
 impl ITerm2Images {
     /// Write an iterm2 inline image command to `writer`.
     ///
     /// `name` is the local file name and `contents` are the contents of the
-    /// given file.
+    /// given file.  Ask iTerm2 to show the image with the given `size`.
     #[cfg(unix)]
     pub fn write_inline_image<W: Write, S: AsRef<OsStr>>(
         &self,
         writer: &mut W,
         name: S,
         contents: &[u8],
+        size: ImageSize,
     ) -> io::Result<()> {
         use std::os::unix::ffi::OsStrExt;
+        let mut arguments = format!("name={};inline=1", base64::encode(name.as_ref().as_bytes()));
+        if let Some(width) = size.width {
+            arguments.push_str(&format!(";width={}px", width));
+        }
+        if let Some(height) = size.height {
+            arguments.push_str(&format!(";height={}px", height));
+        }
         write_osc(
             writer,
-            &format!(
-                "1337;File=name={};inline=1:{}",
-                base64::encode(name.as_ref().as_bytes()),
-                base64::encode(contents)
-            ),
+            &format!("1337;File={}:{}", arguments, base64::encode(contents)),
         )
     }
 
@@ -81,6 +87,7 @@ impl ITerm2Images {
         _writer: &mut W,
         _name: S,
         _contents: &[u8],
+        _size: ImageSize,
     ) -> io::Result<()> {
         unimplemented!()
     }
@@ -89,12 +96,37 @@ impl ITerm2Images {
     ///
     /// Render the binary content of the (rendered) image or an IO error if
     /// reading or rendering failed.
-    pub fn read_and_render(&self, url: &Url) -> Result<Vec<u8>, Box<dyn Error>> {
-        let contents = read_url(&url)?;
-        if magic::is_svg(&magic::detect_mime_type(&contents)?) {
-            svg::render_svg(&contents).map_err(Into::into)
-        } else {
+    ///
+    /// Convert images in formats other than PNG, JPEG or GIF to PNG.
+    ///
+    /// iTerm2 plays animated GIFs; unless `animations` is true render only the
+    /// first frame of GIF images.
+    ///
+    /// Fail if the image at `url` has more than `max_bytes`, or more than
+    /// `max_pixels`.  Send HTTP requests according to `http`.
+    pub fn read_and_render(
+        &self,
+        url: &Url,
+        animations: bool,
+        max_bytes: Option<usize>,
+        max_pixels: Option<u64>,
+        http: &HttpSettings,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let contents = read_url(&url, max_bytes, http)?;
+        let mime = magic::detect_mime_type(&contents)?;
+        if magic::is_svg(&mime) {
+            let png = svg::render_svg(&contents)?;
+            convert::check_pixels(&png, max_pixels)?;
+            return convert::convert_to_png(&png);
+        }
+        convert::check_pixels(&contents, max_pixels)?;
+        if magic::is_gif(&mime) && !animations {
+            // Converting only keeps the first frame
+            convert::convert_to_png(&contents)
+        } else if convert::is_common_format(&mime) {
             Ok(contents)
+        } else {
+            convert::convert_to_png(&contents)
         }
     }
 }
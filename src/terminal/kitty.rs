@@ -19,8 +19,10 @@
 //!
 //! See <https://sw.kovidgoyal.net/kitty/> for more information.
 
+use super::ImageSize;
+use crate::convert;
 use crate::magic;
-use crate::resources::read_url;
+use crate::resources::{read_url, HttpSettings};
 use crate::svg::render_svg;
 use image::imageops::FilterType;
 use image::ColorType;
@@ -102,7 +104,7 @@ fn get_terminal_size() -> std::io::Result<KittyDimension> {
 }
 
 /// Provides access to printing images for kitty.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct KittyImages;
 
 impl KittyImages {
@@ -180,22 +182,46 @@ impl KittyImages {
     }
 
     /// Read the image bytes from the given URL and wrap them in a `KittyImage`.
-    /// It scales the image down, if the image size exceeds the terminal window size.
-    pub fn read_and_render(&self, url: &Url) -> Result<KittyImage, Box<dyn std::error::Error>> {
-        let contents = read_url(url)?;
+    /// It scales the image to the given `size` if any, and down, if the image size
+    /// exceeds the terminal window size.
+    ///
+    /// Fail if the image at `url` has more than `max_bytes`, or more than
+    /// `max_pixels`.  Send HTTP requests according to `http`.
+    pub fn read_and_render(
+        &self,
+        url: &Url,
+        size: ImageSize,
+        max_bytes: Option<usize>,
+        max_pixels: Option<u64>,
+        http: &HttpSettings,
+    ) -> Result<KittyImage, Box<dyn std::error::Error>> {
+        let contents = read_url(url, max_bytes, http)?;
         let mime = magic::detect_mime_type(&contents)?;
         let image = if magic::is_svg(&mime) {
-            image::load_from_memory(&render_svg(&contents)?)
+            let png = render_svg(&contents)?;
+            convert::check_pixels(&png, max_pixels)?;
+            image::load_from_memory(&png)
         } else {
+            convert::check_pixels(&contents, max_pixels)?;
             image::load_from_memory(&contents)
         }?;
+        let (image_width, image_height) = image.dimensions();
+        let scaled = size.scale(image_width, image_height);
+        let image = match scaled {
+            Some((width, height)) => image.resize_exact(width, height, FilterType::Triangle),
+            None => image,
+        };
         let terminal_size = get_terminal_size()?;
         let (image_width, image_height) = image.dimensions();
 
         let needs_scaledown =
             image_width > terminal_size.width || image_height > terminal_size.height;
 
-        if mime.type_() == mime::IMAGE && mime.subtype().as_str() == "png" && !needs_scaledown {
+        if mime.type_() == mime::IMAGE
+            && mime.subtype().as_str() == "png"
+            && !needs_scaledown
+            && scaled.is_none()
+        {
             self.render_as_png(contents)
         } else {
             self.render_as_rgb_or_rgba(image, terminal_size)
@@ -258,6 +284,7 @@ impl KittyImages {
 }
 
 /// Holds the image bytes with its image format and dimensions.
+#[derive(Debug, Clone)]
 pub struct KittyImage {
     contents: Vec<u8>,
     format: KittyFormat,
@@ -265,6 +292,7 @@ pub struct KittyImage {
 }
 
 /// The image format (PNG, RGB or RGBA) of the image bytes.
+#[derive(Debug, Clone)]
 enum KittyFormat {
     PNG,
     RGB,
@@ -286,6 +314,7 @@ impl KittyFormat {
 }
 
 /// The dimension encapsulate the width and height in the pixel unit.
+#[derive(Debug, Clone)]
 struct KittyDimension {
     width: u32,
     height: u32,
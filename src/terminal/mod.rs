@@ -13,23 +13,66 @@
 // limitations under the License.
 
 //! Terminal utilities.
+//!
+//! Detect the capabilities and the size of the current terminal, for use by
+//! mdcat or by other tools which write to terminals.
 
 // Support modules for terminal writing.
 
 mod ansi;
+mod cache;
+mod guard;
 pub mod highlighting;
+mod redraw;
 mod size;
+mod strip;
 
 mod iterm2;
 mod kitty;
 mod osc;
 mod terminology;
 
-pub use self::ansi::AnsiStyle;
+pub use self::ansi::{AnsiStyle, StyleTier};
+pub use self::cache::ImageCache;
+pub use self::guard::ResetGuard;
+pub use self::iterm2::{ITerm2Images, ITerm2Marks};
+pub use self::kitty::KittyImages;
+pub use self::osc::{set_clipboard, OSC133Marks, OSC8Links};
+pub use self::redraw::redraw;
 pub use self::size::Size as TerminalSize;
+pub use self::strip::StripEscapes;
+pub use self::terminology::TerminologyImages;
+
+/// Heuristics to detect terminal emulators.
+///
+/// [`TerminalCapabilities::detect`] uses these functions to pick capabilities;
+/// they only look at the environment, and never query the terminal itself.
+pub mod detection {
+    pub use super::iterm2::is_iterm2;
+    pub use super::kitty::is_kitty;
+    pub use super::terminology::is_terminology;
+
+    /// Get the version of the underlying VTE terminal if any.
+    ///
+    /// Parse `$VTE_VERSION`, e.g. `5202` for VTE 0.52.2, into a pair of major
+    /// and minor version.
+    pub fn vte_version() -> Option<(u8, u8)> {
+        std::env::var("VTE_VERSION").ok().and_then(|value| {
+            value
+                .get(..2)?
+                .parse::<u8>()
+                .into_iter()
+                .zip(value.get(2..4)?.parse::<u8>())
+                .next()
+        })
+    }
+}
+
+use crate::Style;
+use std::io::{Result, Write};
 
 /// The capability of basic styling.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StyleCapability {
     /// The terminal supports no styles.
     None,
@@ -37,41 +80,91 @@ pub enum StyleCapability {
     Ansi(AnsiStyle),
 }
 
+impl StyleCapability {
+    /// The tier of styles the terminal supports.
+    pub fn tier(&self) -> StyleTier {
+        match self {
+            StyleCapability::None => StyleTier::Dumb,
+            StyleCapability::Ansi(ansi) => ansi.tier,
+        }
+    }
+
+    /// Write `text` with `style` to the given writer.
+    ///
+    /// Degrade `style` to what the terminal supports, and write `text` as is
+    /// if the terminal supports no styles at all.
+    pub fn write_styled<W: Write>(&self, writer: &mut W, style: &Style, text: &str) -> Result<()> {
+        match self {
+            StyleCapability::None => write!(writer, "{}", text),
+            StyleCapability::Ansi(ansi) => ansi.write_styled(writer, style, text),
+        }
+    }
+}
+
 /// How the terminal supports inline links.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LinkCapability {
     /// The terminal does not support inline links.
     None,
     /// The terminal supports [OSC 8] inline links.
     ///
     /// [OSC 8]: https://git.io/vd4ee
-    OSC8(self::osc::OSC8Links),
+    OSC8(OSC8Links),
 }
 
 /// The capability of the terminal to set marks.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MarkCapability {
     /// The terminal can't set marks.
     None,
     /// The terminal supports iTerm2 jump marks.
-    ITerm2(self::iterm2::ITerm2Marks),
+    ITerm2(ITerm2Marks),
+    /// The terminal supports OSC 133 marks.
+    OSC133(OSC133Marks),
 }
 
 /// The capability of the terminal to write images inline.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ImageCapability {
     /// The terminal can't write images inline.
     None,
     /// The terminal understands the terminology way of inline images.
-    Terminology(self::terminology::TerminologyImages),
+    Terminology(TerminologyImages),
     /// The terminal understands the iterm2 way of inline images.
-    ITerm2(self::iterm2::ITerm2Images),
+    ITerm2(ITerm2Images),
     /// The terminal understands the Kitty way of inline images.
-    Kitty(self::kitty::KittyImages),
+    Kitty(KittyImages),
+}
+
+/// The size of an image in pixels, as requested by the document.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ImageSize {
+    /// The requested width.
+    pub width: Option<u32>,
+    /// The requested height.
+    pub height: Option<u32>,
+}
+
+impl ImageSize {
+    /// Scale an image of the given `width` and `height` to this size.
+    ///
+    /// If only one of width or height is requested, scale the other one
+    /// proportionally.  Return `None` if neither is requested.
+    pub fn scale(self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let proportional = |value: u32, to: u32, from: u32| {
+            (u64::from(value) * u64::from(to) / u64::from(from.max(1))).max(1) as u32
+        };
+        match (self.width, self.height) {
+            (None, None) => None,
+            (Some(w), Some(h)) => Some((w, h)),
+            (Some(w), None) => Some((w, proportional(height, w, width))),
+            (None, Some(h)) => Some((proportional(width, h, height), h)),
+        }
+    }
 }
 
 /// The capabilities of a terminal.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TerminalCapabilities {
     /// How do we call this terminal?
     pub name: String,
@@ -85,17 +178,6 @@ pub struct TerminalCapabilities {
     pub marks: MarkCapability,
 }
 
-/// Get the version of the underlying VTE terminal if any.
-fn get_vte_version() -> Option<(u8, u8)> {
-    std::env::var("VTE_VERSION").ok().and_then(|value| {
-        value[..2]
-            .parse::<u8>()
-            .into_iter()
-            .zip(value[2..4].parse::<u8>())
-            .next()
-    })
-}
-
 impl TerminalCapabilities {
     /// A terminal which supports nothing.
     pub fn none() -> TerminalCapabilities {
@@ -112,7 +194,7 @@ impl TerminalCapabilities {
     pub fn ansi() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "Ansi".to_string(),
-            style: StyleCapability::Ansi(AnsiStyle),
+            style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::Ansi16)),
             links: LinkCapability::None,
             image: ImageCapability::None,
             marks: MarkCapability::None,
@@ -120,41 +202,245 @@ impl TerminalCapabilities {
     }
 
     /// Detect the capabilities of the current terminal.
+    ///
+    /// Check for iTerm2, Terminology, Kitty and VTE 0.50 or newer in this
+    /// order, per the heuristics in [`detection`], and fall back to basic
+    /// ANSI formatting.  Detect the style tier per [`StyleTier::detect`] where
+    /// the terminal doesn't imply a tier.
+    ///
+    /// Then apply overrides from the environment per [`with_env_overrides`].
+    /// Use the `with_*` methods to override single capabilities if detection
+    /// gets them wrong.
+    ///
+    /// [`with_env_overrides`]: TerminalCapabilities::with_env_overrides
     pub fn detect() -> TerminalCapabilities {
-        if self::iterm2::is_iterm2() {
+        TerminalCapabilities::detect_and_explain().0
+    }
+
+    /// Detect the capabilities of the current terminal, and explain why.
+    ///
+    /// Return the capabilities per [`TerminalCapabilities::detect`], and the
+    /// reasons for each decision, in order of detection.
+    pub fn detect_and_explain() -> (TerminalCapabilities, Vec<String>) {
+        let mut reasons = Vec::new();
+        let mut capabilities = TerminalCapabilities::detect_terminal(&mut reasons);
+        capabilities.apply_env_overrides(&mut reasons);
+        (capabilities, reasons)
+    }
+
+    /// Detect the capabilities for output to a writer.
+    ///
+    /// If the writer is a TTY detect the capabilities of the current terminal
+    /// per [`TerminalCapabilities::detect`].  Otherwise, e.g. if output goes
+    /// to a pipe or a file, assume no capabilities at all, to write plain text
+    /// without any escape sequences.  Apply overrides from the environment in
+    /// either case, per [`with_env_overrides`].
+    ///
+    /// [`with_env_overrides`]: TerminalCapabilities::with_env_overrides
+    pub fn detect_for(writer_is_tty: bool) -> TerminalCapabilities {
+        TerminalCapabilities::detect_for_and_explain(writer_is_tty).0
+    }
+
+    /// Detect the capabilities for output to a writer, and explain why.
+    ///
+    /// See [`TerminalCapabilities::detect_for`].
+    pub fn detect_for_and_explain(writer_is_tty: bool) -> (TerminalCapabilities, Vec<String>) {
+        if writer_is_tty {
+            TerminalCapabilities::detect_and_explain()
+        } else {
+            let mut reasons = vec!["output is not a TTY".to_string()];
+            let mut capabilities = TerminalCapabilities::none();
+            capabilities.apply_env_overrides(&mut reasons);
+            (capabilities, reasons)
+        }
+    }
+
+    /// Detect the capabilities of the current terminal from its type.
+    ///
+    /// Explain the decisions in `reasons`.
+    fn detect_terminal(reasons: &mut Vec<String>) -> TerminalCapabilities {
+        fn detect_tier(reasons: &mut Vec<String>) -> StyleTier {
+            let (tier, reason) = StyleTier::detect_and_explain();
+            reasons.push(reason);
+            tier
+        }
+        if detection::is_iterm2() {
+            reasons.push("$TERM_PROGRAM contains iTerm.app".to_string());
             TerminalCapabilities {
                 name: "iTerm2".to_string(),
-                style: StyleCapability::Ansi(AnsiStyle),
+                style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::TrueColor)),
                 links: LinkCapability::OSC8(self::osc::OSC8Links::for_localhost()),
                 image: ImageCapability::ITerm2(self::iterm2::ITerm2Images),
                 marks: MarkCapability::ITerm2(self::iterm2::ITerm2Marks),
             }
-        } else if self::terminology::is_terminology() {
+        } else if detection::is_terminology() {
+            reasons.push("$TERMINOLOGY is 1".to_string());
             TerminalCapabilities {
                 name: "Terminology".to_string(),
-                style: StyleCapability::Ansi(AnsiStyle),
+                style: StyleCapability::Ansi(AnsiStyle::new(detect_tier(reasons))),
                 links: LinkCapability::OSC8(self::osc::OSC8Links::for_localhost()),
                 image: ImageCapability::Terminology(self::terminology::TerminologyImages),
                 marks: MarkCapability::None,
             }
-        } else if self::kitty::is_kitty() {
+        } else if detection::is_kitty() {
+            reasons.push("$TERM is xterm-kitty".to_string());
             TerminalCapabilities {
                 name: "Kitty".to_string(),
-                style: StyleCapability::Ansi(AnsiStyle),
+                style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::Full)),
                 links: LinkCapability::None,
                 image: ImageCapability::Kitty(self::kitty::KittyImages),
                 marks: MarkCapability::None,
             }
-        } else if get_vte_version().filter(|&v| v >= (50, 0)).is_some() {
+        } else if let Some((major, minor)) = detection::vte_version().filter(|&v| v >= (50, 0)) {
+            reasons.push(format!("$VTE_VERSION is 0.{}.{}", major, minor));
             TerminalCapabilities {
                 name: "VTE 50".to_string(),
-                style: StyleCapability::Ansi(AnsiStyle),
+                style: StyleCapability::Ansi(AnsiStyle::new(StyleTier::TrueColor)),
                 links: LinkCapability::OSC8(self::osc::OSC8Links::for_localhost()),
                 image: ImageCapability::None,
                 marks: MarkCapability::None,
             }
         } else {
-            TerminalCapabilities::ansi()
+            reasons.push("no known terminal emulator found".to_string());
+            TerminalCapabilities {
+                style: StyleCapability::Ansi(AnsiStyle::new(detect_tier(reasons))),
+                ..TerminalCapabilities::ansi()
+            }
+        }
+    }
+
+    /// Override capabilities from the environment.
+    ///
+    /// Read the following variables, and ignore unknown values:
+    ///
+    /// * `$MDCAT_COLOUR`: `off`, `16`, `256`, `truecolor` or `full`
+    /// * `$MDCAT_LINKS`: `off` or `osc8`
+    /// * `$MDCAT_IMAGES`: `off`, `terminology`, `iterm2` or `kitty`
+    /// * `$MDCAT_MARKS`: `off`, `iterm2` or `osc133`
+    pub fn with_env_overrides(mut self) -> TerminalCapabilities {
+        self.apply_env_overrides(&mut Vec::new());
+        self
+    }
+
+    /// Apply overrides from the environment, and explain them in `reasons`.
+    fn apply_env_overrides(&mut self, reasons: &mut Vec<String>) {
+        for name in &["MDCAT_COLOUR", "MDCAT_LINKS", "MDCAT_IMAGES", "MDCAT_MARKS"] {
+            if let Ok(value) = std::env::var(name) {
+                if self.apply_override(name, &value) {
+                    reasons.push(format!("${} is {}", name, value));
+                } else {
+                    reasons.push(format!("ignored unknown value {} of ${}", value, name));
+                }
+            }
+        }
+    }
+
+    /// Override a single capability per the environment variable `name` and
+    /// its `value`.
+    ///
+    /// Return `false` if `value` is unknown.
+    fn apply_override(&mut self, name: &str, value: &str) -> bool {
+        let ansi = |tier| StyleCapability::Ansi(AnsiStyle::new(tier));
+        match (name, value.trim().to_lowercase().as_str()) {
+            ("MDCAT_COLOUR", "off") => self.style = StyleCapability::None,
+            ("MDCAT_COLOUR", "16") => self.style = ansi(StyleTier::Ansi16),
+            ("MDCAT_COLOUR", "256") => self.style = ansi(StyleTier::Ansi256),
+            ("MDCAT_COLOUR", "truecolor") | ("MDCAT_COLOUR", "24bit") => {
+                self.style = ansi(StyleTier::TrueColor)
+            }
+            ("MDCAT_COLOUR", "full") => self.style = ansi(StyleTier::Full),
+            ("MDCAT_LINKS", "off") => self.links = LinkCapability::None,
+            ("MDCAT_LINKS", "osc8") => {
+                self.links = LinkCapability::OSC8(OSC8Links::for_localhost())
+            }
+            ("MDCAT_IMAGES", "off") => self.image = ImageCapability::None,
+            ("MDCAT_IMAGES", "terminology") => {
+                self.image = ImageCapability::Terminology(TerminologyImages)
+            }
+            ("MDCAT_IMAGES", "iterm2") => self.image = ImageCapability::ITerm2(ITerm2Images),
+            ("MDCAT_IMAGES", "kitty") => self.image = ImageCapability::Kitty(KittyImages),
+            ("MDCAT_MARKS", "off") => self.marks = MarkCapability::None,
+            ("MDCAT_MARKS", "iterm2") => self.marks = MarkCapability::ITerm2(ITerm2Marks),
+            ("MDCAT_MARKS", "osc133") => self.marks = MarkCapability::OSC133(OSC133Marks),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Override how the terminal supports basic styling.
+    pub fn with_style(self, style: StyleCapability) -> TerminalCapabilities {
+        TerminalCapabilities { style, ..self }
+    }
+
+    /// Override how the terminal supports links.
+    pub fn with_links(self, links: LinkCapability) -> TerminalCapabilities {
+        TerminalCapabilities { links, ..self }
+    }
+
+    /// Override how the terminal supports images.
+    pub fn with_image(self, image: ImageCapability) -> TerminalCapabilities {
+        TerminalCapabilities { image, ..self }
+    }
+
+    /// Override how the terminal supports marks.
+    pub fn with_marks(self, marks: MarkCapability) -> TerminalCapabilities {
+        TerminalCapabilities { marks, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn scale_image_size() {
+        let size = |width, height| ImageSize { width, height };
+        assert_eq!(size(None, None).scale(400, 200), None);
+        assert_eq!(size(Some(100), Some(100)).scale(400, 200), Some((100, 100)));
+        assert_eq!(size(Some(100), None).scale(400, 200), Some((100, 50)));
+        assert_eq!(size(None, Some(100)).scale(400, 200), Some((200, 100)));
+    }
+
+    #[test]
+    fn override_single_capabilities() {
+        let capabilities = TerminalCapabilities::ansi()
+            .with_image(ImageCapability::ITerm2(ITerm2Images))
+            .with_style(StyleCapability::None);
+        assert_eq!(capabilities.name, "Ansi");
+        assert_eq!(capabilities.style.tier(), StyleTier::Dumb);
+        assert!(matches!(capabilities.image, ImageCapability::ITerm2(_)));
+        assert!(matches!(capabilities.links, LinkCapability::None));
+    }
+
+    #[test]
+    fn no_capabilities_without_tty() {
+        let (capabilities, reasons) = TerminalCapabilities::detect_for_and_explain(false);
+        assert_eq!(reasons[0], "output is not a TTY");
+        if std::env::var_os("MDCAT_COLOUR").is_none() {
+            assert_eq!(capabilities.style.tier(), StyleTier::Dumb);
         }
+        if std::env::var_os("MDCAT_LINKS").is_none() {
+            assert!(matches!(capabilities.links, LinkCapability::None));
+        }
+    }
+
+    #[test]
+    fn override_capabilities_from_environment() {
+        let mut capabilities = TerminalCapabilities::ansi();
+        assert!(capabilities.apply_override("MDCAT_COLOUR", "256"));
+        assert!(capabilities.apply_override("MDCAT_IMAGES", "Kitty"));
+        assert!(capabilities.apply_override("MDCAT_MARKS", "iterm2"));
+        assert_eq!(capabilities.style.tier(), StyleTier::Ansi256);
+        assert!(matches!(capabilities.image, ImageCapability::Kitty(_)));
+        assert!(matches!(capabilities.marks, MarkCapability::ITerm2(_)));
+
+        assert!(capabilities.apply_override("MDCAT_COLOUR", "off"));
+        assert!(!capabilities.apply_override("MDCAT_IMAGES", "sixel"));
+        assert_eq!(capabilities.style.tier(), StyleTier::Dumb);
+        assert!(matches!(capabilities.image, ImageCapability::Kitty(_)));
+
+        assert!(capabilities.apply_override("MDCAT_MARKS", "osc133"));
+        assert!(matches!(capabilities.marks, MarkCapability::OSC133(_)));
     }
 }
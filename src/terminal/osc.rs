@@ -26,7 +26,28 @@ pub fn write_osc<W: Write>(writer: &mut W, command: &str) -> Result<()> {
     Ok(())
 }
 
-#[derive(Debug)]
+/// Copy `text` to the clipboard of this terminal with OSC 52.
+///
+/// Not all terminals support OSC 52, and some ask for permission first.
+pub fn set_clipboard<W: Write>(writer: &mut W, text: &str) -> Result<()> {
+    write_osc(writer, &format!("52;c;{}", base64::encode(text.as_bytes())))
+}
+
+/// OSC 133 marks, aka semantic prompts.
+///
+/// Terminals like kitty, WezTerm or foot let users jump between these marks.
+#[derive(Debug, Clone)]
+pub struct OSC133Marks;
+
+impl OSC133Marks {
+    /// Write a mark which starts a new part of the output to `writer`.
+    pub fn set_mark<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_osc(writer, "133;A")
+    }
+}
+
+/// Access to OSC 8 inline links.
+#[derive(Debug, Clone)]
 pub struct OSC8Links {
     hostname: String,
 }
@@ -88,6 +109,33 @@ impl OSC8Links {
         self.set_link(writer, destination.as_str())
     }
 
+    /// Set a link to the given `destination` URL with a `title`.
+    ///
+    /// Like [`OSC8Links::set_link_url`], but add `title` as `title` parameter,
+    /// which some terminals show as tooltip.  Replace characters which may not
+    /// appear in OSC 8 parameters with spaces.
+    pub fn set_link_url_with_title<W: Write>(
+        &self,
+        writer: &mut W,
+        mut destination: Url,
+        title: &str,
+    ) -> Result<()> {
+        if url_needs_explicit_host(&destination) {
+            destination.set_host(Some(&self.hostname)).unwrap();
+        }
+        let title: String = title
+            .chars()
+            .map(|c| {
+                if c.is_control() || c == ':' || c == ';' {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect();
+        write_osc(writer, &format!("8;title={};{}", title, destination))
+    }
+
     /// Clear the current link if any.
     pub fn clear_link<W: Write>(&self, writer: &mut W) -> Result<()> {
         self.set_link(writer, "")
@@ -102,6 +150,28 @@ impl OSC8Links {
 mod tests {
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn set_clipboard() {
+        let mut buffer = Vec::new();
+        super::set_clipboard(&mut buffer, "foo").unwrap();
+        assert_eq!(buffer, b"\x1b]52;c;Zm9v\x07");
+    }
+
+    #[test]
+    fn set_link_url_with_title() {
+        let osc8 = super::OSC8Links {
+            hostname: "example".to_string(),
+        };
+        let mut buffer = Vec::new();
+        let url = url::Url::parse("http://example.com/foo").unwrap();
+        osc8.set_link_url_with_title(&mut buffer, url, "a;b:c\n")
+            .unwrap();
+        assert_eq!(
+            buffer,
+            b"\x1b]8;title=a b c ;http://example.com/foo\x07".to_vec()
+        );
+    }
+
     #[test]
     fn url_needs_explicit_host() {
         let checks = [
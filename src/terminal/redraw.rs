@@ -0,0 +1,76 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Redraw only changed lines of a screen.
+
+use std::io::{Result, Write};
+
+/// Redraw the screen from `previous` to `current` output.
+///
+/// Assume that `previous` output fills the screen from the top-left corner,
+/// and rewrite only the lines which changed, with cursor positioning escapes,
+/// to avoid flicker when re-rendering a document, e.g. for a live preview.
+/// Clear all lines below the end of `current`, and leave the cursor after
+/// the last line.
+///
+/// Compare lines byte by byte, including their escape sequences, so that
+/// lines which only changed style get redrawn as well.
+pub fn redraw<W: Write>(writer: &mut W, previous: &str, current: &str) -> Result<()> {
+    let previous_lines: Vec<&str> = previous.lines().collect();
+    let current_lines: Vec<&str> = current.lines().collect();
+    for (index, line) in current_lines.iter().enumerate() {
+        if previous_lines.get(index) != Some(line) {
+            // Move to the line, write it, and erase the rest of the old line
+            write!(writer, "\x1b[{};1H{}\x1b[0m\x1b[K", index + 1, line)?;
+        }
+    }
+    let end = current_lines.len() + 1;
+    if current_lines.len() < previous_lines.len() {
+        // Erase everything below the current output
+        write!(writer, "\x1b[{};1H\x1b[J", end)?;
+    } else {
+        write!(writer, "\x1b[{};1H", end)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn redraw_to_string(previous: &str, current: &str) -> String {
+        let mut sink = Vec::new();
+        redraw(&mut sink, previous, current).unwrap();
+        String::from_utf8(sink).unwrap()
+    }
+
+    #[test]
+    fn redraw_changed_lines_only() {
+        assert_eq!(
+            redraw_to_string("a\nb\nc\n", "a\nB\nc\nd\n"),
+            "\x1b[2;1HB\x1b[0m\x1b[K\x1b[4;1Hd\x1b[0m\x1b[K\x1b[5;1H"
+        );
+    }
+
+    #[test]
+    fn redraw_shorter_output() {
+        assert_eq!(redraw_to_string("a\nb\nc\n", "a\n"), "\x1b[2;1H\x1b[J");
+    }
+
+    #[test]
+    fn redraw_unchanged() {
+        assert_eq!(redraw_to_string("a\nb\n", "a\nb\n"), "\x1b[3;1H");
+    }
+}
@@ -15,7 +15,7 @@
 //! Terminal size.
 
 /// The size of a text terminal, in characters and lines.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Size {
     /// The width of the terminal, in characters aka columns.
     pub width: usize,
@@ -58,8 +58,15 @@ impl Size {
     /// Get the terminal size from the underlying TTY, and fallback to
     /// `$COLUMNS` and `$LINES`.
     pub fn detect() -> Option<Size> {
+        Size::detect_and_explain().map(|(size, _)| size)
+    }
+
+    /// Detect the terminal size, and explain where it came from.
+    ///
+    /// See [`Size::detect`].
+    pub fn detect_and_explain() -> Option<(Size, &'static str)> {
         term_size::dimensions()
-            .map(|(w, h)| Size::new(w, h))
-            .or_else(Size::from_env)
+            .map(|(w, h)| (Size::new(w, h), "size of the TTY"))
+            .or_else(|| Size::from_env().map(|size| (size, "$COLUMNS and $LINES")))
     }
 }
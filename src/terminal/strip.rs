@@ -0,0 +1,161 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Strip escape sequences from output.
+
+use std::io::{Result, Write};
+
+/// Where we are in the output.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    /// Plain text.
+    Text,
+    /// After an ESC.
+    Escape,
+    /// Inside intermediate bytes of an escape sequence, e.g. `ESC # 8`.
+    Intermediate,
+    /// Inside a CSI sequence, e.g. an SGR sequence for styles.
+    Csi,
+    /// Inside a command string, e.g. OSC, DCS or APC, up to BEL or ST.
+    Command,
+    /// After an ESC inside a command string.
+    CommandEscape,
+    /// Inside a Terminology command, up to a NUL byte.
+    Terminology,
+}
+
+/// A writer which strips all escape sequences.
+///
+/// Removes styles, OSC 8 links, jump marks, and inline images from all output
+/// written to the underlying writer, and leaves only plain text.  Escape
+/// sequences may span multiple writes.
+#[derive(Debug)]
+pub struct StripEscapes<W: Write> {
+    inner: W,
+    state: State,
+}
+
+impl<W: Write> StripEscapes<W> {
+    /// Strip escape sequences from all output to `inner`.
+    pub fn new(inner: W) -> StripEscapes<W> {
+        StripEscapes {
+            inner,
+            state: State::Text,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Advance the state with `byte`, and return whether `byte` is text.
+    fn advance(&mut self, byte: u8) -> bool {
+        use State::*;
+        let (state, is_text) = match (self.state, byte) {
+            (Text, 0x1b) => (Escape, false),
+            (Text, _) => (Text, true),
+            (Escape, b'[') => (Csi, false),
+            (Escape, b']') | (Escape, b'P') | (Escape, b'_') | (Escape, b'^') | (Escape, b'X') => {
+                (Command, false)
+            }
+            (Escape, b'}') => (Terminology, false),
+            (Escape, 0x20..=0x2f) => (Intermediate, false),
+            (Intermediate, 0x20..=0x2f) => (Intermediate, false),
+            (Escape, _) | (Intermediate, _) => (Text, false),
+            (Csi, 0x40..=0x7e) => (Text, false),
+            (Csi, _) => (Csi, false),
+            (Command, 0x07) => (Text, false),
+            (Command, 0x1b) => (CommandEscape, false),
+            (Command, _) => (Command, false),
+            (CommandEscape, b'\\') => (Text, false),
+            (CommandEscape, _) => (Command, false),
+            (Terminology, 0x00) => (Text, false),
+            (Terminology, _) => (Terminology, false),
+        };
+        self.state = state;
+        is_text
+    }
+}
+
+impl<W: Write> Write for StripEscapes<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut start = 0;
+        for (index, byte) in buf.iter().enumerate() {
+            if !self.advance(*byte) {
+                if start < index {
+                    self.inner.write_all(&buf[start..index])?;
+                }
+                start = index + 1;
+            }
+        }
+        if start < buf.len() {
+            self.inner.write_all(&buf[start..])?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn strip(chunks: &[&str]) -> String {
+        let mut writer = StripEscapes::new(Vec::new());
+        for chunk in chunks {
+            writer.write_all(chunk.as_bytes()).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn strip_styles_and_links() {
+        assert_eq!(
+            strip(&[
+                "\x1b[1;31mfoo\x1b[0m ",
+                "\x1b]8;;https://example.com\x07bar\x1b]8;;\x07"
+            ]),
+            "foo bar"
+        );
+    }
+
+    #[test]
+    fn strip_images() {
+        assert_eq!(
+            strip(&[
+                "a\x1b_Gf=100;AAAA\x1b\\b",
+                "\x1b}ic#1;1;/foo.png\x00c\x1b#8d"
+            ]),
+            "abcd"
+        );
+    }
+
+    #[test]
+    fn strip_sequences_across_writes() {
+        assert_eq!(
+            strip(&["foo\x1b", "[3", "2mbar\x1b]1337;", "SetMark\x1b", "\\"]),
+            "foobar"
+        );
+    }
+}
@@ -28,7 +28,7 @@ pub fn is_terminology() -> bool {
 }
 
 /// Provides access to printing images for Terminology.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TerminologyImages;
 
 impl TerminologyImages {
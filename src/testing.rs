@@ -0,0 +1,69 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for snapshot tests of rendered markdown.
+//!
+//! Render documents with fixed terminal capabilities and size, without
+//! probing the environment, so that crates which embed mdcat can compare the
+//! output against stored snapshots.
+
+use crate::*;
+
+/// Settings to render with the given terminal capabilities and size.
+///
+/// Never access remote resources, and take the default for all other
+/// settings.  In particular use syntect's default syntaxes and mdcat's default
+/// theme to highlight code.
+pub fn settings(
+    terminal_capabilities: TerminalCapabilities,
+    terminal_size: TerminalSize,
+) -> Settings {
+    RenderEnv::new(terminal_capabilities, terminal_size).settings()
+}
+
+/// Render `markdown` with `settings`.
+///
+/// Parse `markdown` with all extensions mdcat supports, resolve relative
+/// references against `base_dir` and return the rendered text.
+///
+/// # Panics
+///
+/// Panic if rendering fails.
+pub fn render(settings: &Settings, base_dir: &Path, markdown: &str) -> String {
+    let parser = parse(markdown, markdown_options(), settings);
+    render_events_to_string(settings, base_dir, parser).expect("Rendering failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn render_plain() {
+        let settings = settings(TerminalCapabilities::none(), TerminalSize::default());
+        let text = render(&settings, Path::new("/"), "# Foo\n\n*bar* `baz`\n");
+        assert_eq!(text, "\u{2504}Foo\n\nbar baz\n");
+    }
+
+    #[test]
+    fn render_ansi_is_stable() {
+        let settings = settings(TerminalCapabilities::ansi(), TerminalSize::default());
+        let markdown = "Some *text*\n\n```rust\nfn main() {}\n```\n";
+        let first = render(&settings, Path::new("/"), markdown);
+        let second = render(&settings, Path::new("/"), markdown);
+        assert!(first.contains('\x1b'), "{:?}", first);
+        assert_eq!(first, second);
+    }
+}
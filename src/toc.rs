@@ -0,0 +1,163 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tables of contents.
+//!
+//! Replace `<!-- toc -->` directives, as used by many Markdown tools, with a
+//! table of contents of all headings in the document.
+
+use crate::slug::{self, Heading};
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+
+/// Whether `html` is a table of contents directive, i.e. `<!-- toc -->`.
+fn is_toc_directive(html: &str) -> bool {
+    let html = html.trim();
+    7 <= html.len()
+        && html.starts_with("<!--")
+        && html.ends_with("-->")
+        && html[4..html.len() - 3].trim().eq_ignore_ascii_case("toc")
+}
+
+/// Create events for a table of contents of `headings`.
+///
+/// Nest headings in lists according to their level, and link every heading
+/// to its slug.
+fn toc_events(headings: Vec<Heading>) -> Vec<Event<'static>> {
+    let base_level = headings
+        .iter()
+        .map(|heading| heading.level)
+        .min()
+        .unwrap_or(1);
+    let mut events = Vec::new();
+    // Whether the list at each depth has an open item
+    let mut lists: Vec<bool> = Vec::new();
+    for Heading { level, text, slug } in headings {
+        let depth = (level - base_level + 1) as usize;
+        while depth < lists.len() {
+            if lists.pop() == Some(true) {
+                events.push(Event::End(Tag::Item));
+            }
+            events.push(Event::End(Tag::List(None)));
+        }
+        while lists.len() < depth {
+            if let Some(item_open) = lists.last_mut() {
+                if !*item_open {
+                    events.push(Event::Start(Tag::Item));
+                    *item_open = true;
+                }
+            }
+            events.push(Event::Start(Tag::List(None)));
+            lists.push(false);
+        }
+        if let Some(item_open) = lists.last_mut() {
+            if *item_open {
+                events.push(Event::End(Tag::Item));
+            }
+            let link = Tag::Link(LinkType::Inline, format!("#{}", slug).into(), "".into());
+            events.push(Event::Start(Tag::Item));
+            events.push(Event::Start(link.clone()));
+            events.push(Event::Text(CowStr::from(text)));
+            events.push(Event::End(link));
+            *item_open = true;
+        }
+    }
+    while let Some(item_open) = lists.pop() {
+        if item_open {
+            events.push(Event::End(Tag::Item));
+        }
+        events.push(Event::End(Tag::List(None)));
+    }
+    events
+}
+
+/// Replace all `<!-- toc -->` directives in `events` with a table of contents.
+///
+/// Every event comes with a `T`, e.g. its offset in the source; the events of
+/// a table of contents take the `T` of their directive.
+pub fn insert_toc<T: Clone>(events: Vec<(Event<'_>, T)>) -> Vec<(Event<'_>, T)> {
+    let has_directive = events.iter().any(|(event, _)| match event {
+        Event::Html(html) => is_toc_directive(html),
+        _ => false,
+    });
+    if !has_directive {
+        return events;
+    }
+    let toc = toc_events(slug::headings(events.iter().map(|(event, _)| event)));
+    let mut result = Vec::with_capacity(events.len() + toc.len());
+    for (event, t) in events {
+        match event {
+            Event::Html(ref html) if is_toc_directive(html) => {
+                result.extend(toc.iter().map(|event| (event.clone(), t.clone())))
+            }
+            event => result.push((event, t)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    #[test]
+    fn toc_directives() {
+        assert!(is_toc_directive("<!-- toc -->\n"));
+        assert!(is_toc_directive("<!--TOC-->"));
+        assert!(!is_toc_directive("<!-- foo -->"));
+        assert!(!is_toc_directive("<p>toc</p>"));
+        assert!(!is_toc_directive("<!--->"));
+    }
+
+    #[test]
+    fn insert_nested_toc() {
+        let markdown = "<!-- toc -->\n\n## A\n\n#### B `c`\n\n## D\n";
+        let events = insert_toc(Parser::new(markdown).map(|event| (event, ())).collect());
+        let link = |slug: &'static str| Tag::Link(LinkType::Inline, slug.into(), "".into());
+        let toc: Vec<Event> = events
+            .into_iter()
+            .map(|(event, _)| event)
+            .take_while(|event| *event != Event::Start(Tag::Heading(2)))
+            .collect();
+        assert_eq!(
+            toc,
+            vec![
+                Event::Start(Tag::List(None)),
+                Event::Start(Tag::Item),
+                Event::Start(link("#a")),
+                Event::Text("A".into()),
+                Event::End(link("#a")),
+                Event::Start(Tag::List(None)),
+                Event::Start(Tag::Item),
+                Event::Start(Tag::List(None)),
+                Event::Start(Tag::Item),
+                Event::Start(link("#b-c")),
+                Event::Text("B c".into()),
+                Event::End(link("#b-c")),
+                Event::End(Tag::Item),
+                Event::End(Tag::List(None)),
+                Event::End(Tag::Item),
+                Event::End(Tag::List(None)),
+                Event::End(Tag::Item),
+                Event::Start(Tag::Item),
+                Event::Start(link("#d")),
+                Event::Text("D".into()),
+                Event::End(link("#d")),
+                Event::End(Tag::Item),
+                Event::End(Tag::List(None)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2018-2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+// 	http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Warnings about problems which do not stop rendering.
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use url::Url;
+
+/// A problem which did not stop rendering.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// mdcat could not show the image at `url`.
+    ImageFailed {
+        /// The URL of the image.
+        url: Url,
+        /// The error, followed by all its causes.
+        causes: Vec<String>,
+    },
+    /// mdcat did not load the remote image at the given URL, because settings
+    /// permit only local resources.
+    RemoteImageSkipped(Url),
+    /// mdcat did not load the local image at `url`, because settings restrict
+    /// local images to the base directory.
+    ImageOutsideBaseDir {
+        /// The URL of the image.
+        url: Url,
+        /// The base directory of the document.
+        base_dir: PathBuf,
+    },
+}
+
+impl Warning {
+    /// A warning about the image at `url` which failed with `error`.
+    pub(crate) fn image_failed(url: &Url, error: &dyn Error) -> Warning {
+        let mut causes = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Warning::ImageFailed {
+            url: url.clone(),
+            causes,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Warning::ImageFailed { url, causes } => {
+                write!(f, "Cannot show image {}", url)?;
+                for cause in causes {
+                    write!(f, ": {}", cause)?;
+                }
+                Ok(())
+            }
+            Warning::RemoteImageSkipped(url) => {
+                write!(f, "Not loading remote image {} in local mode", url)
+            }
+            Warning::ImageOutsideBaseDir { url, base_dir } => write!(
+                f,
+                "Not loading image {} outside of {}",
+                url,
+                base_dir.display()
+            ),
+        }
+    }
+}
+
+/// A function to call with every warning, e.g. to print warnings.
+///
+/// Clones share the function.
+#[derive(Clone)]
+pub struct WarningHandler(Arc<dyn Fn(&Warning) + Send + Sync>);
+
+impl WarningHandler {
+    /// Create a handler which calls `handle` with every warning.
+    pub fn new<F>(handle: F) -> WarningHandler
+    where
+        F: Fn(&Warning) + Send + Sync + 'static,
+    {
+        WarningHandler(Arc::new(handle))
+    }
+
+    /// Handle the given `warning`.
+    pub(crate) fn warn(&self, warning: &Warning) {
+        (self.0)(warning)
+    }
+}
+
+impl Debug for WarningHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str("WarningHandler")
+    }
+}
@@ -18,7 +18,10 @@
 
 mod cli {
     use std::ffi::OsStr;
-    use std::process::{Command, Output};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::net::TcpListener;
+    use std::process::{self, Command, Output};
 
     fn run_cargo_mdcat<I, S>(args: I) -> Output
     where
@@ -47,6 +50,19 @@ mod cli {
         assert!(stdout.contains("mdcat uses the standardized CommonMark dialect"));
     }
 
+    #[test]
+    fn detect_terminal_as_json() {
+        let output = run_cargo_mdcat(&["--no-colour", "--detect=json"]);
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success(), "{:?}", output.status);
+        assert!(
+            stdout.starts_with("{\"terminal\":\"dumb\",\"styles\":\"off\""),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(stdout.contains("\"reasons\":[\"--no-colour disables all styles\"]"));
+    }
+
     #[test]
     fn file_list_fail_late() {
         let output = run_cargo_mdcat(&["does-not-exist", "sample/common-mark.md"]);
@@ -75,4 +91,74 @@ mod cli {
         );
         assert!(output.stdout.is_empty());
     }
+
+    #[test]
+    fn check_only_local_links_with_local() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let file = std::env::temp_dir().join(format!("mdcat-check-local-{}.md", process::id()));
+        fs::write(&file, format!("[remote]({})\n", url)).unwrap();
+        let output = run_cargo_mdcat([
+            OsStr::new("--check-links"),
+            OsStr::new("--local"),
+            file.as_os_str(),
+        ]);
+        fs::remove_file(&file).unwrap();
+        assert!(output.status.success(), "{:?}", output.status);
+        assert!(output.stdout.is_empty());
+        // mdcat never connected to the remote link
+        let error = listener.accept().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn ignore_theme_from_environment_when_deterministic() {
+        let output = Command::new("cargo")
+            .args([
+                "run",
+                "-q",
+                "--",
+                "--deterministic",
+                "sample/common-mark.md",
+            ])
+            .env("MDCAT_THEME", "no such theme")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let output = Command::new("cargo")
+            .args(["run", "-q", "--", "sample/common-mark.md"])
+            .env("MDCAT_THEME", "no such theme")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn show_section() {
+        let output = run_cargo_mdcat(["--no-colour", "sample/showcase.md#iterm2-specials"]);
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success(), "{:?}", output.status);
+        assert!(
+            stdout.starts_with("\u{2504}\u{2504}iTerm2 specials"),
+            "{}",
+            stdout
+        );
+        assert!(!stdout.contains("Markup"), "{}", stdout);
+    }
+
+    #[test]
+    fn limit_inline_and_read_image_bytes_separately() {
+        let output = run_cargo_mdcat([
+            "--no-colour",
+            "--max-image-bytes",
+            "1000",
+            "--max-image-read-bytes",
+            "2000",
+            "sample/common-mark.md",
+        ]);
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert!(stdout.contains("CommonMark sample document"));
+    }
 }
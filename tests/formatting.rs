@@ -20,7 +20,6 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use syntect::parsing::SyntaxSet;
 
 fn format_ansi_to_html(markdown: &str) -> String {
     let child = Command::new("ansi2html")
@@ -41,9 +40,7 @@ fn format_ansi_to_html(markdown: &str) -> String {
         mdcat::push_tty(
             &mdcat::Settings {
                 terminal_capabilities: mdcat::TerminalCapabilities::ansi(),
-                terminal_size: mdcat::TerminalSize::default(),
-                resource_access: mdcat::ResourceAccess::LocalOnly,
-                syntax_set: SyntaxSet::load_defaults_newlines(),
+                ..mdcat::Settings::default()
             },
             &mut child.stdin.unwrap(),
             &std::env::current_dir().expect("No working directory"),